@@ -0,0 +1,344 @@
+//! Works out how often each bin is collected from the dates the council site lists, so
+//! collections can be projected past the handful of dates it shows. Projected dates carry how
+//! sure we are of the cadence, and never replace a date from the site.
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use crate::{Bin, BinDates, NextBinCollection, NextBinCollectionDay};
+
+/// How far ahead collections are projected
+pub const PROJECTION_WEEKS: i64 = 12;
+
+/// A collection moved for a bank holiday is put back by at most this many days
+const MAX_DAYS_MOVED: i64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cadence {
+    Weekly,
+    Fortnightly,
+    FourWeekly,
+}
+
+impl Cadence {
+    const ALL: [Cadence; 3] = [Cadence::Weekly, Cadence::Fortnightly, Cadence::FourWeekly];
+
+    pub fn weeks(&self) -> i64 {
+        return match self {
+            Cadence::Weekly => 1,
+            Cadence::Fortnightly => 2,
+            Cadence::FourWeekly => 4,
+        };
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Cadence::Weekly => "weekly",
+            Cadence::Fortnightly => "fortnightly",
+            Cadence::FourWeekly => "four-weekly",
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Confidence {
+    /// Only two dates, or ones that don't all fit
+    Low,
+    /// Three dates that fit
+    Medium,
+    /// Four or more dates that fit
+    High,
+}
+
+impl Confidence {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredCadence {
+    pub bin: Bin,
+    pub cadence: Cadence,
+    /// A collection on the bin's usual day, that the cadence counts from
+    pub anchor: NaiveDate,
+    pub confidence: Confidence,
+}
+
+/// `None` without at least two dates, or when they don't follow any cadence
+pub fn infer_cadence(bin_dates: &BinDates) -> Option<InferredCadence> {
+    let mut dates = bin_dates.dates.clone();
+    dates.sort();
+    dates.dedup();
+    let latest = *dates.last()?;
+
+    // The usual day is the most common one, as bank holidays move the odd collection
+    let weekdays: Vec<Weekday> = dates.iter().map(|date| date.weekday()).collect();
+    let usual_day = weekdays.iter().copied().max_by_key(|day| {
+        (
+            weekdays.iter().filter(|d| *d == day).count(),
+            *day == latest.weekday(),
+        )
+    })?;
+    let anchor = *dates
+        .iter()
+        .rev()
+        .find(|date| date.weekday() == usual_day)?;
+
+    // Each date moved back to the usual day, so the gaps between them are whole weeks
+    let mut weeks_apart = Vec::new();
+    let mut previous: Option<NaiveDate> = None;
+    // Brought forward, or put back further than a bank holiday would, so left out of the gaps
+    let mut unmatched = 0;
+    for date in &dates {
+        let Some(usual) = on_usual_day(*date, usual_day) else {
+            unmatched += 1;
+            continue;
+        };
+        if let Some(previous) = previous {
+            weeks_apart.push((usual - previous).num_days() / 7);
+        }
+        previous = Some(usual);
+    }
+    if weeks_apart.is_empty() {
+        return None;
+    }
+
+    // The cadence most gaps are exactly, preferring the more frequent on a tie
+    let cadence = Cadence::ALL.iter().copied().max_by_key(|cadence| {
+        let exact = weeks_apart
+            .iter()
+            .filter(|w| **w == cadence.weeks())
+            .count();
+        return (exact, -cadence.weeks());
+    })?;
+    if !weeks_apart.contains(&cadence.weeks()) {
+        return None;
+    }
+    // A gap of a few cycles is still on the cadence, i.e a collection skipped over Christmas
+    let fitting = weeks_apart
+        .iter()
+        .filter(|w| **w > 0 && **w % cadence.weeks() == 0)
+        .count();
+    let considered = weeks_apart.len() + unmatched;
+    let confidence = if fitting < considered {
+        if fitting * 2 <= considered {
+            return None;
+        }
+        Confidence::Low
+    } else {
+        match weeks_apart.len() {
+            1 => Confidence::Low,
+            2 => Confidence::Medium,
+            _ => Confidence::High,
+        }
+    };
+
+    return Some(InferredCadence {
+        bin: bin_dates.bin,
+        cadence,
+        anchor,
+        confidence,
+    });
+}
+
+/// The date on `usual_day` that `date` was put back from, if it's near enough to be the same
+/// collection
+fn on_usual_day(date: NaiveDate, usual_day: Weekday) -> Option<NaiveDate> {
+    return (0..=MAX_DAYS_MOVED)
+        .map(|days| date - Duration::days(days))
+        .find(|moved| moved.weekday() == usual_day);
+}
+
+/// Collections after the last date the site listed, from `from` up to `until`
+pub fn project_dates(
+    bin_dates: &BinDates,
+    cadence: &InferredCadence,
+    from: NaiveDate,
+    until: NaiveDate,
+) -> Vec<NaiveDate> {
+    let Some(last_listed) = bin_dates.dates.iter().max() else {
+        return Vec::new();
+    };
+    let step = Duration::weeks(cadence.cadence.weeks());
+    let mut projected = Vec::new();
+    let mut date = cadence.anchor + step;
+    while date <= until {
+        if date > *last_listed && date >= from {
+            projected.push(date);
+        }
+        date += step;
+    }
+    return projected;
+}
+
+/// Every collection from `from` up to `until` in date order, listed ones and projected ones.
+/// Projections less certain than `min_confidence` are left out
+pub fn upcoming_collections(
+    bins: &[BinDates],
+    from: NaiveDate,
+    until: NaiveDate,
+    min_confidence: Confidence,
+) -> Vec<NextBinCollectionDay> {
+    let mut upcoming = Vec::new();
+    for bin_dates in bins {
+        for date in &bin_dates.dates {
+            if *date >= from && *date <= until {
+                upcoming.push(NextBinCollectionDay {
+                    bin: bin_dates.bin,
+                    date: *date,
+                    projected: None,
+                });
+            }
+        }
+        let Some(cadence) = infer_cadence(bin_dates) else {
+            continue;
+        };
+        if cadence.confidence < min_confidence {
+            continue;
+        }
+        for date in project_dates(bin_dates, &cadence, from, until) {
+            upcoming.push(NextBinCollectionDay {
+                bin: bin_dates.bin,
+                date,
+                projected: Some(cadence.confidence),
+            });
+        }
+    }
+    upcoming.sort_by_key(|day| day.date);
+    return upcoming;
+}
+
+/// Like `next_collection_on_or_after`, but once the listed dates run out the next projected
+/// collection is used instead
+pub fn next_collection_with_projections(
+    bins: &[BinDates],
+    from_date: NaiveDate,
+    min_confidence: Confidence,
+) -> Option<NextBinCollection> {
+    let until = from_date + Duration::weeks(PROJECTION_WEEKS);
+    let upcoming = upcoming_collections(bins, from_date, until, min_confidence);
+    let next_date = upcoming.first()?.date;
+    return Some(NextBinCollection {
+        bins: upcoming
+            .into_iter()
+            .filter(|day| day.date == next_date)
+            .collect(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        return NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+    }
+
+    fn bin_dates(dates: Vec<NaiveDate>) -> BinDates {
+        return BinDates {
+            bin: Bin::Blue,
+            dates,
+        };
+    }
+
+    #[test]
+    fn cadences_are_inferred_around_bank_holidays() {
+        // Mondays, but the second was moved to the Wednesday
+        let fortnightly = bin_dates(vec![date(3, 18), date(4, 3), date(4, 15), date(4, 29)]);
+        assert_eq!(
+            infer_cadence(&fortnightly),
+            Some(InferredCadence {
+                bin: Bin::Blue,
+                cadence: Cadence::Fortnightly,
+                anchor: date(4, 29),
+                confidence: Confidence::High,
+            })
+        );
+
+        let weekly = bin_dates(vec![date(1, 8), date(1, 15), date(1, 22)]);
+        let inferred = infer_cadence(&weekly).unwrap();
+        assert_eq!(inferred.cadence, Cadence::Weekly);
+        assert_eq!(inferred.confidence, Confidence::Medium);
+
+        // A collection skipped over Easter still fits
+        let four_weekly = bin_dates(vec![date(1, 1), date(1, 29), date(3, 25)]);
+        let inferred = infer_cadence(&four_weekly).unwrap();
+        assert_eq!(inferred.cadence, Cadence::FourWeekly);
+        assert_eq!(inferred.confidence, Confidence::Medium);
+
+        // But one that's two weeks early makes it less certain
+        let odd_gap = bin_dates(vec![date(1, 1), date(1, 29), date(2, 26), date(3, 11)]);
+        let inferred = infer_cadence(&odd_gap).unwrap();
+        assert_eq!(inferred.cadence, Cadence::FourWeekly);
+        assert_eq!(inferred.confidence, Confidence::Low);
+
+        // Four days late is too far to be the same collection, so it's left out and makes the
+        // rest less certain
+        let moved_too_far = bin_dates(vec![date(3, 18), date(4, 5), date(4, 15), date(4, 29)]);
+        assert_eq!(on_usual_day(date(4, 5), Weekday::Mon), None);
+        let inferred = infer_cadence(&moved_too_far).unwrap();
+        assert_eq!(inferred.cadence, Cadence::Fortnightly);
+        assert_eq!(inferred.confidence, Confidence::Low);
+
+        // Fridays, but the third was brought forward to the Thursday
+        let brought_forward = bin_dates(vec![
+            date(1, 5),
+            date(1, 19),
+            date(2, 1),
+            date(2, 16),
+            date(3, 1),
+        ]);
+        assert_eq!(
+            infer_cadence(&brought_forward),
+            Some(InferredCadence {
+                bin: Bin::Blue,
+                cadence: Cadence::Fortnightly,
+                anchor: date(3, 1),
+                confidence: Confidence::Low,
+            })
+        );
+
+        assert_eq!(infer_cadence(&bin_dates(vec![date(1, 1)])), None);
+        assert_eq!(
+            infer_cadence(&bin_dates(vec![date(1, 1), date(1, 22), date(2, 12)])),
+            None
+        );
+    }
+
+    #[test]
+    fn projections_follow_on_from_the_listed_dates() {
+        let bins = vec![
+            bin_dates(vec![date(1, 8), date(1, 22), date(2, 5)]),
+            BinDates {
+                bin: Bin::Black,
+                dates: vec![date(1, 15), date(1, 29)],
+            },
+        ];
+        let upcoming = upcoming_collections(&bins, date(1, 20), date(2, 26), Confidence::Low);
+        let listed: Vec<(Bin, NaiveDate, Option<Confidence>)> = upcoming
+            .iter()
+            .map(|day| (day.bin, day.date, day.projected))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                (Bin::Blue, date(1, 22), None),
+                (Bin::Black, date(1, 29), None),
+                (Bin::Blue, date(2, 5), None),
+                (Bin::Black, date(2, 12), Some(Confidence::Low)),
+                (Bin::Blue, date(2, 19), Some(Confidence::Medium)),
+                (Bin::Black, date(2, 26), Some(Confidence::Low)),
+            ]
+        );
+
+        // The black bin's only two dates aren't enough to remind anyone about
+        let next = next_collection_with_projections(&bins, date(2, 6), Confidence::Medium).unwrap();
+        assert_eq!(next.bins.len(), 1);
+        assert_eq!(next.bins[0].date, date(2, 19));
+        assert_eq!(next.bins[0].projected, Some(Confidence::Medium));
+    }
+}
@@ -0,0 +1,225 @@
+//! Reads the dates the council site shows, which come in more formats than "1 January 2024",
+//! i.e "Monday 1st Jan", "Tues 2 January, 2024" or "Tomorrow"
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+/// Dates without a year are taken to be this year, unless that's further in the past than this,
+/// as the site lists upcoming collections
+const MAX_DAYS_AGO: i64 = 31;
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DateProblem {
+    NoDay,
+    NoMonth,
+    /// i.e "31 February"
+    NoSuchDate,
+    /// A word that isn't part of a date, i.e "Bank holiday"
+    UnexpectedWord(String),
+}
+
+impl std::fmt::Display for DateProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            DateProblem::NoDay => write!(f, "no day of the month"),
+            DateProblem::NoMonth => write!(f, "no month"),
+            DateProblem::NoSuchDate => write!(f, "not a real date"),
+            DateProblem::UnexpectedWord(word) => write!(f, "didn't expect \"{}\"", word),
+        };
+    }
+}
+
+impl std::error::Error for DateProblem {}
+
+/// `today` is for relative words and filling in a missing year
+pub fn parse_bin_date(text: &str, today: NaiveDate) -> Result<NaiveDate, DateProblem> {
+    let text = text.trim().to_lowercase();
+    match text.as_str() {
+        "today" => return Ok(today),
+        "tomorrow" => return Ok(today + Duration::days(1)),
+        _ => {}
+    }
+    for format in ["%d/%m/%Y", "%Y-%m-%d"] {
+        if let Ok(date) = NaiveDate::parse_from_str(&text, format) {
+            return Ok(date);
+        }
+    }
+
+    let mut weekday = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    let words = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|word| word.trim_end_matches('.'))
+        .filter(|word| !word.is_empty());
+    for (i, word) in words.enumerate() {
+        if i == 0 {
+            if let Some(parsed) = parse_weekday(word) {
+                weekday = Some(parsed);
+                continue;
+            }
+        }
+        if word == "of" {
+            continue;
+        }
+        if day.is_none() {
+            if let Some(parsed) = parse_day(word) {
+                day = Some(parsed);
+                continue;
+            }
+        }
+        if month.is_none() {
+            if let Some(parsed) = parse_month(word) {
+                month = Some(parsed);
+                continue;
+            }
+        }
+        if year.is_none() && word.len() == 4 {
+            if let Ok(parsed) = word.parse::<i32>() {
+                year = Some(parsed);
+                continue;
+            }
+        }
+        return Err(DateProblem::UnexpectedWord(word.to_string()));
+    }
+
+    let day = day.ok_or(DateProblem::NoDay)?;
+    let month = month.ok_or(DateProblem::NoMonth)?;
+    if let Some(year) = year {
+        return NaiveDate::from_ymd_opt(year, month, day).ok_or(DateProblem::NoSuchDate);
+    }
+
+    // Without a year, the weekday is the best clue to which year it is
+    let candidates: Vec<NaiveDate> = [today.year(), today.year() + 1]
+        .iter()
+        .filter_map(|&year| NaiveDate::from_ymd_opt(year, month, day))
+        .filter(|date| (today - *date).num_days() <= MAX_DAYS_AGO)
+        .collect();
+    if let Some(weekday) = weekday {
+        if let Some(date) = candidates.iter().find(|date| date.weekday() == weekday) {
+            return Ok(*date);
+        }
+    }
+    return candidates.first().copied().ok_or(DateProblem::NoSuchDate);
+}
+
+/// "1", "01" or "1st"
+fn parse_day(word: &str) -> Option<u32> {
+    let digits = ["st", "nd", "rd", "th"]
+        .iter()
+        .find_map(|suffix| word.strip_suffix(suffix))
+        .unwrap_or(word);
+    return match digits.parse::<u32>() {
+        Ok(day) if (1..=31).contains(&day) => Some(day),
+        _ => None,
+    };
+}
+
+/// "January", "Jan" or "Sept"
+fn parse_month(word: &str) -> Option<u32> {
+    if word.len() < 3 {
+        return None;
+    }
+    return MONTHS
+        .iter()
+        .position(|month| month.starts_with(word))
+        .map(|i| i as u32 + 1);
+}
+
+/// "Monday", "Mon" or "Tues"
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    if word.len() < 3 {
+        return None;
+    }
+    let weekdays = [
+        ("monday", Weekday::Mon),
+        ("tuesday", Weekday::Tue),
+        ("wednesday", Weekday::Wed),
+        ("thursday", Weekday::Thu),
+        ("friday", Weekday::Fri),
+        ("saturday", Weekday::Sat),
+        ("sunday", Weekday::Sun),
+    ];
+    return weekdays
+        .iter()
+        .find(|(name, _)| name.starts_with(word))
+        .map(|(_, weekday)| *weekday);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        return NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    }
+
+    #[test]
+    fn reads_the_formats_the_site_has_used() {
+        let today = date(2023, 12, 20);
+        for (text, expected) in [
+            ("14 August 2023", date(2023, 8, 14)),
+            ("Monday 1st January 2024", date(2024, 1, 1)),
+            ("Tues 2nd Jan, 2024", date(2024, 1, 2)),
+            ("Wednesday, 3 Jan.", date(2024, 1, 3)),
+            ("22nd of December", date(2023, 12, 22)),
+            ("Sept 4 2024", date(2024, 9, 4)),
+            ("08/01/2024", date(2024, 1, 8)),
+            ("  Today ", today),
+            ("Tomorrow", date(2023, 12, 21)),
+        ] {
+            assert_eq!(parse_bin_date(text, today), Ok(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn missing_years_are_worked_out_from_today() {
+        let today = date(2023, 12, 20);
+        // Recently passed, so still this year
+        assert_eq!(parse_bin_date("1 December", today), Ok(date(2023, 12, 1)));
+        // Long passed, so it must be next year
+        assert_eq!(parse_bin_date("5 November", today), Ok(date(2024, 11, 5)));
+        // 18 December is a Monday in 2023 but a Wednesday in 2024
+        assert_eq!(
+            parse_bin_date("Wednesday 18 December", today),
+            Ok(date(2024, 12, 18))
+        );
+    }
+
+    #[test]
+    fn says_why_a_date_could_not_be_read() {
+        let today = date(2023, 12, 20);
+        assert_eq!(
+            parse_bin_date("Bank holiday", today),
+            Err(DateProblem::UnexpectedWord("bank".to_string()))
+        );
+        assert_eq!(
+            parse_bin_date("January 2024", today),
+            Err(DateProblem::NoDay)
+        );
+        assert_eq!(
+            parse_bin_date("Monday 8th", today),
+            Err(DateProblem::NoMonth)
+        );
+        assert_eq!(
+            parse_bin_date("30 February 2024", today),
+            Err(DateProblem::NoSuchDate)
+        );
+        assert_eq!(parse_bin_date("", today), Err(DateProblem::NoDay));
+    }
+}
@@ -1,5 +1,15 @@
+#![allow(clippy::needless_return)]
+
 use chrono::{Datelike, NaiveDate};
 
+pub mod cadence;
+pub mod dates;
+pub mod locale;
+pub mod schedule_changes;
+
+use crate::cadence::Confidence;
+use crate::locale::Locale;
+
 /// Date returned will be 1 week from target_date if collection_day is the same day as target_date
 /// Assumption is that we don't request the collection date on the same day
 pub fn next_collection_date_from(
@@ -29,6 +39,7 @@ pub fn next_collection_date_for_bin(
     return Some(NextBinCollectionDay {
         bin: bin_dates.bin,
         date: closest_day.date,
+        projected: None,
     });
 }
 
@@ -42,7 +53,7 @@ pub fn next_bin_collection_date(
     dbg!(next_collection_date);
     let mut next_collection_day_for_bins = Vec::new();
     for bin in bins {
-        let next_day = next_collection_date_for_bin(&bin, next_collection_date);
+        let next_day = next_collection_date_for_bin(bin, next_collection_date);
         if next_day.is_none() {
             continue;
         }
@@ -70,6 +81,31 @@ pub fn next_bin_collection_date(
     };
 }
 
+/// The earliest collection on or after from_date, with every bin collected on that day.
+/// Unlike next_bin_collection_date, this doesn't assume anything about the day of the week
+pub fn next_collection_on_or_after(
+    bins: &[BinDates],
+    from_date: NaiveDate,
+) -> Option<NextBinCollection> {
+    let next_date = bins
+        .iter()
+        .flat_map(|bin_dates| bin_dates.dates.iter())
+        .filter(|date| **date >= from_date)
+        .min()?;
+
+    let bins_on_date = bins
+        .iter()
+        .filter(|bin_dates| bin_dates.dates.contains(next_date))
+        .map(|bin_dates| NextBinCollectionDay {
+            bin: bin_dates.bin,
+            date: *next_date,
+            projected: None,
+        })
+        .collect();
+
+    return Some(NextBinCollection { bins: bins_on_date });
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Bin {
     Black,
@@ -91,6 +127,20 @@ impl std::fmt::Display for Bin {
     }
 }
 
+impl std::str::FromStr for Bin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Black" => Ok(Bin::Black),
+            "Blue" => Ok(Bin::Blue),
+            "Brown" => Ok(Bin::Brown),
+            "Green" => Ok(Bin::Green),
+            _ => Err(format!("Unknown bin {}", s)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BinDates {
     pub bin: Bin,
@@ -106,6 +156,8 @@ pub struct NextBinCollection {
 pub struct NextBinCollectionDay {
     pub bin: Bin,
     pub date: NaiveDate,
+    /// Set when the date isn't from the council site, but from the bin's usual cadence
+    pub projected: Option<Confidence>,
 }
 
 #[derive(Debug)]
@@ -156,17 +208,20 @@ mod tests {
     mod next_collection_date {
         use chrono::{Datelike, Weekday};
 
-        use crate::{next_bin_collection_date, next_collection_date_from, Bin, BinDates};
+        use crate::{
+            next_bin_collection_date, next_collection_date_from, next_collection_on_or_after, Bin,
+            BinDates,
+        };
 
         #[test]
         fn it_calculates_next_collection_date_for_given_weekday() {
             let date = "2023-07-28";
-            let date = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").unwrap();
+            let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
             let next_collection_date = next_collection_date_from(date, Weekday::Mon);
 
             let expected_collection_date = "2023-07-31";
             let expected_collection_date =
-                chrono::NaiveDate::parse_from_str(&expected_collection_date, "%Y-%m-%d").unwrap();
+                chrono::NaiveDate::parse_from_str(expected_collection_date, "%Y-%m-%d").unwrap();
 
             assert_eq!(next_collection_date, expected_collection_date);
 
@@ -174,7 +229,7 @@ mod tests {
 
             let expected_collection_date = "2023-08-02";
             let expected_collection_date =
-                chrono::NaiveDate::parse_from_str(&expected_collection_date, "%Y-%m-%d").unwrap();
+                chrono::NaiveDate::parse_from_str(expected_collection_date, "%Y-%m-%d").unwrap();
 
             assert_eq!(next_collection_date, expected_collection_date);
         }
@@ -182,12 +237,12 @@ mod tests {
         #[test]
         fn same_day_of_week_calculates_next_week() {
             let date = "2023-07-31";
-            let date = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").unwrap();
+            let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
             let next_collection_date = next_collection_date_from(date, Weekday::Mon);
 
             let expected_collection_date = "2023-08-07";
             let expected_collection_date =
-                chrono::NaiveDate::parse_from_str(&expected_collection_date, "%Y-%m-%d").unwrap();
+                chrono::NaiveDate::parse_from_str(expected_collection_date, "%Y-%m-%d").unwrap();
 
             assert_eq!(next_collection_date, expected_collection_date);
         }
@@ -225,6 +280,38 @@ mod tests {
                 .collect();
             assert!(bins_collected_on.iter().eq(expected_bin_dates.iter()));
         }
+
+        #[test]
+        fn next_collection_on_or_after_includes_from_date() {
+            let monday = chrono::NaiveDate::parse_from_str("2023-07-31", "%Y-%m-%d").unwrap();
+            let next_monday = monday + chrono::Duration::days(7);
+
+            let bins = [
+                BinDates {
+                    bin: Bin::Blue,
+                    dates: vec![monday, next_monday],
+                },
+                BinDates {
+                    bin: Bin::Brown,
+                    dates: vec![next_monday],
+                },
+            ];
+
+            let collection = next_collection_on_or_after(&bins, monday).unwrap();
+            assert_eq!(collection.bins.len(), 1);
+            assert_eq!(collection.bins[0].bin, Bin::Blue);
+            assert_eq!(collection.bins[0].date, monday);
+
+            let collection =
+                next_collection_on_or_after(&bins, monday + chrono::Duration::days(1)).unwrap();
+            let collected: Vec<_> = collection.bins.iter().map(|bin| bin.bin).collect();
+            assert_eq!(collected, vec![Bin::Blue, Bin::Brown]);
+
+            assert!(
+                next_collection_on_or_after(&bins, next_monday + chrono::Duration::days(1))
+                    .is_none()
+            );
+        }
     }
 }
 
@@ -235,6 +322,38 @@ pub struct User {
     pub _id: i64,
     // TODO: Better types for these with some validation?
     pub email: String,
-    pub postcode: String,
-    pub address: String,
+    /// Optional, used for things like "Alex's turn"
+    pub name: Option<String>,
+    /// The address this user gets reminders for, shared with everyone else in the household
+    pub household_id: i64,
+    /// E.164 formatted, only set for users who want SMS reminders
+    pub phone_number: Option<String>,
+    /// Lets the user into their own page of the web app without the admin password
+    pub app_token: String,
+    /// Users who get push notifications can turn emails off
+    pub email_reminders: bool,
+    /// Minutes relative to the start of the collection day (UK time) to send the reminder,
+    /// i.e -360 is 6pm the night before
+    pub reminder_offset_minutes: i64,
+    /// Collection date of the last reminder sent, so it's only sent once
+    pub last_reminded_for: Option<NaiveDate>,
+    /// Language their reminders are sent in
+    pub locale: Locale,
+    /// Why SES said to stop emailing them, i.e a permanent bounce or a complaint
+    pub email_suppressed_reason: Option<String>,
+}
+
+impl User {
+    /// Their name if they gave one, otherwise the start of their email
+    pub fn display_name(&self) -> &str {
+        return match &self.name {
+            Some(name) => name,
+            None => self.email.split('@').next().unwrap_or(&self.email),
+        };
+    }
+
+    /// Whether to email them at all, which they can turn off and SES can stop
+    pub fn gets_emails(&self) -> bool {
+        return self.email_reminders && self.email_suppressed_reason.is_none();
+    }
 }
@@ -0,0 +1,477 @@
+//! The message catalogue for everything sent to users: reminders, schedule change notices,
+//! address problems, and the page a "Done" link opens. Each phrase goes through here so it can
+//! be given in their own language. The web app's own labels are still English only.
+
+use chrono::{Datelike, NaiveDate, Weekday};
+
+use crate::cadence::Confidence;
+use crate::schedule_changes::ScheduleChange;
+use crate::Bin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    Pl,
+    /// Scottish Gaelic
+    Gd,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 3] = [Locale::En, Locale::Pl, Locale::Gd];
+
+    /// What's stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Pl => "pl",
+            Locale::Gd => "gd",
+        }
+    }
+
+    /// The language's name in itself, for picking from a list
+    pub fn native_name(&self) -> &'static str {
+        match self {
+            Locale::En => "English",
+            Locale::Pl => "Polski",
+            Locale::Gd => "Gàidhlig",
+        }
+    }
+}
+
+impl std::str::FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Locale::En),
+            "pl" => Ok(Locale::Pl),
+            "gd" => Ok(Locale::Gd),
+            _ => Err(format!("Unknown locale {}", s)),
+        }
+    }
+}
+
+impl Bin {
+    /// The bin's colour, lower case, as it would go in the middle of a sentence
+    pub fn name(&self, locale: Locale) -> &'static str {
+        match (locale, self) {
+            (Locale::En, Bin::Black) => "black",
+            (Locale::En, Bin::Blue) => "blue",
+            (Locale::En, Bin::Brown) => "brown",
+            (Locale::En, Bin::Green) => "green",
+            (Locale::Pl, Bin::Black) => "czarny",
+            (Locale::Pl, Bin::Blue) => "niebieski",
+            (Locale::Pl, Bin::Brown) => "brązowy",
+            (Locale::Pl, Bin::Green) => "zielony",
+            (Locale::Gd, Bin::Black) => "dubh",
+            (Locale::Gd, Bin::Blue) => "gorm",
+            (Locale::Gd, Bin::Brown) => "donn",
+            (Locale::Gd, Bin::Green) => "uaine",
+        }
+    }
+}
+
+fn capitalise(s: &str) -> String {
+    let mut chars = s.chars();
+    return match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    };
+}
+
+fn weekday_name(weekday: Weekday, locale: Locale) -> &'static str {
+    let names = match locale {
+        Locale::En => [
+            "Monday",
+            "Tuesday",
+            "Wednesday",
+            "Thursday",
+            "Friday",
+            "Saturday",
+            "Sunday",
+        ],
+        Locale::Pl => [
+            "poniedziałek",
+            "wtorek",
+            "środa",
+            "czwartek",
+            "piątek",
+            "sobota",
+            "niedziela",
+        ],
+        Locale::Gd => [
+            "Diluain",
+            "Dimàirt",
+            "Diciadain",
+            "Diardaoin",
+            "Dihaoine",
+            "Disathairne",
+            "Didòmhnaich",
+        ],
+    };
+    return names[weekday.num_days_from_monday() as usize];
+}
+
+/// Month names in the form they take after a day number, i.e genitive in Polish
+fn month_name(month: u32, locale: Locale) -> &'static str {
+    let names = match locale {
+        Locale::En => [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ],
+        Locale::Pl => [
+            "stycznia",
+            "lutego",
+            "marca",
+            "kwietnia",
+            "maja",
+            "czerwca",
+            "lipca",
+            "sierpnia",
+            "września",
+            "października",
+            "listopada",
+            "grudnia",
+        ],
+        Locale::Gd => [
+            "dhen Fhaoilleach",
+            "dhen Ghearran",
+            "dhen Mhàrt",
+            "dhen Ghiblean",
+            "dhen Chèitean",
+            "dhen Ògmhios",
+            "dhen Iuchar",
+            "dhen Lùnastal",
+            "dhen t-Sultain",
+            "dhen Dàmhair",
+            "dhen t-Samhain",
+            "dhen Dùbhlachd",
+        ],
+    };
+    return names[month as usize - 1];
+}
+
+/// i.e "Monday 31 July", "poniedziałek, 31 lipca", "Diluain, 31mh dhen Iuchar"
+pub fn long_date(date: NaiveDate, locale: Locale) -> String {
+    let weekday = weekday_name(date.weekday(), locale);
+    let month = month_name(date.month(), locale);
+    return match locale {
+        Locale::En => format!("{} {} {}", weekday, date.day(), month),
+        Locale::Pl => format!("{}, {} {}", weekday, date.day(), month),
+        Locale::Gd => format!("{}, {}mh {}", weekday, date.day(), month),
+    };
+}
+
+/// i.e "Blue, Brown bins out tonight", or "Blue, Brown bins out this morning" for reminders
+/// sent on the day rather than the `day_before`
+pub fn bins_out(bins: &[Bin], day_before: bool, locale: Locale) -> String {
+    let names: Vec<&str> = bins.iter().map(|bin| bin.name(locale)).collect();
+    let when = match (locale, day_before) {
+        (Locale::En, true) => "tonight",
+        (Locale::En, false) => "this morning",
+        (Locale::Pl, true) => "dziś wieczorem",
+        (Locale::Pl, false) => "dziś rano",
+        (Locale::Gd, true) => "a-nochd",
+        (Locale::Gd, false) => "madainn an-diugh",
+    };
+    return match (locale, names.len()) {
+        (Locale::En, 1) => format!("{} bin out {}", capitalise(names[0]), when),
+        (Locale::En, _) => {
+            let names: Vec<String> = names.iter().map(|name| capitalise(name)).collect();
+            format!("{} bins out {}", names.join(", "), when)
+        }
+        (Locale::Pl, 1) => format!("Wystaw {} kosz: {}", when, names[0]),
+        (Locale::Pl, _) => format!("Wystaw {} kosze: {}", when, names.join(", ")),
+        (Locale::Gd, 1) => format!("Cuir a-mach am biona {} {}", names[0], when),
+        (Locale::Gd, _) => format!("Cuir a-mach na bionaichean {}: {}", when, names.join(", ")),
+    };
+}
+
+/// One line of a reminder's body, i.e "Blue bin is being collected on Monday 31 July"
+pub fn bin_collected_on(bin: Bin, date: NaiveDate, locale: Locale) -> String {
+    let date = long_date(date, locale);
+    return match locale {
+        Locale::En => format!(
+            "{} bin is being collected on {}",
+            capitalise(bin.name(locale)),
+            date
+        ),
+        Locale::Pl => format!("Kosz {} zostanie odebrany: {}", bin.name(locale), date),
+        Locale::Gd => format!("Thèid am biona {} a thogail {}", bin.name(locale), date),
+    };
+}
+
+/// Goes after a date that isn't on the council site yet, but is expected from the bin's usual
+/// cadence
+pub fn projected(confidence: Confidence, locale: Locale) -> String {
+    let confidence = match (locale, confidence) {
+        (Locale::En, _) => confidence.as_str(),
+        (Locale::Pl, Confidence::Low) => "niska",
+        (Locale::Pl, Confidence::Medium) => "średnia",
+        (Locale::Pl, Confidence::High) => "wysoka",
+        (Locale::Gd, Confidence::Low) => "ìosal",
+        (Locale::Gd, Confidence::Medium) => "meadhanach",
+        (Locale::Gd, Confidence::High) => "àrd",
+    };
+    return match locale {
+        Locale::En => format!(
+            "(expected from the usual schedule, {} confidence - not on the council site yet)",
+            confidence
+        ),
+        Locale::Pl => format!(
+            "(według zwykłego harmonogramu, pewność {} - jeszcze nie ma na stronie rady)",
+            confidence
+        ),
+        Locale::Gd => format!(
+            "(a rèir a' chlàr àbhaisteach, misneachd {} - chan eil e air làrach na comhairle fhathast)",
+            confidence
+        ),
+    };
+}
+
+/// Puts whose turn it is in front of a subject, i.e "Alex's turn: Blue bin out tonight"
+pub fn on_duty(name: &str, subject: &str, locale: Locale) -> String {
+    return match locale {
+        Locale::En => format!("{}'s turn: {}", name, subject),
+        Locale::Pl => format!("Dyżur: {}. {}", name, subject),
+        Locale::Gd => format!("An t-seal aig {}: {}", name, subject),
+    };
+}
+
+/// The second reminder's subject, when nobody has said the bins are out
+pub fn still_not_out(subject: &str, locale: Locale) -> String {
+    return match locale {
+        Locale::En => format!("Still not out! {}", subject),
+        Locale::Pl => format!("Nadal nie wystawione! {}", subject),
+        Locale::Gd => format!("Fhathast gun chur a-mach! {}", subject),
+    };
+}
+
+/// Goes with the signed "Done" link
+pub fn let_everyone_know(locale: Locale) -> &'static str {
+    match locale {
+        Locale::En => "Put them out? Let everyone know so they aren't reminded",
+        Locale::Pl => "Wystawione? Daj znać pozostałym, żeby nie dostali przypomnienia",
+        Locale::Gd => "An do chuir thu a-mach iad? Innis do chàch",
+    }
+}
+
+/// The button on the page a "Done" link opens
+pub fn done_button(date: NaiveDate, locale: Locale) -> String {
+    let date = long_date(date, locale);
+    return match locale {
+        Locale::En => format!("Done, the bins are out for {}", date),
+        Locale::Pl => format!("Gotowe, kosze są wystawione na {}", date),
+        Locale::Gd => format!("Deiseil, tha na bionaichean a-muigh airson {}", date),
+    };
+}
+
+/// Shown once the "Done" button's been pressed
+pub fn done_thanks(locale: Locale) -> &'static str {
+    match locale {
+        Locale::En => "Thanks, nobody else will be reminded about this one.",
+        Locale::Pl => "Dziękujemy, nikt inny nie dostanie już przypomnienia o tym odbiorze.",
+        Locale::Gd => "Tapadh leat, chan fhaigh duine eile cuimhneachan mu dheidhinn seo.",
+    }
+}
+
+/// One line of a schedule change notice, i.e "Blue bin moved from Monday 25 December to
+/// Wednesday 27 December"
+pub fn schedule_change(change: &ScheduleChange, locale: Locale) -> String {
+    return match (locale, *change) {
+        (Locale::En, ScheduleChange::Added { bin, date }) => format!(
+            "Extra {} bin collection on {}",
+            bin.name(locale),
+            long_date(date, locale)
+        ),
+        (Locale::En, ScheduleChange::Removed { bin, date }) => format!(
+            "{} bin collection on {} has been cancelled",
+            capitalise(bin.name(locale)),
+            long_date(date, locale)
+        ),
+        (Locale::En, ScheduleChange::Moved { bin, from, to }) => format!(
+            "{} bin moved from {} to {}",
+            capitalise(bin.name(locale)),
+            long_date(from, locale),
+            long_date(to, locale)
+        ),
+        (Locale::Pl, ScheduleChange::Added { bin, date }) => format!(
+            "Kosz {}: dodatkowy odbiór ({})",
+            bin.name(locale),
+            long_date(date, locale)
+        ),
+        (Locale::Pl, ScheduleChange::Removed { bin, date }) => format!(
+            "Kosz {}: odbiór ({}) został odwołany",
+            bin.name(locale),
+            long_date(date, locale)
+        ),
+        (Locale::Pl, ScheduleChange::Moved { bin, from, to }) => format!(
+            "Kosz {}: zmiana terminu odbioru ({} → {})",
+            bin.name(locale),
+            long_date(from, locale),
+            long_date(to, locale)
+        ),
+        (Locale::Gd, ScheduleChange::Added { bin, date }) => format!(
+            "Biona {}: togail a bharrachd {}",
+            bin.name(locale),
+            long_date(date, locale)
+        ),
+        (Locale::Gd, ScheduleChange::Removed { bin, date }) => format!(
+            "Biona {}: chaidh an togail {} a chur dheth",
+            bin.name(locale),
+            long_date(date, locale)
+        ),
+        (Locale::Gd, ScheduleChange::Moved { bin, from, to }) => format!(
+            "Biona {}: chaidh an togail a ghluasad bho {} gu {}",
+            bin.name(locale),
+            long_date(from, locale),
+            long_date(to, locale)
+        ),
+    };
+}
+
+/// The subject when there's more than one schedule change to tell a household about
+pub fn schedule_changes_count(count: usize, locale: Locale) -> String {
+    return match locale {
+        Locale::En => format!("{} changes to your bin collections", count),
+        Locale::Pl => format!("Zmiany w odbiorze koszy: {}", count),
+        Locale::Gd => format!("Atharraichean air togail nam biona: {}", count),
+    };
+}
+
+pub fn address_not_found(locale: Locale) -> &'static str {
+    match locale {
+        Locale::En => "We couldn't find your address on the council website",
+        Locale::Pl => "Nie znaleźliśmy Twojego adresu na stronie rady",
+        Locale::Gd => "Cha do lorg sinn an seòladh agad air làrach-lìn na comhairle",
+    }
+}
+
+/// Why the council site couldn't find the address, for when the postcode itself is the problem
+pub fn postcode_not_recognised(postcode: &str, locale: Locale) -> String {
+    return match locale {
+        Locale::En => format!("The council site doesn't recognise {}", postcode),
+        Locale::Pl => format!("Strona rady nie rozpoznaje kodu pocztowego {}", postcode),
+        Locale::Gd => format!("Chan aithnich làrach na comhairle {}", postcode),
+    };
+}
+
+/// Why the council site couldn't find the address, when the postcode's fine but the address
+/// isn't one of the `available` ones
+pub fn address_not_in_list(address: &str, available: &[String], locale: Locale) -> String {
+    let available = available.join("; ");
+    return match locale {
+        Locale::En => format!(
+            "\"{}\" isn't one of the addresses for the postcode: {}",
+            address, available
+        ),
+        Locale::Pl => format!(
+            "Adresu \"{}\" nie ma wśród adresów dla tego kodu pocztowego: {}",
+            address, available
+        ),
+        Locale::Gd => format!(
+            "Chan eil \"{}\" am measg nan seòlaidhean airson a' chòd-phuist: {}",
+            address, available
+        ),
+    };
+}
+
+/// Goes after the reason in an address problem email
+pub fn no_reminders_until_fixed(locale: Locale) -> &'static str {
+    match locale {
+        Locale::En => "You won't get bin reminders until your household's postcode and address are fixed.",
+        Locale::Pl => "Przypomnienia o koszach nie będą wysyłane, dopóki kod pocztowy i adres nie zostaną poprawione.",
+        Locale::Gd => "Chan fhaigh thu cuimhneachain bhiona gus an tèid còd-puist is seòladh an taighe agad a chàradh.",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        return NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    }
+
+    #[test]
+    fn long_dates_in_each_locale() {
+        let date = date("2023-07-31");
+        assert_eq!(long_date(date, Locale::En), "Monday 31 July");
+        assert_eq!(long_date(date, Locale::Pl), "poniedziałek, 31 lipca");
+        assert_eq!(long_date(date, Locale::Gd), "Diluain, 31mh dhen Iuchar");
+    }
+
+    #[test]
+    fn subjects_in_each_locale() {
+        let bins = [Bin::Blue, Bin::Brown];
+        assert_eq!(
+            bins_out(&bins, true, Locale::En),
+            "Blue, Brown bins out tonight"
+        );
+        assert_eq!(
+            bins_out(&bins[..1], true, Locale::En),
+            "Blue bin out tonight"
+        );
+        assert_eq!(
+            bins_out(&bins, true, Locale::Pl),
+            "Wystaw dziś wieczorem kosze: niebieski, brązowy"
+        );
+        assert_eq!(
+            bins_out(&bins[..1], true, Locale::Gd),
+            "Cuir a-mach am biona gorm a-nochd"
+        );
+        // Reminded on the day
+        assert_eq!(
+            bins_out(&bins[..1], false, Locale::En),
+            "Blue bin out this morning"
+        );
+        assert_eq!(
+            bins_out(&bins[..1], false, Locale::Pl),
+            "Wystaw dziś rano kosz: niebieski"
+        );
+        assert_eq!(
+            bins_out(&bins[..1], false, Locale::Gd),
+            "Cuir a-mach am biona gorm madainn an-diugh"
+        );
+
+        assert_eq!(
+            schedule_change(
+                &ScheduleChange::Moved {
+                    bin: Bin::Blue,
+                    from: date("2023-12-25"),
+                    to: date("2023-12-27"),
+                },
+                Locale::Pl
+            ),
+            "Kosz niebieski: zmiana terminu odbioru (poniedziałek, 25 grudnia → środa, 27 grudnia)"
+        );
+
+        assert_eq!(
+            bin_collected_on(Bin::Green, date("2023-12-25"), Locale::En),
+            "Green bin is being collected on Monday 25 December"
+        );
+        assert_eq!(
+            projected(Confidence::Medium, Locale::En),
+            "(expected from the usual schedule, medium confidence - not on the council site yet)"
+        );
+    }
+
+    #[test]
+    fn locales_round_trip() {
+        for locale in Locale::ALL {
+            assert_eq!(locale.as_str().parse::<Locale>(), Ok(locale));
+        }
+        assert!("fr".parse::<Locale>().is_err());
+    }
+}
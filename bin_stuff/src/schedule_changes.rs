@@ -0,0 +1,283 @@
+use chrono::NaiveDate;
+
+use crate::{Bin, BinDates};
+
+/// How far a collection can move and still be treated as the same collection moving,
+/// rather than one being cancelled and another added
+const MAX_MOVE_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleChange {
+    Added {
+        bin: Bin,
+        date: NaiveDate,
+    },
+    Removed {
+        bin: Bin,
+        date: NaiveDate,
+    },
+    Moved {
+        bin: Bin,
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+}
+
+impl std::fmt::Display for ScheduleChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let date_format = "%-d %b";
+        return match self {
+            ScheduleChange::Added { bin, date } => write!(
+                f,
+                "Extra {} bin collection on {}",
+                bin,
+                date.format(date_format)
+            ),
+            ScheduleChange::Removed { bin, date } => write!(
+                f,
+                "{} bin collection on {} has been cancelled",
+                bin,
+                date.format(date_format)
+            ),
+            ScheduleChange::Moved { bin, from, to } => write!(
+                f,
+                "{} bin moved from {} to {}",
+                bin,
+                from.format(date_format),
+                to.format(date_format)
+            ),
+        };
+    }
+}
+
+/// Compares a newly scraped schedule against the previous one.
+/// Dates before `today` are ignored, since they drop off the council site as they pass.
+/// The site just shows a few weeks at a time, so only the weeks both scrapes cover are compared.
+/// New dates past the end of the old schedule are the schedule carrying on, and old dates past
+/// the end of the new one are the site showing fewer weeks, rather than changes.
+/// A bin that's gone from the new schedule has had every collection it covers cancelled
+pub fn diff_schedules(old: &[BinDates], new: &[BinDates], today: NaiveDate) -> Vec<ScheduleChange> {
+    let mut changes = Vec::new();
+    let new_horizon = match new.iter().flat_map(|b| b.dates.iter()).max() {
+        Some(date) => *date,
+        // Nothing to compare with, which is more likely a bad scrape than every bin stopping
+        None => return changes,
+    };
+
+    for old_bin_dates in old {
+        let bin = old_bin_dates.bin;
+        let old_dates: Vec<NaiveDate> = old_bin_dates
+            .dates
+            .iter()
+            .filter(|date| **date >= today)
+            .copied()
+            .collect();
+        let old_horizon = match old_dates.iter().max() {
+            Some(date) => *date,
+            None => continue,
+        };
+        let new_dates: Vec<NaiveDate> = match new.iter().find(|b| b.bin == bin) {
+            Some(new_bin_dates) => new_bin_dates
+                .dates
+                .iter()
+                .filter(|date| **date >= today)
+                .copied()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut removed: Vec<NaiveDate> = old_dates
+            .iter()
+            .filter(|date| **date <= new_horizon && !new_dates.contains(date))
+            .copied()
+            .collect();
+        let mut added: Vec<NaiveDate> = new_dates
+            .iter()
+            .filter(|date| !old_dates.contains(date))
+            .copied()
+            .collect();
+        removed.sort();
+        added.sort();
+
+        // Pair each removed date up with the closest added one, if there is one close enough
+        for removed_date in removed {
+            let closest = added
+                .iter()
+                .enumerate()
+                .map(|(i, added_date)| (i, (*added_date - removed_date).num_days().abs()))
+                .filter(|(_, days)| *days <= MAX_MOVE_DAYS)
+                .min_by_key(|(_, days)| *days);
+            match closest {
+                Some((i, _)) => {
+                    let to = added.remove(i);
+                    changes.push(ScheduleChange::Moved {
+                        bin,
+                        from: removed_date,
+                        to,
+                    });
+                }
+                None => changes.push(ScheduleChange::Removed {
+                    bin,
+                    date: removed_date,
+                }),
+            }
+        }
+
+        for added_date in added.into_iter().filter(|date| *date <= old_horizon) {
+            changes.push(ScheduleChange::Added {
+                bin,
+                date: added_date,
+            });
+        }
+    }
+
+    return changes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        return NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    }
+
+    #[test]
+    fn detects_moved_collections() {
+        let today = date("2023-12-18");
+        let old = [BinDates {
+            bin: Bin::Blue,
+            dates: vec![date("2023-12-11"), date("2023-12-25"), date("2024-01-08")],
+        }];
+        let new = [BinDates {
+            bin: Bin::Blue,
+            dates: vec![date("2023-12-27"), date("2024-01-08"), date("2024-01-22")],
+        }];
+
+        let changes = diff_schedules(&old, &new, today);
+        assert_eq!(
+            changes,
+            vec![ScheduleChange::Moved {
+                bin: Bin::Blue,
+                from: date("2023-12-25"),
+                to: date("2023-12-27"),
+            }]
+        );
+        assert_eq!(
+            changes[0].to_string(),
+            "Blue bin moved from 25 Dec to 27 Dec"
+        );
+    }
+
+    #[test]
+    fn detects_added_and_removed_collections() {
+        let today = date("2023-12-18");
+        let old = [
+            BinDates {
+                bin: Bin::Black,
+                dates: vec![date("2023-12-25"), date("2024-01-08")],
+            },
+            BinDates {
+                bin: Bin::Brown,
+                dates: vec![date("2023-12-18"), date("2024-01-15")],
+            },
+        ];
+        let new = [
+            BinDates {
+                bin: Bin::Black,
+                dates: vec![date("2024-01-08")],
+            },
+            BinDates {
+                bin: Bin::Brown,
+                dates: vec![date("2023-12-18"), date("2024-01-01"), date("2024-01-15")],
+            },
+        ];
+
+        let changes = diff_schedules(&old, &new, today);
+        assert_eq!(
+            changes,
+            vec![
+                ScheduleChange::Removed {
+                    bin: Bin::Black,
+                    date: date("2023-12-25"),
+                },
+                ScheduleChange::Added {
+                    bin: Bin::Brown,
+                    date: date("2024-01-01"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_schedule_carrying_on() {
+        let today = date("2023-12-18");
+        let old = [BinDates {
+            bin: Bin::Green,
+            dates: vec![date("2023-12-04"), date("2023-12-18")],
+        }];
+        let new = [BinDates {
+            bin: Bin::Green,
+            dates: vec![date("2023-12-18"), date("2024-01-01")],
+        }];
+
+        assert!(diff_schedules(&old, &new, today).is_empty());
+        assert!(diff_schedules(&[], &new, today).is_empty());
+    }
+
+    #[test]
+    fn ignores_the_site_showing_fewer_weeks() {
+        let today = date("2023-12-18");
+        let old = [
+            BinDates {
+                bin: Bin::Black,
+                dates: vec![date("2023-12-25"), date("2024-01-08"), date("2024-01-22")],
+            },
+            BinDates {
+                bin: Bin::Blue,
+                dates: vec![date("2024-01-01"), date("2024-01-15")],
+            },
+        ];
+        let new = [
+            BinDates {
+                bin: Bin::Black,
+                dates: vec![date("2023-12-25"), date("2024-01-08")],
+            },
+            BinDates {
+                bin: Bin::Blue,
+                dates: vec![date("2024-01-01")],
+            },
+        ];
+
+        assert!(diff_schedules(&old, &new, today).is_empty());
+    }
+
+    #[test]
+    fn bins_gone_from_the_site_are_cancelled() {
+        let today = date("2023-12-18");
+        let old = [
+            BinDates {
+                bin: Bin::Black,
+                dates: vec![date("2023-12-25"), date("2024-01-08")],
+            },
+            BinDates {
+                bin: Bin::Green,
+                dates: vec![date("2024-01-01"), date("2024-01-29")],
+            },
+        ];
+        let new = [BinDates {
+            bin: Bin::Black,
+            dates: vec![date("2023-12-25"), date("2024-01-08")],
+        }];
+
+        // 29 Jan is past what the new scrape covers, so might still be happening
+        assert_eq!(
+            diff_schedules(&old, &new, today),
+            vec![ScheduleChange::Removed {
+                bin: Bin::Green,
+                date: date("2024-01-01"),
+            }]
+        );
+        assert!(diff_schedules(&old, &[], today).is_empty());
+    }
+}
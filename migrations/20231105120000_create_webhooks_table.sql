@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS webhooks (
+	id          INTEGER PRIMARY KEY,
+	kind        TEXT NOT NULL,
+	url         TEXT NOT NULL,
+	postcode    TEXT NOT NULL,
+	address     TEXT NOT NULL
+);
@@ -0,0 +1,12 @@
+ALTER TABLE emails ADD COLUMN phone_number TEXT;
+
+CREATE TABLE IF NOT EXISTS sms_messages (
+	id              INTEGER PRIMARY KEY,
+	user_id         INTEGER NOT NULL REFERENCES emails (id),
+	phone_number    TEXT NOT NULL,
+	provider_id     TEXT NOT NULL,
+	cost            REAL NOT NULL,
+	sent_at         TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS SmsMessagesSentAtIndex ON sms_messages (sent_at);
@@ -0,0 +1,26 @@
+ALTER TABLE emails ADD COLUMN app_token TEXT;
+UPDATE emails SET app_token = lower(hex(randomblob(16))) WHERE app_token IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS EmailsUniqueIndexOnAppToken ON emails (app_token);
+
+ALTER TABLE emails ADD COLUMN email_reminders INTEGER NOT NULL DEFAULT 1;
+
+CREATE TABLE IF NOT EXISTS push_subscriptions (
+	id          INTEGER PRIMARY KEY,
+	user_id     INTEGER NOT NULL REFERENCES emails (id),
+	endpoint    TEXT NOT NULL,
+	p256dh      TEXT NOT NULL,
+	auth        TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS PushSubscriptionsUniqueIndexOnEndpoint ON push_subscriptions (endpoint);
+
+-- Latest scraped dates for each address, so the web app doesn't need to scrape on every visit
+CREATE TABLE IF NOT EXISTS bin_dates (
+	id          INTEGER PRIMARY KEY,
+	postcode    TEXT NOT NULL,
+	address     TEXT NOT NULL,
+	bin         TEXT NOT NULL,
+	date        TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS BinDatesIndexOnAddress ON bin_dates (postcode, address);
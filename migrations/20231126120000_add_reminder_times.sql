@@ -0,0 +1,5 @@
+-- Minutes relative to the start of the collection day, UK time. Defaults to 6pm the night before
+ALTER TABLE emails ADD COLUMN reminder_offset_minutes INTEGER NOT NULL DEFAULT -360;
+ALTER TABLE emails ADD COLUMN last_reminded_for TEXT;
+
+ALTER TABLE webhooks ADD COLUMN last_reminded_for TEXT;
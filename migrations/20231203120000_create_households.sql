@@ -0,0 +1,39 @@
+CREATE TABLE IF NOT EXISTS households (
+	id          INTEGER PRIMARY KEY,
+	name        TEXT NOT NULL,
+	postcode    TEXT NOT NULL,
+	address     TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS HouseholdsUniqueIndexOnAddress ON households (postcode, address);
+
+-- Every address already in use becomes a household
+INSERT OR IGNORE INTO households (name, postcode, address)
+	SELECT address, postcode, address FROM emails;
+INSERT OR IGNORE INTO households (name, postcode, address)
+	SELECT address, postcode, address FROM webhooks;
+
+ALTER TABLE emails ADD COLUMN household_id INTEGER REFERENCES households (id);
+UPDATE emails SET household_id = (
+	SELECT id FROM households WHERE households.postcode = emails.postcode AND households.address = emails.address
+);
+ALTER TABLE emails DROP COLUMN postcode;
+ALTER TABLE emails DROP COLUMN address;
+
+ALTER TABLE webhooks ADD COLUMN household_id INTEGER REFERENCES households (id);
+UPDATE webhooks SET household_id = (
+	SELECT id FROM households WHERE households.postcode = webhooks.postcode AND households.address = webhooks.address
+);
+ALTER TABLE webhooks DROP COLUMN postcode;
+ALTER TABLE webhooks DROP COLUMN address;
+
+-- Dates are scraped once per household now
+DROP TABLE IF EXISTS bin_dates;
+CREATE TABLE IF NOT EXISTS bin_dates (
+	id              INTEGER PRIMARY KEY,
+	household_id    INTEGER NOT NULL REFERENCES households (id),
+	bin             TEXT NOT NULL,
+	date            TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS BinDatesIndexOnHousehold ON bin_dates (household_id);
@@ -0,0 +1,25 @@
+-- What to call people in the rota, i.e "Alex's turn"
+ALTER TABLE emails ADD COLUMN name TEXT;
+
+CREATE TABLE IF NOT EXISTS rota_members (
+	id              INTEGER PRIMARY KEY,
+	household_id    INTEGER NOT NULL REFERENCES households (id),
+	user_id         INTEGER NOT NULL REFERENCES emails (id),
+	position        INTEGER NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS RotaMembersUniqueIndexOnUser ON rota_members (user_id);
+
+-- Whose turn it is (an index into the rota in position order), and which collection it's for
+ALTER TABLE households ADD COLUMN rota_turn INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE households ADD COLUMN rota_turn_for TEXT;
+
+CREATE TABLE IF NOT EXISTS acknowledgements (
+	id                  INTEGER PRIMARY KEY,
+	household_id        INTEGER NOT NULL REFERENCES households (id),
+	collection_date     TEXT NOT NULL,
+	user_id             INTEGER REFERENCES emails (id),
+	acknowledged_at     TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS AcknowledgementsUniqueIndexOnCollection ON acknowledgements (household_id, collection_date);
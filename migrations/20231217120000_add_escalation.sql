@@ -0,0 +1,9 @@
+-- Minutes relative to the start of the collection day to send a second reminder if nobody has
+-- said the bins are out, i.e -120 is 10pm the night before. NULL means never
+ALTER TABLE households ADD COLUMN escalation_offset_minutes INTEGER;
+-- Someone outside the household (i.e a landlord or neighbour) who also gets the second reminder
+ALTER TABLE households ADD COLUMN escalation_email TEXT;
+ALTER TABLE households ADD COLUMN last_escalated_for TEXT;
+
+-- How the collection was marked as done, i.e "app" or "link"
+ALTER TABLE acknowledgements ADD COLUMN source TEXT NOT NULL DEFAULT 'app';
@@ -0,0 +1,2 @@
+-- Language reminders are sent in, i.e "en", "pl", or "gd"
+ALTER TABLE emails ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
@@ -0,0 +1,3 @@
+-- Set when SES reports the address bounced for good or the user marked a reminder as spam
+ALTER TABLE emails ADD COLUMN email_suppressed_reason TEXT;
+ALTER TABLE emails ADD COLUMN email_suppressed_at TEXT;
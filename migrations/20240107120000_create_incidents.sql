@@ -0,0 +1,13 @@
+-- A run of the same error, alerted about once when it starts and once when it stops
+CREATE TABLE IF NOT EXISTS incidents (
+	id              INTEGER PRIMARY KEY,
+	area            TEXT NOT NULL,
+	fingerprint     TEXT NOT NULL,
+	summary         TEXT NOT NULL,
+	first_seen      TEXT NOT NULL,
+	last_seen       TEXT NOT NULL,
+	occurrences     INTEGER NOT NULL DEFAULT 1,
+	resolved_at     TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS IncidentsUniqueIndexOnOpenFingerprint ON incidents (fingerprint) WHERE resolved_at IS NULL;
@@ -0,0 +1,2 @@
+-- Why the council site couldn't find the household's address, until it next can
+ALTER TABLE households ADD COLUMN address_problem TEXT;
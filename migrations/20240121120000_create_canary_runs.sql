@@ -0,0 +1,11 @@
+-- Each daily scrape of a known-good address, and what was wrong with it if anything
+CREATE TABLE IF NOT EXISTS canary_runs (
+	id              INTEGER PRIMARY KEY,
+	postcode        TEXT NOT NULL,
+	address         TEXT NOT NULL,
+	ran_at          TEXT NOT NULL,
+	ok              INTEGER NOT NULL,
+	problem         TEXT
+);
+
+CREATE INDEX IF NOT EXISTS CanaryRunsIndexOnAddress ON canary_runs (postcode, address);
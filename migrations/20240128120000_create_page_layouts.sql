@@ -0,0 +1,13 @@
+-- The dates page's fingerprint from each scrape of an address. Compared with the last known good
+-- one for the address, and only known good itself if it matched or was accepted on the health page
+CREATE TABLE IF NOT EXISTS page_layouts (
+	id              INTEGER PRIMARY KEY,
+	postcode        TEXT NOT NULL,
+	address         TEXT NOT NULL,
+	seen_at         TEXT NOT NULL,
+	fingerprint     TEXT NOT NULL,
+	known_good      INTEGER NOT NULL,
+	changes         TEXT
+);
+
+CREATE INDEX IF NOT EXISTS PageLayoutsIndexOnAddress ON page_layouts (postcode, address);
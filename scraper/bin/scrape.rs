@@ -0,0 +1,310 @@
+//! Scrapes one address and prints what was found, for debugging an address without adding it
+//! to the server. Reads the same BROWSER_*, GECKODRIVER_URL, SCRAPE_STEP_TIMEOUTS and
+//! SCRAPE_FLOW_FILE variables as the server, which the options override.
+
+#![allow(clippy::needless_return)]
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use bin_stuff::cadence::{infer_cadence, upcoming_collections, Confidence, PROJECTION_WEEKS};
+use bin_stuff::{next_collection_on_or_after, NextBinCollection, NextBinCollectionDay};
+use chrono::NaiveDate;
+use scraper::{BrowserConfig, ScrapeFlow, ScrapeReport, ScrapeSession, StepTimeouts};
+use serde_json::json;
+
+const USAGE: &str =
+    "Usage: scrape [--json] [--save DIR] [--flow FILE] [--driver URL] POSTCODE ADDRESS
+
+  --json        Print JSON rather than a table
+  --save DIR    Save the dates page's HTML and a screenshot in DIR, or the failure if it fails
+  --flow FILE   Scrape flow to follow, rather than SCRAPE_FLOW_FILE or the built in one
+  --driver URL  WebDriver to use, rather than the first in GECKODRIVER_URL";
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    json: bool,
+    save: Option<PathBuf>,
+    flow: Option<PathBuf>,
+    driver: Option<String>,
+    postcode: String,
+    address: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, Error> {
+    let mut parsed = Args::default();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            return args.next().ok_or_else(|| anyhow!("{} needs a value", name));
+        };
+        match arg.as_str() {
+            "--json" => parsed.json = true,
+            "--save" => parsed.save = Some(PathBuf::from(value("--save")?)),
+            "--flow" => parsed.flow = Some(PathBuf::from(value("--flow")?)),
+            "--driver" => parsed.driver = Some(value("--driver")?),
+            other if other.starts_with("--") => return Err(anyhow!("Unknown option {}", other)),
+            _ => positional.push(arg),
+        }
+    }
+    let [postcode, address]: [String; 2] = positional
+        .try_into()
+        .map_err(|_| anyhow!("Expected a postcode and an address"))?;
+    parsed.postcode = postcode;
+    parsed.address = address;
+    return Ok(parsed);
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    // Only problems by default, so the table isn't lost among the scraper's progress
+    let env = env_logger::Env::default().default_filter_or("warn");
+    env_logger::init_from_env(env);
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = run(args).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> Result<(), Error> {
+    let browser = BrowserConfig::from_env()?;
+    let timeouts: StepTimeouts = match std::env::var("SCRAPE_STEP_TIMEOUTS") {
+        Ok(timeouts) => timeouts.parse()?,
+        Err(_) => StepTimeouts::default(),
+    };
+    let flow_path = args
+        .flow
+        .or_else(|| std::env::var("SCRAPE_FLOW_FILE").ok().map(PathBuf::from));
+    let flow = match flow_path {
+        Some(path) => ScrapeFlow::load(&path)?,
+        None => ScrapeFlow::default(),
+    };
+    let driver_url = args.driver.or_else(|| {
+        let urls = std::env::var("GECKODRIVER_URL").ok()?;
+        return urls.split(',').next().map(|url| url.trim().to_string());
+    });
+
+    let session = ScrapeSession::open(driver_url, &browser, Arc::new(flow), timeouts).await?;
+    let result = match &args.save {
+        Some(_) => session
+            .scrape_and_capture(&args.postcode, &args.address)
+            .await
+            .map(|(report, page)| (report, Some(page))),
+        None => session
+            .scrape(&args.postcode, &args.address)
+            .await
+            .map(|report| (report, None)),
+    };
+    session.close().await;
+
+    let (report, page) = match result {
+        Ok(scraped) => scraped,
+        Err(failure) => {
+            if let Some(dir) = &args.save {
+                let saved = failure.save(dir)?;
+                eprintln!("Saved the failure to {}", saved.display());
+            }
+            return Err(failure.into());
+        }
+    };
+    if let (Some(dir), Some(page)) = (&args.save, page) {
+        let saved = page.save(dir)?;
+        eprintln!("Saved the dates page to {}", saved.display());
+    }
+
+    let today = chrono::Utc::now().date_naive();
+    let next_collection = next_collection_on_or_after(&report.bins, today);
+    let projected = projected_collections(&report, today);
+    if args.json {
+        println!("{}", to_json(&report, next_collection.as_ref(), &projected));
+    } else {
+        print!(
+            "{}",
+            to_table(&report, next_collection.as_ref(), &projected)
+        );
+    }
+    return Ok(());
+}
+
+fn format_date(date: &NaiveDate) -> String {
+    return date.format("%a %d %b %Y").to_string();
+}
+
+/// Collections expected from each bin's cadence after the dates the site listed
+fn projected_collections(report: &ScrapeReport, from: NaiveDate) -> Vec<NextBinCollectionDay> {
+    let until = from + chrono::Duration::weeks(PROJECTION_WEEKS);
+    return upcoming_collections(&report.bins, from, until, Confidence::Low)
+        .into_iter()
+        .filter(|day| day.projected.is_some())
+        .collect();
+}
+
+fn to_table(
+    report: &ScrapeReport,
+    next_collection: Option<&NextBinCollection>,
+    projected: &[NextBinCollectionDay],
+) -> String {
+    let mut table = format!("{:<6} Dates\n", "Bin");
+    for bin_dates in &report.bins {
+        let dates: Vec<String> = bin_dates.dates.iter().map(format_date).collect();
+        // Bin's Display ignores padding
+        let bin = bin_dates.bin.to_string();
+        table.push_str(&format!("{:<6} {}\n", bin, dates.join(", ")));
+    }
+    table.push('\n');
+    match next_collection.and_then(|next| next.bins.first().map(|day| (next, day.date))) {
+        Some((next, date)) => {
+            let bins: Vec<String> = next.bins.iter().map(|day| day.bin.to_string()).collect();
+            table.push_str(&format!(
+                "Next collection: {} - {}\n",
+                format_date(&date),
+                bins.join(", ")
+            ));
+        }
+        None => table.push_str("Next collection: none found\n"),
+    }
+    for day in projected {
+        if let Some(confidence) = day.projected {
+            table.push_str(&format!(
+                "Projected: {} - {} ({} confidence)\n",
+                format_date(&day.date),
+                day.bin,
+                confidence.as_str()
+            ));
+        }
+    }
+    for warning in &report.warnings {
+        table.push_str(&format!("Warning: {}\n", warning));
+    }
+    return table;
+}
+
+fn to_json(
+    report: &ScrapeReport,
+    next_collection: Option<&NextBinCollection>,
+    projected: &[NextBinCollectionDay],
+) -> serde_json::Value {
+    let bins: Vec<serde_json::Value> = report
+        .bins
+        .iter()
+        .map(|bin_dates| {
+            let dates: Vec<String> = bin_dates.dates.iter().map(|d| d.to_string()).collect();
+            let cadence = infer_cadence(bin_dates).map(|inferred| {
+                json!({
+                    "cadence": inferred.cadence.as_str(),
+                    "anchor": inferred.anchor.to_string(),
+                    "confidence": inferred.confidence.as_str(),
+                })
+            });
+            return json!({ "bin": bin_dates.bin.to_string(), "dates": dates, "cadence": cadence });
+        })
+        .collect();
+    let next_collection = next_collection.and_then(|next| {
+        let date = next.bins.first()?.date;
+        let bins: Vec<String> = next.bins.iter().map(|day| day.bin.to_string()).collect();
+        return Some(json!({ "date": date.to_string(), "bins": bins }));
+    });
+    let projected: Vec<serde_json::Value> = projected
+        .iter()
+        .map(|day| {
+            json!({
+                "date": day.date.to_string(),
+                "bin": day.bin.to_string(),
+                "confidence": day.projected.map(|confidence| confidence.as_str()),
+            })
+        })
+        .collect();
+    let warnings: Vec<String> = report.warnings.iter().map(|w| w.to_string()).collect();
+    return json!({
+        "bins": bins,
+        "next_collection": next_collection,
+        "projected": projected,
+        "warnings": warnings,
+        "fingerprint": report.fingerprint,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bin_stuff::{Bin, BinDates};
+
+    fn args(args: &[&str]) -> Result<Args, Error> {
+        return parse_args(args.iter().map(|arg| arg.to_string()));
+    }
+
+    #[test]
+    fn options_can_go_anywhere() {
+        assert_eq!(
+            args(&["--json", "ML6 0AA", "--save", "out", "1 Main Street"]).unwrap(),
+            Args {
+                json: true,
+                save: Some(PathBuf::from("out")),
+                postcode: "ML6 0AA".to_string(),
+                address: "1 Main Street".to_string(),
+                ..Args::default()
+            }
+        );
+        assert!(args(&["ML6 0AA"]).is_err());
+        assert!(args(&["ML6 0AA", "1 Main Street", "--driver"]).is_err());
+        assert!(args(&["--verbose", "ML6 0AA", "1 Main Street"]).is_err());
+    }
+
+    #[test]
+    fn prints_the_next_collection() {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        let report = ScrapeReport {
+            bins: vec![
+                BinDates {
+                    bin: Bin::Black,
+                    dates: vec![date(8), date(22)],
+                },
+                BinDates {
+                    bin: Bin::Blue,
+                    dates: vec![date(8)],
+                },
+            ],
+            warnings: Vec::new(),
+            fingerprint: None,
+        };
+        let next_collection = next_collection_on_or_after(&report.bins, date(2));
+        let projected = projected_collections(&report, date(2));
+        assert_eq!(
+            to_table(&report, next_collection.as_ref(), &projected),
+            "Bin    Dates
+Black  Mon 08 Jan 2024, Mon 22 Jan 2024
+Blue   Mon 08 Jan 2024
+
+Next collection: Mon 08 Jan 2024 - Black, Blue
+Projected: Mon 05 Feb 2024 - Black (low confidence)
+Projected: Mon 19 Feb 2024 - Black (low confidence)
+Projected: Mon 04 Mar 2024 - Black (low confidence)
+Projected: Mon 18 Mar 2024 - Black (low confidence)
+"
+        );
+        let json = to_json(&report, next_collection.as_ref(), &projected);
+        assert_eq!(
+            json["next_collection"],
+            json!({ "date": "2024-01-08", "bins": ["Black", "Blue"] })
+        );
+        assert_eq!(
+            json["bins"][0]["cadence"],
+            json!({ "cadence": "fortnightly", "anchor": "2024-01-22", "confidence": "low" })
+        );
+        assert_eq!(json["bins"][1]["cadence"], json!(null));
+        assert_eq!(
+            json["projected"][0],
+            json!({ "date": "2024-02-05", "bin": "Black", "confidence": "low" })
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Which browser to scrape with, and how it's set up. Firefox is driven through geckodriver and
+//! Chrome or Chromium through chromedriver, with the same steps either way.
+
+use anyhow::{anyhow, Error};
+use fantoccini::wd::Capabilities;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Browser {
+    #[default]
+    Firefox,
+    /// Chrome or Chromium
+    Chrome,
+}
+
+impl Browser {
+    /// Where its driver listens unless told otherwise
+    pub fn default_driver_url(&self) -> &'static str {
+        return match self {
+            Browser::Firefox => "http://127.0.0.1:4444",
+            Browser::Chrome => "http://127.0.0.1:9515",
+        };
+    }
+}
+
+impl std::str::FromStr for Browser {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s.trim().to_lowercase().as_str() {
+            "firefox" => Ok(Browser::Firefox),
+            "chrome" | "chromium" => Ok(Browser::Chrome),
+            other => Err(anyhow!("Expected firefox or chrome, got {}", other)),
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrowserConfig {
+    pub browser: Browser,
+    pub headless: bool,
+    /// The browser to run, if the driver can't find it itself
+    pub binary: Option<String>,
+    /// Width and height. Left to the browser if not set
+    pub window_size: Option<(u32, u32)>,
+    pub user_agent: Option<String>,
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        return BrowserConfig {
+            browser: Browser::default(),
+            headless: true,
+            binary: None,
+            window_size: None,
+            user_agent: None,
+        };
+    }
+}
+
+impl BrowserConfig {
+    /// From BROWSER, BROWSER_HEADLESS, BROWSER_BINARY, BROWSER_WINDOW_SIZE and
+    /// BROWSER_USER_AGENT, with the defaults for any that aren't set. The user agent defaults to
+    /// an honest one, with SCRAPE_CONTACT in it if that's set, and an empty BROWSER_USER_AGENT
+    /// leaves the browser's own
+    pub fn from_env() -> Result<BrowserConfig, Error> {
+        let browser = match std::env::var("BROWSER") {
+            Ok(browser) => browser
+                .parse()
+                .map_err(|e| anyhow!("BROWSER must be firefox or chrome: {}", e))?,
+            Err(_) => Browser::Firefox,
+        };
+        let window_size = match std::env::var("BROWSER_WINDOW_SIZE") {
+            Ok(size) => Some(
+                parse_window_size(&size)
+                    .map_err(|e| anyhow!("BROWSER_WINDOW_SIZE must be like 1280x1024: {}", e))?,
+            ),
+            Err(_) => None,
+        };
+        return Ok(BrowserConfig {
+            browser,
+            headless: std::env::var("BROWSER_HEADLESS")
+                .map_or(true, |headless| headless != "false"),
+            binary: std::env::var("BROWSER_BINARY").ok(),
+            window_size,
+            user_agent: match std::env::var("BROWSER_USER_AGENT") {
+                Ok(user_agent) if user_agent.trim().is_empty() => None,
+                Ok(user_agent) => Some(user_agent),
+                Err(_) => Some(honest_user_agent(
+                    std::env::var("SCRAPE_CONTACT").ok().as_deref(),
+                )),
+            },
+        });
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        let mut capabilities = Capabilities::new();
+        match self.browser {
+            Browser::Firefox => {
+                let mut args = Vec::new();
+                if self.headless {
+                    args.push("--headless".to_string());
+                }
+                if let Some((width, height)) = self.window_size {
+                    args.push(format!("--width={}", width));
+                    args.push(format!("--height={}", height));
+                }
+                let mut options = serde_json::json!({ "args": args });
+                if let Some(binary) = &self.binary {
+                    options["binary"] = binary.as_str().into();
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    options["prefs"] =
+                        serde_json::json!({ "general.useragent.override": user_agent });
+                }
+                capabilities.insert("moz:firefoxOptions".to_string(), options);
+            }
+            Browser::Chrome => {
+                // Chrome won't start as root without --no-sandbox, and /dev/shm is tiny in
+                // containers
+                let mut args = vec![
+                    "--no-sandbox".to_string(),
+                    "--disable-dev-shm-usage".to_string(),
+                ];
+                if self.headless {
+                    args.push("--headless=new".to_string());
+                }
+                if let Some((width, height)) = self.window_size {
+                    args.push(format!("--window-size={},{}", width, height));
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    args.push(format!("--user-agent={}", user_agent));
+                }
+                let mut options = serde_json::json!({ "args": args });
+                if let Some(binary) = &self.binary {
+                    options["binary"] = binary.as_str().into();
+                }
+                capabilities.insert("goog:chromeOptions".to_string(), options);
+            }
+        }
+        return capabilities;
+    }
+}
+
+/// Says what's scraping and, with `contact`, how to get in touch, in the form crawlers use. Sites
+/// that only work with a real browser's user agent need BROWSER_USER_AGENT set instead
+pub fn honest_user_agent(contact: Option<&str>) -> String {
+    let name = format!("what-bin-is-it/{}", env!("CARGO_PKG_VERSION"));
+    return match contact {
+        Some(contact) => format!("Mozilla/5.0 (compatible; {}; +{})", name, contact),
+        None => format!("Mozilla/5.0 (compatible; {})", name),
+    };
+}
+
+/// i.e "1280x1024"
+pub fn parse_window_size(s: &str) -> Result<(u32, u32), Error> {
+    let (width, height) = s
+        .trim()
+        .split_once('x')
+        .ok_or_else(|| anyhow!("Expected WIDTHxHEIGHT, got {}", s))?;
+    return Ok((width.trim().parse()?, height.trim().parse()?));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_are_for_the_chosen_browser() {
+        let config = BrowserConfig {
+            browser: "chromium".parse().unwrap(),
+            headless: true,
+            binary: Some("/usr/bin/chromium".to_string()),
+            window_size: Some(parse_window_size("1280x1024").unwrap()),
+            user_agent: Some("what-bin-is-it".to_string()),
+        };
+        let capabilities = config.capabilities();
+        assert!(!capabilities.contains_key("moz:firefoxOptions"));
+        let options = &capabilities["goog:chromeOptions"];
+        assert_eq!(options["binary"], "/usr/bin/chromium");
+        let args: Vec<&str> = options["args"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|arg| arg.as_str().unwrap())
+            .collect();
+        assert!(args.contains(&"--headless=new"));
+        assert!(args.contains(&"--window-size=1280,1024"));
+        assert!(args.contains(&"--user-agent=what-bin-is-it"));
+
+        let firefox = BrowserConfig {
+            user_agent: Some("what-bin-is-it".to_string()),
+            ..BrowserConfig::default()
+        }
+        .capabilities();
+        assert_eq!(
+            firefox["moz:firefoxOptions"]["prefs"]["general.useragent.override"],
+            "what-bin-is-it"
+        );
+        assert!(parse_window_size("1280").is_err());
+        assert_eq!(
+            honest_user_agent(Some("https://bins.example.com")),
+            "Mozilla/5.0 (compatible; what-bin-is-it/0.1.0; +https://bins.example.com)"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+use fantoccini::error::{CmdError, NewSessionError};
+
+/// Why a scrape failed. The first two are the user's address being wrong, the rest are the
+/// council site or the browser
+#[derive(Debug)]
+pub enum ScrapeError {
+    PostcodeNotRecognised(String),
+    AddressNotInList {
+        address: String,
+        /// What the site offered for the postcode instead
+        available: Vec<String>,
+    },
+    CookieBannerMissing,
+    /// An element never turned up, which usually means the site's layout has changed
+    SelectorMissing {
+        /// What the element is, i.e "postcode input"
+        name: String,
+        selector: String,
+    },
+    DateParse(String),
+    WebDriverConnection(NewSessionError),
+    /// Anything else the browser had a problem with
+    Browser(CmdError),
+}
+
+impl ScrapeError {
+    /// Whether the user needs to fix their address, rather than anything being broken
+    pub fn is_address_problem(&self) -> bool {
+        return matches!(
+            self,
+            ScrapeError::PostcodeNotRecognised(_) | ScrapeError::AddressNotInList { .. }
+        );
+    }
+
+    /// Trying again straight away could work, i.e the page was slow
+    pub fn is_retryable(&self) -> bool {
+        return matches!(
+            self,
+            ScrapeError::SelectorMissing { .. }
+                | ScrapeError::CookieBannerMissing
+                | ScrapeError::Browser(_)
+        );
+    }
+
+    /// The browser can't be reached, so nothing else will work until it's back
+    pub fn is_browser_gone(&self) -> bool {
+        return matches!(
+            self,
+            ScrapeError::WebDriverConnection(_)
+                | ScrapeError::Browser(CmdError::Lost(_) | CmdError::Failed(_))
+        );
+    }
+
+    /// A short description without anything that changes between runs, for grouping alerts
+    pub fn kind(&self) -> String {
+        return match self {
+            ScrapeError::PostcodeNotRecognised(_) => "postcode not recognised".to_string(),
+            ScrapeError::AddressNotInList { .. } => "address not in list".to_string(),
+            ScrapeError::CookieBannerMissing => "cookie banner missing".to_string(),
+            ScrapeError::SelectorMissing { name, .. } => format!("{} missing", name),
+            ScrapeError::DateParse(_) => "date parse".to_string(),
+            _ if self.is_browser_gone() => "webdriver unreachable".to_string(),
+            ScrapeError::WebDriverConnection(_) | ScrapeError::Browser(_) => {
+                "webdriver".to_string()
+            }
+        };
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ScrapeError::PostcodeNotRecognised(postcode) => {
+                write!(f, "The council site doesn't recognise {}", postcode)
+            }
+            ScrapeError::AddressNotInList { address, available } => write!(
+                f,
+                "\"{}\" isn't one of the addresses for the postcode: {}",
+                address,
+                available.join("; ")
+            ),
+            ScrapeError::CookieBannerMissing => write!(f, "The cookie banner never appeared"),
+            ScrapeError::SelectorMissing { name, selector } => {
+                write!(f, "Could not find the {} ({})", name, selector)
+            }
+            ScrapeError::DateParse(text) => write!(f, "Could not read a date from \"{}\"", text),
+            ScrapeError::WebDriverConnection(e) => write!(f, "Could not start a browser: {}", e),
+            ScrapeError::Browser(e) => write!(f, "Browser error: {}", e),
+        };
+    }
+}
+
+impl std::error::Error for ScrapeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            ScrapeError::WebDriverConnection(e) => Some(e),
+            ScrapeError::Browser(e) => Some(e),
+            _ => None,
+        };
+    }
+}
+
+impl From<CmdError> for ScrapeError {
+    fn from(e: CmdError) -> Self {
+        return ScrapeError::Browser(e);
+    }
+}
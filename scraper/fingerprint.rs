@@ -0,0 +1,163 @@
+//! The shape of the dates page, rather than its contents: which sections it has, how many dates
+//! are in each, and the IDs of its form fields. A redesign shows up as a change in shape even
+//! when the scrape itself still works, i.e a bin's section being there but empty.
+
+use anyhow::Error;
+use fantoccini::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::flow::ScrapeFlow;
+
+/// Arguments are the sections' selector, their class prefix, and the dates' selector
+const SCRIPT: &str = "
+const [sectionSelector, classPrefix, dateSelector] = arguments;
+const sections = [...document.querySelectorAll(sectionSelector)].map(section => ({
+    class: [...section.classList].find(c => c.startsWith(classPrefix)) || section.className,
+    dates: section.querySelectorAll(dateSelector).length,
+}));
+const fields = document.querySelectorAll(
+    'form input[id], form select[id], form button[id], form textarea[id]'
+);
+return { sections, form_fields: [...fields].map(field => field.id) };
+";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageFingerprint {
+    /// In page order
+    pub sections: Vec<SectionShape>,
+    /// Sorted and without repeats
+    pub form_fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SectionShape {
+    /// i.e "waste-type--general-waste"
+    pub class: String,
+    pub dates: usize,
+}
+
+impl PageFingerprint {
+    /// Takes the fingerprint of the page the browser is on, if the flow says what its sections
+    /// look like
+    pub(crate) async fn take(
+        client: &Client,
+        flow: &ScrapeFlow,
+    ) -> Result<Option<PageFingerprint>, Error> {
+        let Some(layout) = &flow.layout else {
+            return Ok(None);
+        };
+        let page = client
+            .execute(
+                SCRIPT,
+                vec![
+                    layout.sections.clone().into(),
+                    layout.section_class_prefix.clone().into(),
+                    flow.dates.selector.clone().into(),
+                ],
+            )
+            .await?;
+        let fingerprint: PageFingerprint = serde_json::from_value(page)?;
+        return Ok(Some(fingerprint.normalised()));
+    }
+
+    /// Drupal gives fields a new "--" suffix on every request, i.e "edit-submit--Xk2", so only
+    /// what's before it is kept
+    fn normalised(mut self) -> PageFingerprint {
+        for field in self.form_fields.iter_mut() {
+            if let Some((id, _)) = field.split_once("--") {
+                *field = id.to_string();
+            }
+        }
+        self.form_fields.sort();
+        self.form_fields.dedup();
+        return self;
+    }
+
+    /// What's different about this page compared to `known_good`. How many dates a section has
+    /// changes from week to week, so it only counts when a section goes from having some to
+    /// having none or back
+    pub fn changes_from(&self, known_good: &PageFingerprint) -> Vec<String> {
+        let mut changes = Vec::new();
+        for section in &known_good.sections {
+            match self.sections.iter().find(|s| s.class == section.class) {
+                None => changes.push(format!("the {} section is gone", section.class)),
+                Some(now) if section.dates > 0 && now.dates == 0 => {
+                    changes.push(format!("the {} section has no dates", section.class))
+                }
+                Some(now) if section.dates == 0 && now.dates > 0 => {
+                    changes.push(format!("the {} section has dates again", section.class))
+                }
+                Some(_) => {}
+            }
+        }
+        for section in &self.sections {
+            if !known_good.sections.iter().any(|s| s.class == section.class) {
+                changes.push(format!("there's a new {} section", section.class));
+            }
+        }
+        for field in &known_good.form_fields {
+            if !self.form_fields.contains(field) {
+                changes.push(format!("the {} form field is gone", field));
+            }
+        }
+        for field in &self.form_fields {
+            if !known_good.form_fields.contains(field) {
+                changes.push(format!("there's a new {} form field", field));
+            }
+        }
+        return changes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(class: &str, dates: usize) -> SectionShape {
+        return SectionShape {
+            class: class.to_string(),
+            dates,
+        };
+    }
+
+    #[test]
+    fn only_changes_in_shape_count() {
+        let known_good = PageFingerprint {
+            sections: vec![
+                section("waste-type--general-waste", 3),
+                section("waste-type--food-and-garden", 2),
+            ],
+            form_fields: vec!["edit-submit--a1".to_string(), "edit-postcode".to_string()],
+        }
+        .normalised();
+        assert_eq!(known_good.form_fields, vec!["edit-postcode", "edit-submit"]);
+
+        // Fewer dates this week, and the form's been given new suffixes
+        let same_shape = PageFingerprint {
+            sections: vec![
+                section("waste-type--general-waste", 2),
+                section("waste-type--food-and-garden", 4),
+            ],
+            form_fields: vec!["edit-postcode".to_string(), "edit-submit--b2".to_string()],
+        }
+        .normalised();
+        assert!(same_shape.changes_from(&known_good).is_empty());
+
+        let redesigned = PageFingerprint {
+            sections: vec![
+                section("waste-type--general-waste", 0),
+                section("waste-type--garden", 2),
+            ],
+            form_fields: vec!["edit-postcode".to_string()],
+        };
+        assert_eq!(
+            redesigned.changes_from(&known_good),
+            vec![
+                "the waste-type--general-waste section has no dates",
+                "the waste-type--food-and-garden section is gone",
+                "there's a new waste-type--garden section",
+                "the edit-submit form field is gone",
+            ]
+        );
+    }
+}
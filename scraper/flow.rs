@@ -0,0 +1,256 @@
+//! What to do on the council site to get an address's bin dates, described in TOML so a change
+//! to the site is a change to the file rather than a new build. The North Lanarkshire flow is
+//! built in.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Error};
+use bin_stuff::Bin;
+use serde::{Deserialize, Deserializer};
+
+use crate::steps::Ready;
+
+const BUILT_IN: &str = include_str!("flows/north_lanarkshire.toml");
+
+/// The step for loading `url`, which every flow starts with
+pub const VISIT_STEP: &str = "visit bins page";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScrapeFlow {
+    pub url: String,
+    /// Where the site says it doesn't know the postcode, checked when the address list doesn't
+    /// turn up
+    pub postcode_error: Option<String>,
+    pub actions: Vec<FlowAction>,
+    pub dates: DatesConfig,
+    pub bins: Vec<BinSection>,
+    /// Without it, the dates page isn't fingerprinted
+    pub layout: Option<LayoutConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowAction {
+    /// i.e "enter postcode"
+    pub step: String,
+    pub action: Action,
+    /// What the element is, for errors, i.e "postcode input"
+    pub element: String,
+    /// CSS
+    pub selector: String,
+    pub ready: Ready,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Just wait for the element, i.e for the next page to load
+    Wait,
+    Click,
+    /// Click, and if it never shows up it's the cookie banner that's missing
+    AcceptCookies,
+    /// Click and type the postcode
+    EnterPostcode,
+    /// Pick the address from a select, which is only there if the postcode was recognised
+    SelectAddress,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatesConfig {
+    pub step: String,
+    /// Each date, within a bin's section
+    pub selector: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BinSection {
+    #[serde(deserialize_with = "deserialize_bin")]
+    pub bin: Bin,
+    pub element: String,
+    pub selector: String,
+}
+
+/// What the dates page's sections look like, for fingerprinting it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutConfig {
+    /// Every section, including ones for bins the flow doesn't know about
+    pub sections: String,
+    /// The class that says which section it is, i.e "waste-type--"
+    pub section_class_prefix: String,
+}
+
+fn deserialize_bin<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bin, D::Error> {
+    let bin = String::deserialize(deserializer)?;
+    return bin.parse().map_err(serde::de::Error::custom);
+}
+
+impl Default for ScrapeFlow {
+    fn default() -> Self {
+        return BUILT_IN.parse().expect("The built in flow is valid");
+    }
+}
+
+impl std::str::FromStr for ScrapeFlow {
+    type Err = Error;
+
+    /// Parses and validates the flow
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let flow: ScrapeFlow = toml::from_str(s)?;
+        flow.validate()?;
+        return Ok(flow);
+    }
+}
+
+impl ScrapeFlow {
+    pub fn load(path: &Path) -> Result<ScrapeFlow, Error> {
+        let flow = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        return flow
+            .parse()
+            .map_err(|e| anyhow!("Invalid flow in {}: {}", path.display(), e));
+    }
+
+    /// Catches what would otherwise only show up as a failed scrape. Every problem is listed,
+    /// so a broken file can be fixed in one go
+    fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+        if !(self.url.starts_with("https://") || self.url.starts_with("http://")) {
+            problems.push(format!("url \"{}\" isn't http or https", self.url));
+        }
+
+        for (i, action) in self.actions.iter().enumerate() {
+            let number = i + 1;
+            if action.step.trim().is_empty() {
+                problems.push(format!("action {} has no step", number));
+            }
+            if action.element.trim().is_empty() {
+                problems.push(format!("action {} has no element", number));
+            }
+            if action.selector.trim().is_empty() {
+                problems.push(format!("action {} has no selector", number));
+            }
+        }
+        let position = |wanted: Action| self.actions.iter().position(|a| a.action == wanted);
+        for (wanted, name) in [
+            (Action::EnterPostcode, "enter_postcode"),
+            (Action::SelectAddress, "select_address"),
+        ] {
+            let count = self.actions.iter().filter(|a| a.action == wanted).count();
+            if count != 1 {
+                problems.push(format!("expected one {} action, found {}", name, count));
+            }
+        }
+        if let (Some(enter), Some(select)) = (
+            position(Action::EnterPostcode),
+            position(Action::SelectAddress),
+        ) {
+            if select < enter {
+                problems.push("select_address comes before enter_postcode".to_string());
+            }
+        }
+
+        if self.dates.step.trim().is_empty() {
+            problems.push("dates has no step".to_string());
+        }
+        if self.dates.selector.trim().is_empty() {
+            problems.push("dates has no selector".to_string());
+        }
+        if self.bins.is_empty() {
+            problems.push("no bins".to_string());
+        }
+        for (i, section) in self.bins.iter().enumerate() {
+            if section.selector.trim().is_empty() {
+                problems.push(format!("{} bin has no selector", section.bin));
+            }
+            if self.bins[..i].iter().any(|other| other.bin == section.bin) {
+                problems.push(format!("{} bin is listed twice", section.bin));
+            }
+        }
+
+        if let Some(layout) = &self.layout {
+            if layout.sections.trim().is_empty() {
+                problems.push("layout has no sections".to_string());
+            }
+            if layout.section_class_prefix.trim().is_empty() {
+                problems.push("layout has no section_class_prefix".to_string());
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        return Err(anyhow!(problems.join("; ")));
+    }
+
+    /// Every step, in order, for checking timeouts are set for ones that exist
+    pub fn step_names(&self) -> Vec<&str> {
+        let mut names = vec![VISIT_STEP];
+        for action in &self.actions {
+            if names.last() != Some(&action.step.as_str()) {
+                names.push(&action.step);
+            }
+        }
+        names.push(&self.dates.step);
+        return names;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_flow_is_valid() {
+        let flow = ScrapeFlow::default();
+        assert_eq!(
+            flow.step_names(),
+            vec![
+                "visit bins page",
+                "accept cookies",
+                "enter postcode",
+                "find address",
+                "select address",
+                "confirm address",
+                "go to dates page",
+                "read bin dates"
+            ]
+        );
+        assert_eq!(flow.bins.len(), 4);
+        assert!(flow.layout.is_some());
+    }
+
+    #[test]
+    fn invalid_flows_list_every_problem() {
+        let flow = r#"
+            url = "www.example.com"
+            actions = [
+                { step = "find", action = "select_address", element = "list", selector = "select", ready = "options_loaded" },
+                { step = "enter", action = "enter_postcode", element = "input", selector = "", ready = "enabled" },
+            ]
+            dates = { step = "read", selector = "p" }
+            bins = [
+                { bin = "Black", element = "black", selector = ".black" },
+                { bin = "Black", element = "black", selector = ".black" },
+            ]
+        "#;
+        let err = flow.parse::<ScrapeFlow>().unwrap_err().to_string();
+        assert!(err.contains("isn't http or https"), "{}", err);
+        assert!(err.contains("action 2 has no selector"), "{}", err);
+        assert!(
+            err.contains("select_address comes before enter_postcode"),
+            "{}",
+            err
+        );
+        assert!(err.contains("Black bin is listed twice"), "{}", err);
+
+        // Typos are caught rather than ignored
+        let typo = BUILT_IN.replace("ready = \"visible\"", "ready = \"visable\"");
+        assert!(typo.parse::<ScrapeFlow>().is_err());
+        let unknown_field = BUILT_IN.replace("[dates]", "[dates]\nselecter = \"p\"");
+        assert!(unknown_field.parse::<ScrapeFlow>().is_err());
+    }
+}
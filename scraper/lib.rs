@@ -1,48 +1,200 @@
+#![allow(clippy::needless_return)]
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
 use anyhow::Error;
 
 use chrono::NaiveDate;
 use fantoccini::elements::Element;
-use fantoccini::wd::Capabilities;
-use fantoccini::{Client, ClientBuilder, Locator};
+use fantoccini::error::CmdError;
+use fantoccini::{Client, Locator};
 
+use bin_stuff::dates::{parse_bin_date, DateProblem};
 use bin_stuff::{Bin, BinDates};
-use log::{error, info};
+use log::{error, info, warn};
 
-pub async fn get_stuff(
-    postcode: &str,
-    address: &str,
-    driver_url: Option<String>,
-) -> Result<Vec<BinDates>, Error> {
-    let mut capabilities = Capabilities::new();
-    let options = serde_json::json!({ "args": ["--headless"] });
-    capabilities.insert("moz:firefoxOptions".to_string(), options);
-
-    info!("Attempting to connect to webdriver client");
-    let default_driver_url = "http://127.0.0.1:4444".to_string();
-    if driver_url.is_none() {
-        info!(
-            "No driver_url provided. Defaulting to {}",
-            default_driver_url
+mod browser;
+mod error;
+mod fingerprint;
+mod flow;
+mod politeness;
+mod pool;
+mod session;
+mod steps;
+
+pub use browser::{honest_user_agent, parse_window_size, Browser, BrowserConfig};
+pub use error::ScrapeError;
+pub use fingerprint::{PageFingerprint, SectionShape};
+use flow::VISIT_STEP;
+pub use flow::{Action, ScrapeFlow};
+pub use politeness::{Politeness, RateLimiter};
+pub use pool::ScrapePool;
+pub use session::ScrapeSession;
+use steps::Steps;
+pub use steps::{Ready, StepTimeouts};
+
+/// What a successful scrape found
+#[derive(Debug)]
+pub struct ScrapeReport {
+    pub bins: Vec<BinDates>,
+    /// Dates that were skipped because they couldn't be read
+    pub warnings: Vec<DateWarning>,
+    /// The dates page's shape, if the flow has a layout and the browser could give it
+    pub fingerprint: Option<PageFingerprint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateWarning {
+    pub bin: Bin,
+    pub text: String,
+    pub problem: DateProblem,
+}
+
+impl fmt::Display for DateWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "Skipped \"{}\" for the {} bin: {}",
+            self.text, self.bin, self.problem
         );
     }
-    let client = ClientBuilder::native()
-        .capabilities(capabilities)
-        .connect(&driver_url.unwrap_or("http://127.0.0.1:4444".to_string()))
-        .await?;
-    info!("Got webdriver client");
+}
+
+/// What the browser was showing
+#[derive(Debug)]
+pub struct PageCapture {
+    pub url: Option<String>,
+    /// PNG
+    pub screenshot: Option<Vec<u8>>,
+    pub page_source: Option<String>,
+}
+
+impl PageCapture {
+    /// Anything the browser can't give us is left out rather than failing
+    async fn take(client: &Client) -> PageCapture {
+        return PageCapture {
+            url: client.current_url().await.ok().map(|url| url.to_string()),
+            screenshot: client.screenshot().await.ok(),
+            page_source: client.source().await.ok(),
+        };
+    }
+
+    /// Writes the screenshot and HTML to a new directory in `dir`, named after when it was
+    /// saved, and returns its path
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, Error> {
+        let dir = dir.join(format!(
+            "{}-results",
+            chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+        ));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("url.txt"),
+            format!("{}\n", self.url.as_deref().unwrap_or("unknown")),
+        )?;
+        if let Some(screenshot) = &self.screenshot {
+            std::fs::write(dir.join("screenshot.png"), screenshot)?;
+        }
+        if let Some(page_source) = &self.page_source {
+            std::fs::write(dir.join("page.html"), page_source)?;
+        }
+        return Ok(dir);
+    }
+}
+
+/// What the scraper was doing, and what the page looked like, when it gave up. Anything that
+/// couldn't be captured from the browser is left out
+#[derive(Debug)]
+pub struct ScrapeFailure {
+    /// i.e "enter postcode"
+    pub step: String,
+    pub url: Option<String>,
+    /// PNG
+    pub screenshot: Option<Vec<u8>>,
+    pub page_source: Option<String>,
+    pub error: ScrapeError,
+}
+
+impl fmt::Display for ScrapeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "Scraping failed at \"{}\": {}", self.step, self.error);
+    }
+}
+
+impl std::error::Error for ScrapeFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return Some(&self.error);
+    }
+}
+
+impl ScrapeFailure {
+    async fn capture(client: &Client, step: String, error: ScrapeError) -> ScrapeFailure {
+        let page = PageCapture::take(client).await;
+        return ScrapeFailure {
+            step,
+            url: page.url,
+            screenshot: page.screenshot,
+            page_source: page.page_source,
+            error,
+        };
+    }
+
+    /// Writes everything captured to a new directory in `failures_dir`, named after when it
+    /// happened and the step, and returns its path
+    pub fn save(&self, failures_dir: &Path) -> Result<PathBuf, Error> {
+        let dir = failures_dir.join(format!(
+            "{}-{}",
+            chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+            self.step.replace(' ', "-")
+        ));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("error.txt"),
+            format!(
+                "Step: {}\nURL: {}\nError: {:#}\n",
+                self.step,
+                self.url.as_deref().unwrap_or("unknown"),
+                self.error
+            ),
+        )?;
+        if let Some(screenshot) = &self.screenshot {
+            std::fs::write(dir.join("screenshot.png"), screenshot)?;
+        }
+        if let Some(page_source) = &self.page_source {
+            std::fs::write(dir.join("page.html"), page_source)?;
+        }
+        return Ok(dir);
+    }
+}
 
+/// Each retry waits on `limiter` like any other lookup, as it submits the form again
+async fn scrape_bin_dates(
+    client: &Client,
+    flow: &ScrapeFlow,
+    postcode: &str,
+    address: &str,
+    steps: &mut Steps<'_>,
+    limiter: Option<&RateLimiter>,
+) -> Result<ScrapeReport, ScrapeError> {
     // NOTE: Some of the fields get different IDs when submitting each step it seems
     let max_attempts = 3;
     let mut attempts = 0;
     while attempts < max_attempts {
+        if let (true, Some(limiter)) = (attempts > 0, limiter) {
+            limiter.wait().await;
+        }
         info!("Visiting bin page");
         info!("Attempt {}/{}", attempts, max_attempts);
-        match fill_out_address_form(&client, postcode, address).await {
+        match fill_out_address_form(client, flow, postcode, address, steps).await {
             Ok(_) => break,
             Err(e) => {
                 attempts += 1;
                 error!("{}", e);
 
+                // No point asking again about an address that doesn't exist
+                if !e.is_retryable() {
+                    return Err(e);
+                }
                 if attempts == max_attempts {
                     error!("Reached max attempt limit");
                     return Err(e);
@@ -51,142 +203,119 @@ pub async fn get_stuff(
         }
     }
 
-    let black_bins_div = client
-        .find(Locator::Css(".waste-type--general-waste"))
-        .await?;
-
-    let blue_bins_div = client
-        .find(Locator::Css(".waste-type--blue-lidded-recycling-bin"))
-        .await?;
-    let brown_bins_div = client
-        .find(Locator::Css(".waste-type--food-and-garden"))
-        .await?;
-    let green_bins_div = client
-        .find(Locator::Css(
-            ".waste-type--glass-metals-plastics-and-cartons",
-        ))
-        .await?;
-    let black_bin_date_elements = black_bins_div.find_all(Locator::Css("p")).await?;
-    let blue_bin_date_elements = blue_bins_div.find_all(Locator::Css("p")).await?;
-    let brown_bin_date_elements = brown_bins_div.find_all(Locator::Css("p")).await?;
-    let green_bin_date_elements = green_bins_div.find_all(Locator::Css("p")).await?;
-
-    // TODO - Clean this up
-
-    let black_bin_dates = get_bin_dates_from_elements(&black_bin_date_elements).await?;
-    let blue_bin_dates = get_bin_dates_from_elements(&blue_bin_date_elements).await?;
-    let brown_bin_dates = get_bin_dates_from_elements(&brown_bin_date_elements).await?;
-    let green_bin_dates = get_bin_dates_from_elements(&green_bin_date_elements).await?;
-
-    let parsed_black_bin_dates = parse_bin_dates(&black_bin_dates);
-    let black_bins = BinDates {
-        bin: Bin::Black,
-        dates: parsed_black_bin_dates,
-    };
-    let parsed_blue_bin_dates = parse_bin_dates(&blue_bin_dates);
-    let blue_bins = BinDates {
-        bin: Bin::Blue,
-        dates: parsed_blue_bin_dates,
-    };
-    let parsed_brown_bin_dates = parse_bin_dates(&brown_bin_dates);
-    let brown_bins = BinDates {
-        bin: Bin::Brown,
-        dates: parsed_brown_bin_dates,
-    };
-    let parsed_green_bin_dates = parse_bin_dates(&green_bin_dates);
-    let green_bins = BinDates {
-        bin: Bin::Green,
-        dates: parsed_green_bin_dates,
-    };
+    steps.start(&flow.dates.step);
+    let today = chrono::Utc::now().date_naive();
+    let mut bins = Vec::new();
+    let mut warnings = Vec::new();
+    for section in &flow.bins {
+        let bin_div = steps
+            .wait_for(
+                &section.element,
+                Locator::Css(&section.selector),
+                Ready::Present,
+            )
+            .await?;
+        let date_elements = bin_div.find_all(Locator::Css(&flow.dates.selector)).await?;
+        let bin_dates = get_bin_dates_from_elements(&date_elements).await?;
+        let (dates, mut skipped) = parse_bin_dates(section.bin, &bin_dates, today)?;
+        for warning in &skipped {
+            warn!("{}", warning);
+        }
+        warnings.append(&mut skipped);
+        bins.push(BinDates {
+            bin: section.bin,
+            dates,
+        });
+    }
+    steps.finish();
 
-    let bins = vec![black_bins, blue_bins, brown_bins, green_bins];
+    // Only for spotting a redesign, so not worth failing the scrape over
+    let fingerprint = match PageFingerprint::take(client, flow).await {
+        Ok(fingerprint) => fingerprint,
+        Err(e) => {
+            warn!("Could not fingerprint the dates page: {}", e);
+            None
+        }
+    };
 
-    return Ok(bins);
+    return Ok(ScrapeReport {
+        bins,
+        warnings,
+        fingerprint,
+    });
 }
 
 async fn fill_out_address_form(
     client: &Client,
+    flow: &ScrapeFlow,
     postcode: &str,
     address: &str,
-) -> Result<(), Error> {
-    let bins_url = "https://www.northlanarkshire.gov.uk/bin-collection-dates";
-
-    let postcode_input_id = "address-finder-postcode-search-text";
-
-    let find_address_input_id = "address_finder-postcode-search-button";
-
-    let confirm_button_id = "address_finder_confirm_address_selection";
-
-    let next_button_name = "op";
-
-    info!("Going to site");
-    client.goto(bins_url).await?;
-
-    info!("Waiting for cookie confirmation");
-    client
-        .find(Locator::Css(".cb-enable"))
-        .await?
-        .click()
-        .await?;
-    info!("Clicked cookie");
-
-    info!("Little sleep for page load");
-    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-    info!("Waiting for postcode input box");
-    let postcode_input = client.find(Locator::Id(postcode_input_id)).await?;
-
-    postcode_input.click().await?;
-    info!("Clicked postcode input box");
-    // Enter key doesn't submit this form
-    postcode_input.send_keys(postcode).await?;
-    // TODO - Check the input box to make sure a value is selected (or rely on the confirm part
-    // after?)
-
-    info!("Waiting for find address button");
-    client
-        .find(Locator::Id(find_address_input_id))
-        .await?
-        .click()
-        .await?;
-    info!("Submitted find address button");
-    info!("Little sleep for page load");
-    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-
-    info!("Waiting for address drop down");
-    let address_drop_down = client.find(Locator::Css("select.form-select")).await?;
-    address_drop_down.click().await?;
-    info!("Clicked address drop down");
-
-    address_drop_down.send_keys(address).await?;
-    // TODO: Is Enter needed here?
-    info!("Waiting for confirm address button");
-    client
-        .find(Locator::Id(confirm_button_id))
-        .await?
-        .click()
-        .await?;
-    info!("Clicked confirm address button");
-    // Ignoring successful address lookup check
-
-    info!("Waiting for next button");
-    client
-        .find(Locator::Css(&format!("input[name={}]", next_button_name)))
-        .await?
-        .click()
-        .await?;
-    info!("Clicked next button");
-
-    info!("Waiting for next page");
-    client
-        .wait()
-        .for_element(Locator::Css(".bin-collection-dates-container"))
-        .await?;
+    steps: &mut Steps<'_>,
+) -> Result<(), ScrapeError> {
+    steps.start(VISIT_STEP);
+    client.goto(&flow.url).await?;
+
+    for action in &flow.actions {
+        steps.start(&action.step);
+        let waited = steps
+            .wait_for(
+                &action.element,
+                Locator::Css(&action.selector),
+                action.ready,
+            )
+            .await;
+        let element = match (action.action, waited) {
+            (_, Ok(element)) => element,
+            (Action::AcceptCookies, Err(ScrapeError::SelectorMissing { .. })) => {
+                return Err(ScrapeError::CookieBannerMissing)
+            }
+            (Action::SelectAddress, Err(e @ ScrapeError::SelectorMissing { .. })) => {
+                // The site says so instead of showing any addresses
+                let Some(postcode_error) = &flow.postcode_error else {
+                    return Err(e);
+                };
+                return match client.find(Locator::Css(postcode_error)).await {
+                    Ok(_) => Err(ScrapeError::PostcodeNotRecognised(postcode.to_string())),
+                    Err(CmdError::NoSuchElement(_)) => Err(e),
+                    Err(e) => Err(e.into()),
+                };
+            }
+            (_, Err(e)) => return Err(e),
+        };
+
+        match action.action {
+            Action::Wait => {}
+            Action::Click | Action::AcceptCookies => element.click().await?,
+            Action::EnterPostcode => {
+                element.click().await?;
+                // Enter key doesn't submit this form
+                element.send_keys(postcode).await?;
+            }
+            Action::SelectAddress => {
+                let mut available = Vec::new();
+                for option in element.find_all(Locator::Css("option")).await? {
+                    available.push(option.text().await?.trim().to_string());
+                }
+                let Some(index) = find_address(address, &available) else {
+                    return Err(ScrapeError::AddressNotInList {
+                        address: address.to_string(),
+                        // The first is "Select an address"
+                        available: available.into_iter().skip(1).collect(),
+                    });
+                };
+                // Clicking the option rather than typing into the list, which only Firefox
+                // picks up
+                element.select_by_index(index).await?;
+            }
+        }
+    }
+    steps.finish();
 
     info!("On next page");
     return Ok(());
 }
 
-async fn get_bin_dates_from_elements(elements: &Vec<Element>) -> Result<Vec<String>, Error> {
+async fn get_bin_dates_from_elements(elements: &Vec<Element>) -> Result<Vec<String>, ScrapeError> {
     let mut bin_dates = Vec::new();
     for element in elements {
         bin_dates.push(element.text().await?);
@@ -195,13 +324,76 @@ async fn get_bin_dates_from_elements(elements: &Vec<Element>) -> Result<Vec<Stri
     return Ok(bin_dates);
 }
 
-fn parse_bin_dates(bin_date_strings: &[String]) -> Vec<NaiveDate> {
+/// The odd line that isn't a date is skipped with a warning, but if none of them are the format
+/// has changed
+fn parse_bin_dates(
+    bin: Bin,
+    bin_date_strings: &[String],
+    today: NaiveDate,
+) -> Result<(Vec<NaiveDate>, Vec<DateWarning>), ScrapeError> {
     let mut parsed_dates = Vec::new();
-    for date in bin_date_strings {
-        match chrono::NaiveDate::parse_from_str(date, "%d %B %Y") {
-            Ok(parsed_date) => parsed_dates.push(parsed_date),
-            Err(e) => eprintln!("Error parsing {}: {}", date, e),
+    let mut warnings = Vec::new();
+    for text in bin_date_strings {
+        match parse_bin_date(text, today) {
+            Ok(date) => parsed_dates.push(date),
+            Err(problem) => warnings.push(DateWarning {
+                bin,
+                text: text.clone(),
+                problem,
+            }),
+        }
+    }
+    if parsed_dates.is_empty() {
+        if let Some(date) = bin_date_strings.first() {
+            return Err(ScrapeError::DateParse(date.clone()));
         }
     }
-    return parsed_dates;
+    return Ok((parsed_dates, warnings));
+}
+
+/// The first option starting with the address, like typing it into the list would pick
+fn find_address(address: &str, options: &[String]) -> Option<usize> {
+    let address = address.trim().to_lowercase();
+    return options
+        .iter()
+        .position(|option| option.to_lowercase().starts_with(&address));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_only_fail_when_none_can_be_read() {
+        let today = NaiveDate::from_ymd_opt(2023, 8, 1).unwrap();
+        let dates = vec!["Monday 14th Aug".to_string(), "Bank holiday".to_string()];
+        let (parsed, warnings) = parse_bin_dates(Bin::Black, &dates, today).unwrap();
+        assert_eq!(parsed, vec![NaiveDate::from_ymd_opt(2023, 8, 14).unwrap()]);
+        assert_eq!(
+            warnings,
+            vec![DateWarning {
+                bin: Bin::Black,
+                text: "Bank holiday".to_string(),
+                problem: DateProblem::UnexpectedWord("bank".to_string()),
+            }]
+        );
+        assert!(parse_bin_dates(Bin::Black, &[], today)
+            .unwrap()
+            .0
+            .is_empty());
+        assert!(matches!(
+            parse_bin_dates(Bin::Black, &["Collection suspended".to_string()], today),
+            Err(ScrapeError::DateParse(text)) if text == "Collection suspended"
+        ));
+    }
+
+    #[test]
+    fn addresses_match_the_start_of_an_option() {
+        let options = vec![
+            "Select an address".to_string(),
+            "1 Main Street, Airdrie".to_string(),
+        ];
+        assert_eq!(find_address("1 main street", &options), Some(1));
+        assert_eq!(find_address("10 Main Street", &options), None);
+    }
 }
@@ -0,0 +1,131 @@
+//! Keeps lookups on the council site spread out, however many runs or browsers there are, so a
+//! run looks less like a flood of form submissions all at the same moment.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use log::info;
+use rand::Rng;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Politeness {
+    /// Between the start of one lookup and the next
+    pub min_delay: Duration,
+    /// Up to this much more is added to every wait, at random
+    pub jitter: Duration,
+    /// Lookups in any hour. Unlimited if `None`
+    pub max_per_hour: Option<usize>,
+}
+
+impl Default for Politeness {
+    fn default() -> Self {
+        return Politeness {
+            min_delay: Duration::from_secs(5),
+            jitter: Duration::from_secs(10),
+            max_per_hour: Some(60),
+        };
+    }
+}
+
+/// When lookups were started, most recent last, going back an hour
+#[derive(Debug, Default)]
+struct History {
+    started: VecDeque<Instant>,
+}
+
+impl History {
+    /// The earliest another lookup can start, before any jitter
+    fn next_allowed(&self, politeness: &Politeness, now: Instant) -> Instant {
+        let mut next = now;
+        if let Some(last) = self.started.back() {
+            next = next.max(*last + politeness.min_delay);
+        }
+        if let Some(max_per_hour) = politeness.max_per_hour {
+            let in_last_hour = self.started.iter().filter(|s| **s + HOUR > now).count();
+            if in_last_hour >= max_per_hour.max(1) {
+                // Once the oldest of the last `max_per_hour` is an hour old
+                let oldest = self.started[self.started.len() - max_per_hour.max(1)];
+                next = next.max(oldest + HOUR);
+            }
+        }
+        return next;
+    }
+
+    fn record(&mut self, started: Instant) {
+        self.started.push_back(started);
+        while self
+            .started
+            .front()
+            .is_some_and(|oldest| *oldest + HOUR <= started)
+        {
+            self.started.pop_front();
+        }
+    }
+}
+
+/// Shared by everything that scrapes, so the limits are for the whole server
+#[derive(Debug)]
+pub struct RateLimiter {
+    politeness: Politeness,
+    history: Mutex<History>,
+}
+
+impl RateLimiter {
+    pub fn new(politeness: Politeness) -> RateLimiter {
+        return RateLimiter {
+            politeness,
+            history: Mutex::new(History::default()),
+        };
+    }
+
+    /// Waits until another lookup is allowed. Waiters go one at a time, in the order they asked
+    pub async fn wait(&self) {
+        let mut history = self.history.lock().await;
+        let jitter = match self.politeness.jitter.as_millis() {
+            0 => Duration::ZERO,
+            millis => Duration::from_millis(rand::thread_rng().gen_range(0..=millis as u64)),
+        };
+        let start_at = history.next_allowed(&self.politeness, Instant::now()) + jitter;
+        let wait = start_at.saturating_duration_since(Instant::now());
+        if wait > Duration::from_secs(60) {
+            info!("Waiting {}s before the next lookup", wait.as_secs());
+        }
+        tokio::time::sleep_until(start_at).await;
+        history.record(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_are_spaced_out_and_capped_per_hour() {
+        let politeness = Politeness {
+            min_delay: Duration::from_secs(10),
+            jitter: Duration::ZERO,
+            max_per_hour: Some(3),
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = History::default();
+        assert_eq!(history.next_allowed(&politeness, start), start);
+
+        history.record(at(0));
+        assert_eq!(history.next_allowed(&politeness, at(1)), at(10));
+        // Long enough since the last one
+        assert_eq!(history.next_allowed(&politeness, at(30)), at(30));
+
+        history.record(at(30));
+        history.record(at(40));
+        // Three in the last hour, so not until the first is an hour old
+        assert_eq!(history.next_allowed(&politeness, at(50)), at(3600));
+        history.record(at(3600));
+        assert_eq!(history.next_allowed(&politeness, at(3600)), at(3630));
+        assert_eq!(history.started.len(), 3);
+    }
+}
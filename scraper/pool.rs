@@ -0,0 +1,173 @@
+//! Several browsers, so a run can scrape more than one address at a time
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use fantoccini::error::CmdError;
+use log::{error, info, warn};
+use tokio::sync::{OwnedMutexGuard, Semaphore};
+
+use crate::browser::BrowserConfig;
+use crate::flow::ScrapeFlow;
+use crate::politeness::RateLimiter;
+use crate::steps::StepTimeouts;
+use crate::{ScrapeError, ScrapeFailure, ScrapeReport, ScrapeSession};
+
+/// Scrapes addresses on whichever session is free, waiting for one if they're all busy. A
+/// session whose browser has gone away is dropped from the pool rather than failing every
+/// address after it
+pub struct ScrapePool {
+    idle: Mutex<Vec<ScrapeSession>>,
+    /// One permit per idle session
+    available: Semaphore,
+    open: AtomicUsize,
+    flow: Arc<ScrapeFlow>,
+    /// Shared with any other pools, so they're polite together
+    limiter: Arc<RateLimiter>,
+    /// Released once the pool is closed
+    run_lock: Option<OwnedMutexGuard<()>>,
+}
+
+impl ScrapePool {
+    /// Opens `size` sessions, spread across the drivers in turn. Geckodriver only runs one
+    /// browser at a time, so with Firefox `size` should be no more than the number of drivers.
+    /// Drivers that can't be reached are skipped, and it's only an error if none can be
+    pub async fn open(
+        driver_urls: &[String],
+        size: usize,
+        browser: &BrowserConfig,
+        flow: Arc<ScrapeFlow>,
+        timeouts: &StepTimeouts,
+        limiter: Arc<RateLimiter>,
+    ) -> Result<ScrapePool, ScrapeFailure> {
+        let mut sessions = Vec::new();
+        let mut last_failure = None;
+        for driver_url in driver_urls.iter().cycle().take(size.max(1)) {
+            match ScrapeSession::open(
+                Some(driver_url.clone()),
+                browser,
+                flow.clone(),
+                timeouts.clone(),
+            )
+            .await
+            {
+                Ok(session) => sessions.push(session.limited_by(limiter.clone())),
+                Err(failure) => {
+                    error!("Could not open a session on {}: {}", driver_url, failure);
+                    last_failure = Some(failure);
+                }
+            }
+        }
+        if sessions.is_empty() {
+            return Err(
+                last_failure.unwrap_or_else(|| no_sessions_left("no drivers to connect to"))
+            );
+        }
+        info!("Opened {} scrape session(s)", sessions.len());
+        return Ok(ScrapePool {
+            available: Semaphore::new(sessions.len()),
+            open: AtomicUsize::new(sessions.len()),
+            idle: Mutex::new(sessions),
+            flow,
+            limiter,
+            run_lock: None,
+        });
+    }
+
+    /// Keeps `run_lock` held until the pool is closed, so another run can't start or restart
+    /// the same drivers while this one's using them
+    pub fn holding(mut self, run_lock: OwnedMutexGuard<()>) -> ScrapePool {
+        self.run_lock = Some(run_lock);
+        return self;
+    }
+
+    /// What every session in the pool follows, however the flow's been reloaded since
+    pub fn flow(&self) -> &ScrapeFlow {
+        return &self.flow;
+    }
+
+    /// How many addresses can be scraped at once
+    pub fn size(&self) -> usize {
+        return self.open.load(Ordering::SeqCst);
+    }
+
+    pub async fn scrape(
+        &self,
+        postcode: &str,
+        address: &str,
+    ) -> Result<ScrapeReport, ScrapeFailure> {
+        // Before taking a session, so a browser isn't tied up while waiting
+        self.limiter.wait().await;
+        let Ok(permit) = self.available.acquire().await else {
+            return Err(no_sessions_left("every browser in the pool has gone away"));
+        };
+        let session = self
+            .idle
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("A permit means there's an idle session");
+
+        let result = session.scrape(postcode, address).await;
+        if result.as_ref().is_err_and(|f| f.error.is_browser_gone()) {
+            warn!("Dropping a session whose browser has gone away");
+            permit.forget();
+            if self.open.fetch_sub(1, Ordering::SeqCst) == 1 {
+                // Wakes anything waiting for a session, so it fails instead of waiting forever
+                self.available.close();
+            }
+            session.close().await;
+        } else {
+            self.idle.lock().unwrap().push(session);
+        }
+        return result;
+    }
+
+    pub async fn close(self) {
+        let sessions = self.idle.into_inner().unwrap();
+        for session in sessions {
+            session.close().await;
+        }
+        drop(self.run_lock);
+    }
+}
+
+fn no_sessions_left(reason: &str) -> ScrapeFailure {
+    return ScrapeFailure {
+        step: "connect".to_string(),
+        url: None,
+        screenshot: None,
+        page_source: None,
+        error: ScrapeError::Browser(CmdError::Lost(io::Error::new(
+            io::ErrorKind::NotConnected,
+            reason.to_string(),
+        ))),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::politeness::Politeness;
+
+    #[tokio::test]
+    async fn pool_fails_to_open_only_when_every_driver_is_unreachable() {
+        // Nothing listens on port 1
+        let drivers = vec!["http://127.0.0.1:1".to_string()];
+        let browser = BrowserConfig::default();
+        let flow = Arc::new(ScrapeFlow::default());
+        let timeouts = StepTimeouts::default();
+        let limiter = Arc::new(RateLimiter::new(Politeness::default()));
+        let failure = match ScrapePool::open(&drivers, 2, &browser, flow, &timeouts, limiter).await
+        {
+            Ok(_) => panic!("Opened a pool without a driver"),
+            Err(failure) => failure,
+        };
+        assert_eq!(failure.step, "connect");
+        assert!(failure.error.is_browser_gone());
+        assert!(!failure.error.is_retryable());
+
+        assert!(no_sessions_left("gone").error.is_browser_gone());
+    }
+}
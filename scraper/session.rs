@@ -0,0 +1,176 @@
+//! One browser for a whole run, rather than one per address
+
+use std::sync::Arc;
+
+use fantoccini::{Client, ClientBuilder};
+use log::{error, info, warn};
+
+use crate::browser::BrowserConfig;
+use crate::flow::ScrapeFlow;
+use crate::politeness::RateLimiter;
+use crate::steps::{StepTimeouts, Steps};
+use crate::{scrape_bin_dates, PageCapture, ScrapeError, ScrapeFailure, ScrapeReport};
+
+/// A WebDriver session that scrapes any number of addresses, one at a time. Call `close` when
+/// done, otherwise dropping it closes the browser in the background
+pub struct ScrapeSession {
+    /// Only `None` once it's been closed
+    client: Option<Client>,
+    /// Kept for the whole session, so a reload doesn't change the flow part way through a run
+    flow: Arc<ScrapeFlow>,
+    timeouts: StepTimeouts,
+    /// Retries wait on this. The first attempt is up to the caller
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl ScrapeSession {
+    /// Starts the browser, through the driver at `driver_url` or the browser's default one
+    pub async fn open(
+        driver_url: Option<String>,
+        browser: &BrowserConfig,
+        flow: Arc<ScrapeFlow>,
+        timeouts: StepTimeouts,
+    ) -> Result<ScrapeSession, ScrapeFailure> {
+        info!("Attempting to connect to webdriver client");
+        let driver_url = driver_url.unwrap_or_else(|| {
+            let default_driver_url = browser.browser.default_driver_url();
+            info!(
+                "No driver_url provided. Defaulting to {}",
+                default_driver_url
+            );
+            default_driver_url.to_string()
+        });
+        let client = match ClientBuilder::native()
+            .capabilities(browser.capabilities())
+            .connect(&driver_url)
+            .await
+        {
+            Ok(client) => client,
+            Err(e) => {
+                // No browser, so nothing to capture
+                return Err(ScrapeFailure {
+                    step: "connect".to_string(),
+                    url: None,
+                    screenshot: None,
+                    page_source: None,
+                    error: ScrapeError::WebDriverConnection(e),
+                });
+            }
+        };
+        info!("Got webdriver client");
+        return Ok(ScrapeSession {
+            client: Some(client),
+            flow,
+            timeouts,
+            limiter: None,
+        });
+    }
+
+    pub fn limited_by(mut self, limiter: Arc<RateLimiter>) -> ScrapeSession {
+        self.limiter = Some(limiter);
+        return self;
+    }
+
+    fn client(&self) -> &Client {
+        return self
+            .client
+            .as_ref()
+            .expect("Session is only closed by consuming it");
+    }
+
+    /// Scrapes the dates for an address, leaving the browser as it found it for the next one
+    pub async fn scrape(
+        &self,
+        postcode: &str,
+        address: &str,
+    ) -> Result<ScrapeReport, ScrapeFailure> {
+        let (report, _) = self
+            .scrape_and_maybe_capture(postcode, address, false)
+            .await?;
+        return Ok(report);
+    }
+
+    /// Like `scrape`, but also keeps the dates page as it was when the dates were read
+    pub async fn scrape_and_capture(
+        &self,
+        postcode: &str,
+        address: &str,
+    ) -> Result<(ScrapeReport, PageCapture), ScrapeFailure> {
+        let (report, page) = self
+            .scrape_and_maybe_capture(postcode, address, true)
+            .await?;
+        return Ok((report, page.expect("Captured when asked to")));
+    }
+
+    async fn scrape_and_maybe_capture(
+        &self,
+        postcode: &str,
+        address: &str,
+        capture: bool,
+    ) -> Result<(ScrapeReport, Option<PageCapture>), ScrapeFailure> {
+        let client = self.client();
+        let mut steps = Steps::new(client, &self.timeouts);
+        let limiter = self.limiter.as_deref();
+        let result = match scrape_bin_dates(
+            client, &self.flow, postcode, address, &mut steps, limiter,
+        )
+        .await
+        {
+            Ok(report) if capture => Ok((report, Some(PageCapture::take(client).await))),
+            Ok(report) => Ok((report, None)),
+            Err(e) => {
+                error!(
+                    "Step \"{}\" failed after {}ms",
+                    steps.current,
+                    steps.elapsed().as_millis()
+                );
+                Err(ScrapeFailure::capture(client, steps.current.clone(), e).await)
+            }
+        };
+        self.reset().await;
+        return result;
+    }
+
+    /// Forgets the council site's cookies, so the next address starts from the cookie banner
+    /// like a new browser would. Cookies can only be deleted while on the site, so this is done
+    /// after each scrape rather than before
+    async fn reset(&self) {
+        let client = self.client();
+        if let Err(e) = client.delete_all_cookies().await {
+            warn!("Could not clear cookies between addresses: {}", e);
+        }
+        if let Err(e) = client.goto("about:blank").await {
+            warn!("Could not leave the council site between addresses: {}", e);
+        }
+    }
+
+    pub async fn close(mut self) {
+        if let Some(client) = self.client.take() {
+            match client.close().await {
+                Ok(()) => info!("Closed webdriver session"),
+                Err(e) => error!("Could not close webdriver session: {}", e),
+            }
+        }
+    }
+}
+
+impl Drop for ScrapeSession {
+    /// For when the run errored or panicked before `close`, so geckodriver isn't left with a
+    /// browser it won't start another alongside
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                warn!("Webdriver session dropped without being closed, closing it");
+                handle.spawn(async move {
+                    if let Err(e) = client.close().await {
+                        error!("Could not close webdriver session: {}", e);
+                    }
+                });
+            }
+            Err(_) => error!("Webdriver session dropped outside a runtime, so it can't be closed"),
+        }
+    }
+}
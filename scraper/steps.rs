@@ -0,0 +1,201 @@
+//! Runs the scrape as named steps, each waiting for the element it needs to be ready instead of
+//! sleeping, and logs how long every step took.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Error};
+use fantoccini::elements::Element;
+use fantoccini::error::CmdError;
+use fantoccini::{Client, Locator};
+use log::info;
+use serde::Deserialize;
+
+use crate::ScrapeError;
+
+const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long each step waits for the page before giving up
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepTimeouts {
+    pub default: Duration,
+    /// By step name, i.e "select address"
+    pub per_step: HashMap<String, Duration>,
+}
+
+impl Default for StepTimeouts {
+    fn default() -> Self {
+        return StepTimeouts {
+            default: DEFAULT_STEP_TIMEOUT,
+            per_step: HashMap::new(),
+        };
+    }
+}
+
+impl StepTimeouts {
+    pub fn for_step(&self, step: &str) -> Duration {
+        return *self.per_step.get(step).unwrap_or(&self.default);
+    }
+}
+
+impl std::str::FromStr for StepTimeouts {
+    type Err = Error;
+
+    /// Seconds per step, i.e "default=10, select address=30"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut timeouts = StepTimeouts::default();
+        for entry in s.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let (step, seconds) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected step=seconds, got {}", entry))?;
+            let timeout = Duration::from_secs_f64(seconds.trim().parse()?);
+            match step.trim() {
+                "default" => timeouts.default = timeout,
+                step => {
+                    timeouts.per_step.insert(step.to_string(), timeout);
+                }
+            }
+        }
+        return Ok(timeouts);
+    }
+}
+
+/// What an element has to be before a step can use it
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ready {
+    Present,
+    Visible,
+    /// Visible and not disabled, i.e a button that can be clicked
+    Enabled,
+    /// A select with more than its placeholder option in it
+    OptionsLoaded,
+}
+
+impl Ready {
+    async fn is_met(&self, element: &Element) -> Result<bool, CmdError> {
+        return match self {
+            Ready::Present => Ok(true),
+            Ready::Visible => element.is_displayed().await,
+            Ready::Enabled => Ok(element.is_displayed().await? && element.is_enabled().await?),
+            Ready::OptionsLoaded => Ok(element.find_all(Locator::Css("option")).await?.len() > 1),
+        };
+    }
+}
+
+pub struct Steps<'a> {
+    client: &'a Client,
+    timeouts: &'a StepTimeouts,
+    /// The step being run, or the last one if they've all finished
+    pub current: String,
+    started: Option<Instant>,
+}
+
+impl<'a> Steps<'a> {
+    pub fn new(client: &'a Client, timeouts: &'a StepTimeouts) -> Steps<'a> {
+        return Steps {
+            client,
+            timeouts,
+            current: "start".to_string(),
+            started: None,
+        };
+    }
+
+    /// Finishes the step before, if it hasn't been already. Starting the step that's already
+    /// running carries on with it
+    pub fn start(&mut self, step: &str) {
+        if self.current == step && self.started.is_some() {
+            return;
+        }
+        self.finish();
+        info!("Starting \"{}\"", step);
+        self.current = step.to_string();
+        self.started = Some(Instant::now());
+    }
+
+    pub fn finish(&mut self) {
+        if let Some(started) = self.started.take() {
+            info!(
+                "Step \"{}\" took {}ms",
+                self.current,
+                started.elapsed().as_millis()
+            );
+        }
+    }
+
+    /// How long the current step has been running, for logging failures
+    pub fn elapsed(&self) -> Duration {
+        return self
+            .started
+            .map(|started| started.elapsed())
+            .unwrap_or_default();
+    }
+
+    /// Polls for the element until it's `ready`, for as long as the current step's timeout.
+    /// `name` says what the element is if it never turns up
+    pub async fn wait_for(
+        &self,
+        name: &str,
+        locator: Locator<'_>,
+        ready: Ready,
+    ) -> Result<Element, ScrapeError> {
+        let timeout = self.timeouts.for_step(&self.current);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let element = match self.client.find(locator).await {
+                Ok(element) => Some(element),
+                Err(CmdError::NoSuchElement(_)) => None,
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(element) = element {
+                match ready.is_met(&element).await {
+                    Ok(true) => return Ok(element),
+                    // Replaced while we were looking at it
+                    Ok(false) | Err(CmdError::NoSuchElement(_)) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if Instant::now() >= deadline {
+                info!("Waited {:?} for the {} to be {:?}", timeout, name, ready);
+                return Err(ScrapeError::SelectorMissing {
+                    name: name.to_string(),
+                    selector: describe(locator),
+                });
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// i.e "#address-finder-postcode-search-text"
+fn describe(locator: Locator<'_>) -> String {
+    return match locator {
+        Locator::Css(css) => css.to_string(),
+        Locator::Id(id) => format!("#{}", id),
+        Locator::LinkText(text) => format!("link \"{}\"", text),
+        Locator::XPath(xpath) => xpath.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_parse_per_step() {
+        let timeouts: StepTimeouts = "default=5, select address=30,go to dates page=12.5"
+            .parse()
+            .unwrap();
+        assert_eq!(timeouts.for_step("enter postcode"), Duration::from_secs(5));
+        assert_eq!(timeouts.for_step("select address"), Duration::from_secs(30));
+        assert_eq!(
+            timeouts.for_step("go to dates page"),
+            Duration::from_millis(12_500)
+        );
+
+        assert_eq!("".parse::<StepTimeouts>().unwrap(), StepTimeouts::default());
+        assert!("select address".parse::<StepTimeouts>().is_err());
+        assert!("select address=soon".parse::<StepTimeouts>().is_err());
+    }
+}
@@ -0,0 +1,355 @@
+//! Someone in a household saying the bins are out for a collection, so nobody else needs chasing.
+//! Reminders carry a signed "Done" link, so it can be done without signing in to anything.
+
+use anyhow::{anyhow, Error};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Form;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bin_stuff::locale::{self, Locale};
+use chrono::NaiveDate;
+use log::info;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde::Deserialize;
+use sqlx::{Row, SqlitePool};
+
+use crate::households::get_household;
+use crate::scheduler::TIMEZONE;
+use crate::{escape_html, get_all_users, AppState};
+
+pub const DONE_ROUTE: &str = "/done";
+pub const ACKNOWLEDGEMENTS_ROUTE: &str = "/acknowledgements";
+
+/// Builds and checks the "Done" links put in reminders
+#[derive(Clone)]
+pub struct AcknowledgeLinks {
+    /// Where the server can be reached from outside, i.e https://bins.example.com
+    base_url: String,
+    secret: Vec<u8>,
+}
+
+impl AcknowledgeLinks {
+    pub fn new(base_url: &str, secret: &str) -> AcknowledgeLinks {
+        return AcknowledgeLinks {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            secret: secret.as_bytes().to_vec(),
+        };
+    }
+
+    /// `user_id` is who the link was sent to, or `None` for shared channels like webhooks
+    pub fn link(
+        &self,
+        household_id: i64,
+        collection_date: NaiveDate,
+        user_id: Option<i64>,
+    ) -> Result<String, Error> {
+        let user = user_id.map(|id| id.to_string()).unwrap_or_default();
+        return Ok(format!(
+            "{}{}?household={}&date={}&user={}&sig={}",
+            self.base_url,
+            DONE_ROUTE,
+            household_id,
+            collection_date,
+            user,
+            self.signature(household_id, collection_date, user_id)?
+        ));
+    }
+
+    fn signature(
+        &self,
+        household_id: i64,
+        collection_date: NaiveDate,
+        user_id: Option<i64>,
+    ) -> Result<String, Error> {
+        let message = format!(
+            "{}:{}:{}",
+            household_id,
+            collection_date,
+            user_id.map(|id| id.to_string()).unwrap_or_default()
+        );
+        let key = PKey::hmac(&self.secret)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(message.as_bytes())?;
+        return Ok(URL_SAFE_NO_PAD.encode(signer.sign_to_vec()?));
+    }
+
+    /// The date is part of what's signed, so a link stops working once its collection is over
+    fn verify(&self, link: &DoneLink, today: NaiveDate) -> Result<(), Error> {
+        if link.date < today {
+            return Err(anyhow!("The collection on {} is over", link.date));
+        }
+        let expected = self.signature(link.household, link.date, link.user_id())?;
+        if expected.len() != link.sig.len()
+            || !openssl::memcmp::eq(expected.as_bytes(), link.sig.as_bytes())
+        {
+            return Err(anyhow!("Invalid signature"));
+        }
+        return Ok(());
+    }
+}
+
+/// The query string of a "Done" link
+#[derive(Deserialize, Debug)]
+pub struct DoneLink {
+    household: i64,
+    date: NaiveDate,
+    /// Empty for links sent to shared channels
+    #[serde(default)]
+    user: String,
+    sig: String,
+}
+
+impl DoneLink {
+    fn user_id(&self) -> Option<i64> {
+        return self.user.parse().ok();
+    }
+}
+
+#[derive(Debug)]
+pub struct Acknowledgement {
+    pub collection_date: NaiveDate,
+    pub user_id: Option<i64>,
+    pub acknowledged_at: String,
+    pub source: String,
+}
+
+/// Only the first acknowledgement for a collection is kept
+pub async fn acknowledge(
+    pool: &SqlitePool,
+    household_id: i64,
+    collection_date: NaiveDate,
+    user_id: Option<i64>,
+    source: &str,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO acknowledgements (household_id, collection_date, user_id, acknowledged_at, source) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )
+    .bind(household_id)
+    .bind(collection_date)
+    .bind(user_id)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(source)
+    .execute(pool)
+    .await?;
+    return Ok(());
+}
+
+pub async fn is_acknowledged(
+    pool: &SqlitePool,
+    household_id: i64,
+    collection_date: NaiveDate,
+) -> Result<bool, Error> {
+    let acknowledgement = sqlx::query(
+        "SELECT id FROM acknowledgements WHERE household_id = ?1 AND collection_date = ?2",
+    )
+    .bind(household_id)
+    .bind(collection_date)
+    .fetch_optional(pool)
+    .await?;
+    return Ok(acknowledgement.is_some());
+}
+
+/// Most recent first
+pub async fn get_acknowledgements(
+    pool: &SqlitePool,
+    household_id: i64,
+) -> Result<Vec<Acknowledgement>, Error> {
+    let acknowledgements = sqlx::query(
+        "SELECT collection_date, user_id, acknowledged_at, source FROM acknowledgements WHERE household_id = ?1 ORDER BY collection_date DESC",
+    )
+    .bind(household_id)
+    .map(|row: sqlx::sqlite::SqliteRow| Acknowledgement {
+        collection_date: row.get("collection_date"),
+        user_id: row.get("user_id"),
+        acknowledged_at: row.get("acknowledged_at"),
+        source: row.get("source"),
+    })
+    .fetch_all(pool)
+    .await?;
+    return Ok(acknowledgements);
+}
+
+/// The response to give instead if the link can't be trusted
+fn invalid_link_response(app_state: &AppState, link: &DoneLink) -> Option<Response> {
+    let acknowledge_links = match &app_state.acknowledge_links {
+        Some(acknowledge_links) => acknowledge_links,
+        None => return Some(StatusCode::NOT_FOUND.into_response()),
+    };
+    let today = chrono::Utc::now().with_timezone(&TIMEZONE).date_naive();
+    if let Err(e) = acknowledge_links.verify(link, today) {
+        info!("Refused a done link: {}", e);
+        return Some(
+            (StatusCode::FORBIDDEN, "This link is out of date or invalid").into_response(),
+        );
+    }
+    return None;
+}
+
+/// The language of whoever the link was sent to, or English for shared channels
+async fn link_locale(app_state: &AppState, link: &DoneLink) -> Locale {
+    let user_id = match link.user_id() {
+        Some(user_id) => user_id,
+        None => return Locale::default(),
+    };
+    let users = get_all_users(&app_state.pool).await.unwrap();
+    return users
+        .iter()
+        .find(|u| u._id == user_id)
+        .map(|u| u.locale)
+        .unwrap_or_default();
+}
+
+/// Just asks for confirmation, as email scanners open links on their own and shouldn't count
+pub(crate) async fn done_page(
+    State(app_state): State<AppState>,
+    Query(link): Query<DoneLink>,
+) -> Response {
+    if let Some(response) = invalid_link_response(&app_state, &link) {
+        return response;
+    }
+    let locale = link_locale(&app_state, &link).await;
+    let html = format!(
+        r#"
+        <!doctype html>
+        <html>
+            <head>
+                <meta name="viewport" content="width=device-width, initial-scale=1">
+                <title>What bin is it</title>
+            </head>
+            <body>
+                <form action="{}" method="post">
+                    <input type="hidden" name="household" value="{}">
+                    <input type="hidden" name="date" value="{}">
+                    <input type="hidden" name="user" value="{}">
+                    <input type="hidden" name="sig" value="{}">
+                    <input type="submit" value="{}">
+                </form>
+            </body>
+        </html>
+        "#,
+        DONE_ROUTE,
+        link.household,
+        link.date,
+        escape_html(&link.user),
+        escape_html(&link.sig),
+        escape_html(&locale::done_button(link.date, locale))
+    );
+    return Html(html).into_response();
+}
+
+pub(crate) async fn submit_done(
+    State(app_state): State<AppState>,
+    Form(link): Form<DoneLink>,
+) -> Response {
+    if let Some(response) = invalid_link_response(&app_state, &link) {
+        return response;
+    }
+    acknowledge(
+        &app_state.pool,
+        link.household,
+        link.date,
+        link.user_id(),
+        "link",
+    )
+    .await
+    .unwrap();
+    let locale = link_locale(&app_state, &link).await;
+    return Html(format!("<p>{}</p>", locale::done_thanks(locale))).into_response();
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HistoryQuery {
+    household_id: i64,
+}
+
+pub(crate) async fn history_page(
+    State(app_state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Html<String> {
+    let household = get_household(&app_state.pool, query.household_id)
+        .await
+        .unwrap();
+    let users = get_all_users(&app_state.pool).await.unwrap();
+    let acknowledgements = get_acknowledgements(&app_state.pool, household.id)
+        .await
+        .unwrap();
+
+    let mut html = format!("<h1>Bins put out at {}</h1>", escape_html(&household.name));
+    if acknowledgements.is_empty() {
+        html.push_str("<p>Nobody has marked a collection as done yet.</p>");
+        return Html(html);
+    }
+    html.push_str("<ul>");
+    for acknowledgement in &acknowledgements {
+        let who = match acknowledgement.user_id {
+            Some(user_id) => users
+                .iter()
+                .find(|u| u._id == user_id)
+                .map(|u| u.display_name())
+                .unwrap_or("Someone who has since left"),
+            None => "Someone in a shared channel",
+        };
+        html.push_str(&format!(
+            "<li>{} - {} at {} (from the {})</li>",
+            acknowledgement.collection_date,
+            escape_html(who),
+            acknowledgement.acknowledged_at,
+            acknowledgement.source
+        ));
+    }
+    html.push_str("</ul>");
+    return Html(html);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_only_verify_with_the_right_signature() {
+        let links = AcknowledgeLinks::new("https://bins.example.com/", "secret");
+        let date = NaiveDate::parse_from_str("2024-01-08", "%Y-%m-%d").unwrap();
+        let link = links.link(3, date, Some(7)).unwrap();
+        assert!(link
+            .starts_with("https://bins.example.com/done?household=3&date=2024-01-08&user=7&sig="));
+
+        let sig = link.split("sig=").nth(1).unwrap().to_string();
+        let done = DoneLink {
+            household: 3,
+            date,
+            user: "7".to_string(),
+            sig: sig.clone(),
+        };
+        assert!(links.verify(&done, date).is_ok());
+        // Too late once the collection's been
+        assert!(links.verify(&done, date.succ_opt().unwrap()).is_err());
+
+        // Someone else's household
+        let tampered = DoneLink {
+            household: 4,
+            date,
+            user: "7".to_string(),
+            sig: sig.clone(),
+        };
+        assert!(links.verify(&tampered, date).is_err());
+
+        // A later date that was never signed for
+        let extended = DoneLink {
+            household: 3,
+            date: date + chrono::Duration::weeks(52),
+            user: "7".to_string(),
+            sig: sig.clone(),
+        };
+        assert!(links.verify(&extended, date).is_err());
+
+        let other_secret = AcknowledgeLinks::new("https://bins.example.com", "other");
+        assert!(other_secret.verify(&done, date).is_err());
+
+        let shared = links.link(3, date, None).unwrap();
+        assert!(shared.contains("&user=&sig="));
+    }
+}
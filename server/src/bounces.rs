@@ -0,0 +1,536 @@
+//! SES bounce and complaint notifications, which SES delivers through an SNS topic. Users whose
+//! address bounces for good, or who mark a reminder as spam, stop being emailed.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::{info, warn};
+use openssl::hash::MessageDigest;
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tokio::sync::Mutex;
+
+use crate::{AppState, USERS_ROUTE};
+
+pub const SES_NOTIFICATIONS_ROUTE: &str = "/ses/notifications";
+pub const RESUME_EMAILS_ROUTE: &str = "/users/resume_emails";
+
+/// An SNS message, as POSTed to the subscribed endpoint
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct SnsMessage {
+    #[serde(rename = "Type")]
+    kind: String,
+    message_id: String,
+    topic_arn: String,
+    #[serde(default)]
+    subject: Option<String>,
+    message: String,
+    timestamp: String,
+    signature_version: String,
+    signature: String,
+    #[serde(rename = "SigningCertURL")]
+    signing_cert_url: String,
+    #[serde(rename = "SubscribeURL", default)]
+    subscribe_url: Option<String>,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+impl SnsMessage {
+    /// The fields SNS signs, in the order it signs them
+    fn string_to_sign(&self) -> String {
+        let fields = match self.kind.as_str() {
+            "Notification" => vec![
+                ("Message", Some(&self.message)),
+                ("MessageId", Some(&self.message_id)),
+                ("Subject", self.subject.as_ref()),
+                ("Timestamp", Some(&self.timestamp)),
+                ("TopicArn", Some(&self.topic_arn)),
+                ("Type", Some(&self.kind)),
+            ],
+            _ => vec![
+                ("Message", Some(&self.message)),
+                ("MessageId", Some(&self.message_id)),
+                ("SubscribeURL", self.subscribe_url.as_ref()),
+                ("Timestamp", Some(&self.timestamp)),
+                ("Token", self.token.as_ref()),
+                ("TopicArn", Some(&self.topic_arn)),
+                ("Type", Some(&self.kind)),
+            ],
+        };
+        let mut string_to_sign = String::new();
+        for (name, value) in fields {
+            if let Some(value) = value {
+                string_to_sign.push_str(&format!("{}\n{}\n", name, value));
+            }
+        }
+        return string_to_sign;
+    }
+}
+
+/// Checks SNS messages really came from AWS, for the one topic SES was set up to publish to
+#[derive(Clone)]
+pub struct SnsVerifier {
+    topic_arn: String,
+    /// Signing certificates by URL, as SNS uses the same one for a long time
+    certificates: Arc<Mutex<HashMap<String, X509>>>,
+}
+
+impl SnsVerifier {
+    pub fn new(topic_arn: &str) -> SnsVerifier {
+        return SnsVerifier {
+            topic_arn: topic_arn.to_string(),
+            certificates: Arc::new(Mutex::new(HashMap::new())),
+        };
+    }
+
+    /// Stands in for AWS by having the certificate for `url` already, so nothing is fetched
+    #[cfg(test)]
+    async fn with_certificate(self, url: &str, pem: &[u8]) -> Result<SnsVerifier, Error> {
+        self.certificates
+            .lock()
+            .await
+            .insert(url.to_string(), X509::from_pem(pem)?);
+        return Ok(self);
+    }
+
+    async fn certificate(&self, url: &str, http_client: &reqwest::Client) -> Result<X509, Error> {
+        check_aws_url(url)?;
+        if let Some(certificate) = self.certificates.lock().await.get(url) {
+            return Ok(certificate.clone());
+        }
+        let pem = http_client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let certificate = X509::from_pem(&pem)?;
+        self.certificates
+            .lock()
+            .await
+            .insert(url.to_string(), certificate.clone());
+        return Ok(certificate);
+    }
+
+    pub async fn verify(
+        &self,
+        message: &SnsMessage,
+        http_client: &reqwest::Client,
+    ) -> Result<(), Error> {
+        if message.topic_arn != self.topic_arn {
+            return Err(anyhow!("Unexpected topic {}", message.topic_arn));
+        }
+        let digest = match message.signature_version.as_str() {
+            "1" => MessageDigest::sha1(),
+            "2" => MessageDigest::sha256(),
+            version => return Err(anyhow!("Unknown signature version {}", version)),
+        };
+        let certificate = self
+            .certificate(&message.signing_cert_url, http_client)
+            .await?;
+        let public_key = certificate.public_key()?;
+        let mut verifier = Verifier::new(digest, &public_key)?;
+        verifier.update(message.string_to_sign().as_bytes())?;
+        if !verifier.verify(&STANDARD.decode(&message.signature)?)? {
+            return Err(anyhow!("Invalid signature"));
+        }
+        return Ok(());
+    }
+}
+
+/// Only fetch certificates and confirm subscriptions with SNS itself, i.e
+/// https://sns.eu-west-1.amazonaws.com/...
+fn check_aws_url(url: &str) -> Result<(), Error> {
+    let parsed = reqwest::Url::parse(url)?;
+    let from_sns = parsed.host_str().is_some_and(is_sns_host);
+    if parsed.scheme() != "https" || parsed.port().is_some() || !from_sns {
+        return Err(anyhow!("{} is not an SNS URL", url));
+    }
+    return Ok(());
+}
+
+/// `sns.<region>.amazonaws.com(.cn)`, as other AWS services under amazonaws.com can serve
+/// whatever their customers give them
+fn is_sns_host(host: &str) -> bool {
+    let region = host
+        .strip_prefix("sns.")
+        .and_then(|rest| {
+            rest.strip_suffix(".amazonaws.com")
+                .or_else(|| rest.strip_suffix(".amazonaws.com.cn"))
+        })
+        .unwrap_or("");
+    return !region.is_empty()
+        && region
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+}
+
+/// The part of an SES notification we care about. Event publishing calls the type `eventType`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SesNotification {
+    #[serde(alias = "eventType")]
+    notification_type: String,
+    bounce: Option<Bounce>,
+    complaint: Option<Complaint>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Bounce {
+    bounce_type: String,
+    #[serde(default)]
+    bounce_sub_type: String,
+    bounced_recipients: Vec<Recipient>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Complaint {
+    complained_recipients: Vec<Recipient>,
+    complaint_feedback_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Recipient {
+    email_address: String,
+}
+
+/// Addresses to stop emailing and why. Transient bounces, like a full mailbox, are left alone
+fn suppressions(notification: &SesNotification) -> Vec<(String, String)> {
+    return match (&notification.bounce, &notification.complaint) {
+        (Some(bounce), _) if bounce.bounce_type == "Permanent" => bounce
+            .bounced_recipients
+            .iter()
+            .map(|recipient| {
+                (
+                    recipient.email_address.clone(),
+                    format!("Permanent bounce ({})", bounce.bounce_sub_type),
+                )
+            })
+            .collect(),
+        (_, Some(complaint)) => complaint
+            .complained_recipients
+            .iter()
+            .map(|recipient| {
+                let reason = match &complaint.complaint_feedback_type {
+                    Some(feedback_type) => format!("Complaint ({})", feedback_type),
+                    None => "Complaint".to_string(),
+                };
+                (recipient.email_address.clone(), reason)
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+}
+
+/// Returns the number of users whose emails were stopped
+pub async fn suppress_email(pool: &SqlitePool, email: &str, reason: &str) -> Result<u64, Error> {
+    let result = sqlx::query(
+        "UPDATE emails SET email_suppressed_reason = ?1, email_suppressed_at = ?2 WHERE lower(email) = lower(?3) AND email_suppressed_reason IS NULL",
+    )
+    .bind(reason)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(email)
+    .execute(pool)
+    .await?;
+    return Ok(result.rows_affected());
+}
+
+pub async fn resume_emails(pool: &SqlitePool, user_id: i64) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE emails SET email_suppressed_reason = NULL, email_suppressed_at = NULL WHERE id = ?1",
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    return Ok(());
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SnsOutcome {
+    /// A new subscription, which has to be confirmed by visiting the URL
+    ConfirmSubscription(String),
+    /// The addresses that belonged to users
+    Suppressed(Vec<String>),
+    Ignored,
+}
+
+/// Verifies an SNS POST body and stops emailing anyone it says to
+pub async fn handle_sns_message(
+    pool: &SqlitePool,
+    verifier: &SnsVerifier,
+    http_client: &reqwest::Client,
+    body: &str,
+) -> Result<SnsOutcome, Error> {
+    let message: SnsMessage = serde_json::from_str(body)?;
+    verifier.verify(&message, http_client).await?;
+
+    match message.kind.as_str() {
+        "SubscriptionConfirmation" => {
+            let subscribe_url = message
+                .subscribe_url
+                .ok_or_else(|| anyhow!("Subscription confirmation without a SubscribeURL"))?;
+            check_aws_url(&subscribe_url)?;
+            return Ok(SnsOutcome::ConfirmSubscription(subscribe_url));
+        }
+        "Notification" => {
+            let notification: SesNotification = serde_json::from_str(&message.message)?;
+            let mut suppressed = Vec::new();
+            for (email, reason) in suppressions(&notification) {
+                if suppress_email(pool, &email, &reason).await? > 0 {
+                    info!("Stopped emailing {}: {}", email, reason);
+                    suppressed.push(email);
+                }
+            }
+            if suppressed.is_empty() {
+                info!(
+                    "Nothing to do for {} notification",
+                    notification.notification_type
+                );
+                return Ok(SnsOutcome::Ignored);
+            }
+            return Ok(SnsOutcome::Suppressed(suppressed));
+        }
+        _ => return Ok(SnsOutcome::Ignored),
+    }
+}
+
+/// SNS sends JSON as text/plain, so this takes the raw body
+pub(crate) async fn ses_notification(State(app_state): State<AppState>, body: String) -> Response {
+    let verifier = match &app_state.sns_verifier {
+        Some(verifier) => verifier,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let outcome =
+        handle_sns_message(&app_state.pool, verifier, &app_state.http_client, &body).await;
+    match outcome {
+        Ok(SnsOutcome::ConfirmSubscription(subscribe_url)) => {
+            info!("Confirming SNS subscription");
+            let confirmation = app_state
+                .http_client
+                .get(&subscribe_url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = confirmation {
+                warn!("Could not confirm SNS subscription: {}", e);
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+            return StatusCode::OK.into_response();
+        }
+        Ok(_) => return StatusCode::OK.into_response(),
+        Err(e) => {
+            warn!("Rejected SNS message: {}", e);
+            return (StatusCode::FORBIDDEN, "Rejected").into_response();
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ResumeEmailsQuery {
+    user_id: i64,
+}
+
+/// For when someone has fixed their mailbox, or complained by mistake
+pub(crate) async fn submit_resume_emails(
+    State(app_state): State<AppState>,
+    Query(query): Query<ResumeEmailsQuery>,
+) -> Response {
+    resume_emails(&app_state.pool, query.user_id).await.unwrap();
+    return Redirect::to(USERS_ROUTE).into_response();
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::Row;
+
+    use super::*;
+
+    const TOPIC_ARN: &str = "arn:aws:sns:eu-west-1:123456789012:ses-notifications";
+    const CERT_URL: &str = "https://sns.eu-west-1.amazonaws.com/SimpleNotificationService-test.pem";
+
+    /// Signs a sample payload the way SNS would, with the test key
+    fn signed(sample: &str) -> String {
+        let mut payload: serde_json::Value = serde_json::from_str(sample).unwrap();
+        let message: SnsMessage = serde_json::from_value(payload.clone()).unwrap();
+        let digest = match message.signature_version.as_str() {
+            "1" => MessageDigest::sha1(),
+            _ => MessageDigest::sha256(),
+        };
+        let key =
+            PKey::private_key_from_pem(include_bytes!("../fixtures/sns/test-signing-key.pem"))
+                .unwrap();
+        let mut signer = Signer::new(digest, &key).unwrap();
+        signer.update(message.string_to_sign().as_bytes()).unwrap();
+        payload["Signature"] = STANDARD.encode(signer.sign_to_vec().unwrap()).into();
+        return payload.to_string();
+    }
+
+    async fn stand_in_verifier() -> SnsVerifier {
+        return SnsVerifier::new(TOPIC_ARN)
+            .with_certificate(
+                CERT_URL,
+                include_bytes!("../fixtures/sns/test-signing-cert.pem"),
+            )
+            .await
+            .unwrap();
+    }
+
+    async fn pool_with_users(emails: &[&str]) -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+        sqlx::query("INSERT INTO households (id, name, postcode, address) VALUES (1, 'Flat', 'AB1 2CD', '1 Road')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for email in emails {
+            sqlx::query("INSERT INTO emails (email, household_id, app_token) VALUES (?1, 1, ?1)")
+                .bind(email)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        return pool;
+    }
+
+    async fn suppressed_reason(pool: &SqlitePool, email: &str) -> Option<String> {
+        return sqlx::query("SELECT email_suppressed_reason FROM emails WHERE email = ?1")
+            .bind(email)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+            .get("email_suppressed_reason");
+    }
+
+    #[tokio::test]
+    async fn replays_sample_notifications() {
+        let pool = pool_with_users(&[
+            "gone@example.com",
+            "full@example.com",
+            "annoyed@example.com",
+        ])
+        .await;
+        let verifier = stand_in_verifier().await;
+        let http_client = reqwest::Client::new();
+
+        let outcome = handle_sns_message(
+            &pool,
+            &verifier,
+            &http_client,
+            &signed(include_str!("../fixtures/sns/permanent_bounce.json")),
+        )
+        .await
+        .unwrap();
+        // SES keeps the case the recipient was sent with
+        assert_eq!(
+            outcome,
+            SnsOutcome::Suppressed(vec!["Gone@Example.com".to_string()])
+        );
+        assert_eq!(
+            suppressed_reason(&pool, "gone@example.com").await,
+            Some("Permanent bounce (General)".to_string())
+        );
+
+        let outcome = handle_sns_message(
+            &pool,
+            &verifier,
+            &http_client,
+            &signed(include_str!("../fixtures/sns/transient_bounce.json")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, SnsOutcome::Ignored);
+        assert_eq!(suppressed_reason(&pool, "full@example.com").await, None);
+
+        handle_sns_message(
+            &pool,
+            &verifier,
+            &http_client,
+            &signed(include_str!("../fixtures/sns/complaint.json")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            suppressed_reason(&pool, "annoyed@example.com").await,
+            Some("Complaint (abuse)".to_string())
+        );
+
+        let outcome = handle_sns_message(
+            &pool,
+            &verifier,
+            &http_client,
+            &signed(include_str!(
+                "../fixtures/sns/subscription_confirmation.json"
+            )),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, SnsOutcome::ConfirmSubscription(url)
+            if url.starts_with("https://sns.eu-west-1.amazonaws.com/?Action=ConfirmSubscription")));
+    }
+
+    #[tokio::test]
+    async fn rejects_messages_that_are_not_from_the_topic() {
+        let pool = pool_with_users(&["gone@example.com"]).await;
+        let verifier = stand_in_verifier().await;
+        let http_client = reqwest::Client::new();
+
+        // Unsigned
+        let unsigned = include_str!("../fixtures/sns/permanent_bounce.json");
+        assert!(handle_sns_message(&pool, &verifier, &http_client, unsigned)
+            .await
+            .is_err());
+
+        // Signed, then the recipient changed
+        let tampered = signed(include_str!("../fixtures/sns/permanent_bounce.json"))
+            .replace("Gone@Example.com", "someone@example.com");
+        assert!(
+            handle_sns_message(&pool, &verifier, &http_client, &tampered)
+                .await
+                .is_err()
+        );
+
+        // Properly signed, but for somebody else's topic
+        let other_topic = SnsVerifier::new("arn:aws:sns:eu-west-1:123456789012:other")
+            .with_certificate(
+                CERT_URL,
+                include_bytes!("../fixtures/sns/test-signing-cert.pem"),
+            )
+            .await
+            .unwrap();
+        let bounce = signed(include_str!("../fixtures/sns/permanent_bounce.json"));
+        assert!(
+            handle_sns_message(&pool, &other_topic, &http_client, &bounce)
+                .await
+                .is_err()
+        );
+
+        assert_eq!(suppressed_reason(&pool, "gone@example.com").await, None);
+        assert!(check_aws_url("https://sns.eu-west-1.amazonaws.com.evil.com/cert.pem").is_err());
+        assert!(check_aws_url("http://sns.eu-west-1.amazonaws.com/cert.pem").is_err());
+        assert!(check_aws_url("https://sns.cn-north-1.amazonaws.com.cn/cert.pem").is_ok());
+        // Another service's bucket, which anyone could put a certificate in
+        assert!(check_aws_url("https://sns.example.s3.amazonaws.com/cert.pem").is_err());
+        assert!(check_aws_url("https://sns.amazonaws.com/cert.pem").is_err());
+    }
+}
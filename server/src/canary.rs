@@ -0,0 +1,420 @@
+//! Scrapes a few addresses known to work every day, whether or not anyone lives there, so a change
+//! to the council site is noticed before the households' run fails. The results are checked too,
+//! as a page that loads but is missing a bin or shows nonsense dates is just as broken.
+
+use anyhow::{anyhow, Error};
+use axum::extract::State;
+use axum::response::Html;
+use bin_stuff::{Bin, BinDates};
+use chrono::{NaiveDate, NaiveTime};
+use log::{error, info};
+use sqlx::{Row, SqlitePool};
+
+use crate::incidents::{self, report_error, report_success};
+use crate::layout::{check_layout, layout_html};
+use crate::scheduler::TIMEZONE;
+use crate::{escape_html, open_scrape_pool, AppState};
+
+pub const HEALTH_ROUTE: &str = "/health";
+
+/// Collections are at most four weeks apart, and a bank holiday can push one back a few days
+const MAX_DAYS_BETWEEN_COLLECTIONS: i64 = 31;
+/// Closer than this and the same collection has probably been listed twice
+const MIN_DAYS_BETWEEN_COLLECTIONS: i64 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanaryAddress {
+    pub postcode: String,
+    pub address: String,
+}
+
+impl std::fmt::Display for CanaryAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}, {}", self.address, self.postcode);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CanaryConfig {
+    pub addresses: Vec<CanaryAddress>,
+    /// UK local time it runs at each day
+    pub time: NaiveTime,
+}
+
+/// i.e "ML6 0AA|1 Main Street;ML1 1AA|2 High Street", as addresses have commas in them
+pub fn parse_canary_addresses(s: &str) -> Result<Vec<CanaryAddress>, Error> {
+    let mut addresses = Vec::new();
+    for entry in s
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (postcode, address) = entry
+            .split_once('|')
+            .ok_or_else(|| anyhow!("Expected POSTCODE|ADDRESS, got {}", entry))?;
+        addresses.push(CanaryAddress {
+            postcode: postcode.trim().to_string(),
+            address: address.trim().to_string(),
+        });
+    }
+    return Ok(addresses);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanaryProblem {
+    /// A bin in the flow with no dates
+    MissingBin(Bin),
+    PastDate(Bin, NaiveDate),
+    /// Nothing soon enough to be the next collection
+    NothingSoon(Bin, NaiveDate),
+    UnlikelyGap(Bin, NaiveDate, NaiveDate),
+}
+
+impl CanaryProblem {
+    /// Missing bins mean the page has changed shape, rather than the dates being off
+    fn is_structural(&self) -> bool {
+        return matches!(self, CanaryProblem::MissingBin(_));
+    }
+}
+
+impl std::fmt::Display for CanaryProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            CanaryProblem::MissingBin(bin) => write!(f, "no dates for the {} bin", bin),
+            CanaryProblem::PastDate(bin, date) => {
+                write!(f, "{} bin date {} is in the past", bin, date)
+            }
+            CanaryProblem::NothingSoon(bin, date) => {
+                write!(f, "the {} bin's first date {} is too far away", bin, date)
+            }
+            CanaryProblem::UnlikelyGap(bin, from, to) => write!(
+                f,
+                "{} bin dates {} and {} are an unlikely gap apart",
+                bin, from, to
+            ),
+        };
+    }
+}
+
+/// A canary address that scraped but didn't look right
+#[derive(Debug)]
+pub struct CanaryFailure {
+    pub address: CanaryAddress,
+    pub problems: Vec<CanaryProblem>,
+}
+
+impl CanaryFailure {
+    /// For grouping into incidents, so a layout change isn't lumped in with odd dates
+    pub fn kind(&self) -> &'static str {
+        if self.problems.iter().any(|p| p.is_structural()) {
+            return "site layout changed";
+        }
+        return "implausible dates";
+    }
+}
+
+impl std::fmt::Display for CanaryFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let problems: Vec<String> = self.problems.iter().map(|p| p.to_string()).collect();
+        return write!(
+            f,
+            "Canary scrape of {}: {}",
+            self.address,
+            problems.join("; ")
+        );
+    }
+}
+
+impl std::error::Error for CanaryFailure {}
+
+/// Everything that looks wrong with a canary's dates. Every bin in `expected` should have dates,
+/// none in the past, starting soon and spaced like a real schedule
+pub fn check_bins(expected: &[Bin], bins: &[BinDates], today: NaiveDate) -> Vec<CanaryProblem> {
+    let mut problems = Vec::new();
+    for bin in expected {
+        let mut dates = match bins.iter().find(|b| b.bin == *bin) {
+            Some(bin_dates) if !bin_dates.dates.is_empty() => bin_dates.dates.clone(),
+            _ => {
+                problems.push(CanaryProblem::MissingBin(*bin));
+                continue;
+            }
+        };
+        dates.sort();
+        for date in dates.iter().filter(|date| **date < today) {
+            problems.push(CanaryProblem::PastDate(*bin, *date));
+        }
+        if let Some(first) = dates.iter().find(|date| **date >= today) {
+            if (*first - today).num_days() > MAX_DAYS_BETWEEN_COLLECTIONS {
+                problems.push(CanaryProblem::NothingSoon(*bin, *first));
+            }
+        }
+        for pair in dates.windows(2) {
+            let gap = (pair[1] - pair[0]).num_days();
+            if !(MIN_DAYS_BETWEEN_COLLECTIONS..=MAX_DAYS_BETWEEN_COLLECTIONS).contains(&gap) {
+                problems.push(CanaryProblem::UnlikelyGap(*bin, pair[0], pair[1]));
+            }
+        }
+    }
+    return problems;
+}
+
+/// Scrapes and checks every canary address, alerting through incidents like any other error
+pub(crate) async fn run_canary(app_state: &AppState, addresses: &[CanaryAddress]) {
+    info!("Running the canary scrape");
+    let scrape_pool = match open_scrape_pool(app_state).await {
+        Ok(scrape_pool) => scrape_pool,
+        Err(failure) => {
+            for address in addresses {
+                record_canary_run(app_state, address, Some(&failure.to_string())).await;
+            }
+            report_error(app_state, incidents::CANARY, failure.into()).await;
+            return;
+        }
+    };
+    let expected: Vec<Bin> = scrape_pool.flow().bins.iter().map(|s| s.bin).collect();
+    let today = chrono::Utc::now().with_timezone(&TIMEZONE).date_naive();
+
+    let mut all_passed = true;
+    let mut layout_changed = false;
+    for address in addresses {
+        let result = match scrape_pool
+            .scrape(&address.postcode, &address.address)
+            .await
+        {
+            Ok(report) => {
+                let fingerprint = report.fingerprint.as_ref();
+                if check_layout(app_state, &address.postcode, &address.address, fingerprint).await {
+                    layout_changed = true;
+                }
+                let problems = check_bins(&expected, &report.bins, today);
+                if problems.is_empty() {
+                    Ok(())
+                } else {
+                    Err(CanaryFailure {
+                        address: address.clone(),
+                        problems,
+                    }
+                    .into())
+                }
+            }
+            // Households are left to fix their own addresses, but a canary's address is known
+            // to be right, so the site not finding it is worth an alert
+            Err(failure) if failure.error.is_address_problem() => Err(anyhow!(
+                "Canary address {} wasn't found: {}",
+                address,
+                failure
+            )),
+            Err(failure) => {
+                match failure.save(&app_state.failures_dir) {
+                    Ok(dir) => info!("Saved canary failure to {}", dir.display()),
+                    Err(e) => error!("Could not save canary failure: {}", e),
+                }
+                Err(Error::from(failure))
+            }
+        };
+        match result {
+            Ok(()) => {
+                info!("Canary {} passed", address);
+                record_canary_run(app_state, address, None).await;
+            }
+            Err(e) => {
+                all_passed = false;
+                record_canary_run(app_state, address, Some(&e.to_string())).await;
+                report_error(app_state, incidents::CANARY, e).await;
+            }
+        }
+        if scrape_pool.size() == 0 {
+            break;
+        }
+    }
+    scrape_pool.close().await;
+    if all_passed {
+        report_success(app_state, incidents::CANARY).await;
+        if !layout_changed {
+            report_success(app_state, incidents::LAYOUT).await;
+        }
+    }
+}
+
+async fn record_canary_run(app_state: &AppState, address: &CanaryAddress, problem: Option<&str>) {
+    if let Err(e) = insert_canary_run(&app_state.pool, address, problem).await {
+        error!("Could not record canary run for {}: {}", address, e);
+    }
+}
+
+async fn insert_canary_run(
+    pool: &SqlitePool,
+    address: &CanaryAddress,
+    problem: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO canary_runs (postcode, address, ran_at, ok, problem) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )
+    .bind(&address.postcode)
+    .bind(&address.address)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(problem.is_none())
+    .bind(problem)
+    .execute(pool)
+    .await?;
+    return Ok(());
+}
+
+#[derive(Debug)]
+pub struct CanaryStatus {
+    pub last_run: Option<String>,
+    /// Why the last run failed, if it did
+    pub problem: Option<String>,
+    pub last_passed: Option<String>,
+}
+
+pub async fn get_canary_status(
+    pool: &SqlitePool,
+    address: &CanaryAddress,
+) -> Result<CanaryStatus, Error> {
+    let last_run = sqlx::query(
+        "SELECT ran_at, problem FROM canary_runs WHERE postcode = ?1 AND address = ?2 ORDER BY id DESC LIMIT 1",
+    )
+    .bind(&address.postcode)
+    .bind(&address.address)
+    .fetch_optional(pool)
+    .await?;
+    let last_passed: Option<String> = sqlx::query(
+        "SELECT ran_at FROM canary_runs WHERE postcode = ?1 AND address = ?2 AND ok = 1 ORDER BY id DESC LIMIT 1",
+    )
+    .bind(&address.postcode)
+    .bind(&address.address)
+    .map(|row: sqlx::sqlite::SqliteRow| row.get("ran_at"))
+    .fetch_optional(pool)
+    .await?;
+    return Ok(CanaryStatus {
+        last_run: last_run.as_ref().map(|row| row.get("ran_at")),
+        problem: last_run.and_then(|row| row.get("problem")),
+        last_passed,
+    });
+}
+
+pub(crate) async fn health_page(State(app_state): State<AppState>) -> Html<String> {
+    let mut html = "<h1>Health</h1>".to_string();
+    html.push_str(&canary_html(&app_state).await);
+    html.push_str(&layout_html(&app_state.pool).await);
+    return Html(html);
+}
+
+async fn canary_html(app_state: &AppState) -> String {
+    let mut html = "<h2>Canary</h2>".to_string();
+    let Some(canary) = &app_state.canary else {
+        html.push_str("<p>Not set up. Set CANARY_ADDRESSES to scrape known addresses daily.</p>");
+        return html;
+    };
+    html.push_str(&format!(
+        "<p>Runs every day at {}.</p><ul>",
+        canary.time.format("%H:%M")
+    ));
+    for address in &canary.addresses {
+        let status = get_canary_status(&app_state.pool, address).await.unwrap();
+        let state = match (&status.last_run, &status.problem) {
+            (None, _) => "not run yet".to_string(),
+            (Some(ran_at), None) => format!("passed at {}", ran_at),
+            (Some(ran_at), Some(problem)) => {
+                format!("failed at {}: {}", ran_at, escape_html(problem))
+            }
+        };
+        html.push_str(&format!(
+            "<li>{} - {} (last passed {})</li>",
+            escape_html(&address.to_string()),
+            state,
+            status.last_passed.as_deref().unwrap_or("never")
+        ));
+    }
+    html.push_str("</ul>");
+    return html;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        return NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+    }
+
+    #[test]
+    fn plausible_schedules_pass() {
+        let today = date(1, 3);
+        let bins = vec![
+            BinDates {
+                bin: Bin::Black,
+                // Fortnightly, but pushed back a couple of days by a bank holiday
+                dates: vec![date(1, 8), date(1, 24), date(2, 5)],
+            },
+            BinDates {
+                bin: Bin::Blue,
+                dates: vec![date(1, 3), date(1, 31)],
+            },
+        ];
+        assert!(check_bins(&[Bin::Black, Bin::Blue], &bins, today).is_empty());
+    }
+
+    #[test]
+    fn every_problem_is_found() {
+        let today = date(3, 1);
+        let bins = vec![
+            BinDates {
+                bin: Bin::Black,
+                dates: vec![date(2, 28), date(3, 1), date(3, 2)],
+            },
+            BinDates {
+                bin: Bin::Blue,
+                dates: vec![date(5, 1)],
+            },
+            BinDates {
+                bin: Bin::Brown,
+                dates: Vec::new(),
+            },
+        ];
+        let problems = check_bins(
+            &[Bin::Black, Bin::Blue, Bin::Brown, Bin::Green],
+            &bins,
+            today,
+        );
+        assert_eq!(
+            problems,
+            vec![
+                CanaryProblem::PastDate(Bin::Black, date(2, 28)),
+                CanaryProblem::UnlikelyGap(Bin::Black, date(2, 28), date(3, 1)),
+                CanaryProblem::UnlikelyGap(Bin::Black, date(3, 1), date(3, 2)),
+                CanaryProblem::NothingSoon(Bin::Blue, date(5, 1)),
+                CanaryProblem::MissingBin(Bin::Brown),
+                CanaryProblem::MissingBin(Bin::Green),
+            ]
+        );
+        let failure = CanaryFailure {
+            address: CanaryAddress {
+                postcode: "ML6 0AA".to_string(),
+                address: "1 Main Street".to_string(),
+            },
+            problems,
+        };
+        assert_eq!(failure.kind(), "site layout changed");
+    }
+
+    #[test]
+    fn addresses_are_separated_by_semicolons() {
+        assert_eq!(
+            parse_canary_addresses("ML6 0AA|1 Main Street, Airdrie; ML1 1AA | 2 High Street;")
+                .unwrap(),
+            vec![
+                CanaryAddress {
+                    postcode: "ML6 0AA".to_string(),
+                    address: "1 Main Street, Airdrie".to_string(),
+                },
+                CanaryAddress {
+                    postcode: "ML1 1AA".to_string(),
+                    address: "2 High Street".to_string(),
+                },
+            ]
+        );
+        assert!(parse_canary_addresses("ML6 0AA 1 Main Street").is_err());
+    }
+}
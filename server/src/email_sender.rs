@@ -1,27 +1,168 @@
 use anyhow::Error;
 
-use aws_sdk_sesv2::types::{Body, Content, Destination, EmailContent, Message};
+use aws_sdk_sesv2::primitives::Blob;
+use aws_sdk_sesv2::types::{Body, Content, Destination, EmailContent, Message, RawMessage};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 
+use bin_stuff::locale::{self, Locale};
+use bin_stuff::schedule_changes::ScheduleChange;
 use bin_stuff::{NextBinCollection, User};
 use log::info;
+use scraper::{ScrapeError, ScrapeFailure};
+
+const ERROR_SUBJECT: &str = "Error with what bin service";
+
+/// What goes in a bin reminder, whichever channel it's sent over
+#[derive(Debug)]
+pub struct ReminderText {
+    pub subject: String,
+    /// Signed link for saying the bins are out, if the server knows its public URL
+    pub done_link: Option<String>,
+    /// The recipient's language, for the rest of the message
+    pub locale: Locale,
+}
 
 pub async fn email_user(
     user: &User,
     next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
+    aws_client: &aws_sdk_sesv2::Client,
+    from_email_address: &str,
+) -> Result<(), Error> {
+    return email_reminder_to(
+        &user.email,
+        next_bin_collection,
+        text,
+        aws_client,
+        from_email_address,
+    )
+    .await;
+}
+
+/// For sending reminders to people who aren't users, i.e a household's escalation contact
+pub async fn email_reminder_to(
+    to_email_address: &str,
+    next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
     aws_client: &aws_sdk_sesv2::Client,
     from_email_address: &str,
 ) -> Result<(), Error> {
     let email = build_bin_email_to_send(
-        &next_bin_collection,
-        &user,
-        &aws_client,
-        &from_email_address,
+        next_bin_collection,
+        text,
+        to_email_address,
+        aws_client,
+        from_email_address,
     );
     email.send().await?;
-    println!("Email sent");
+    log::debug!("Reminder email sent to {}", to_email_address);
+    return Ok(());
+}
+
+pub async fn email_schedule_changes(
+    user: &User,
+    changes: &[ScheduleChange],
+    aws_client: &aws_sdk_sesv2::Client,
+    from_email_address: &str,
+) -> Result<(), Error> {
+    let subject = schedule_changes_subject(changes, user.locale);
+    let mut body = String::new();
+    for change in changes {
+        body.push_str(&format!(
+            "{}\n",
+            locale::schedule_change(change, user.locale)
+        ));
+    }
+
+    let destination_email = Destination::builder().to_addresses(&user.email).build();
+
+    let subject_content = Content::builder().data(subject).charset("UTF-8").build();
+
+    let body_content = Content::builder().data(body).charset("UTF-8").build();
+
+    let body = Body::builder().text(body_content).build();
+
+    let msg = Message::builder()
+        .subject(subject_content)
+        .body(body)
+        .build();
+
+    let email_content = EmailContent::builder().simple(msg).build();
+    aws_client
+        .send_email()
+        .from_email_address(from_email_address)
+        .destination(destination_email)
+        .content(email_content)
+        .send()
+        .await?;
+    info!("Schedule change email sent to {}", user.email);
     return Ok(());
 }
 
+/// For when the council site doesn't know the household's postcode or address
+pub async fn email_address_problem(
+    user: &User,
+    problem: &ScrapeError,
+    aws_client: &aws_sdk_sesv2::Client,
+    from_email_address: &str,
+) -> Result<(), Error> {
+    let destination_email = Destination::builder().to_addresses(&user.email).build();
+
+    let subject_content = Content::builder()
+        .data(locale::address_not_found(user.locale))
+        .charset("UTF-8")
+        .build();
+
+    let body_content = Content::builder()
+        .data(format!(
+            "{}\n\n{}",
+            address_problem_text(problem, user.locale),
+            locale::no_reminders_until_fixed(user.locale)
+        ))
+        .charset("UTF-8")
+        .build();
+
+    let body = Body::builder().text(body_content).build();
+
+    let msg = Message::builder()
+        .subject(subject_content)
+        .body(body)
+        .build();
+
+    let email_content = EmailContent::builder().simple(msg).build();
+    aws_client
+        .send_email()
+        .from_email_address(from_email_address)
+        .destination(destination_email)
+        .content(email_content)
+        .send()
+        .await?;
+    info!("Address problem email sent to {}", user.email);
+    return Ok(());
+}
+
+/// Why the council site couldn't find the household's address, in the user's language
+fn address_problem_text(problem: &ScrapeError, locale: Locale) -> String {
+    return match problem {
+        ScrapeError::PostcodeNotRecognised(postcode) => {
+            locale::postcode_not_recognised(postcode, locale)
+        }
+        ScrapeError::AddressNotInList { address, available } => {
+            locale::address_not_in_list(address, available, locale)
+        }
+        // Only address problems are sent to users
+        problem => problem.to_string(),
+    };
+}
+
+pub fn schedule_changes_subject(changes: &[ScheduleChange], locale: Locale) -> String {
+    if changes.len() == 1 {
+        return locale::schedule_change(&changes[0], locale);
+    }
+    return locale::schedule_changes_count(changes.len(), locale);
+}
+
 pub async fn send_error_email(
     aws_client: &aws_sdk_sesv2::Client,
     from_email_address: &str,
@@ -32,16 +173,68 @@ pub async fn send_error_email(
         .to_addresses(to_email_address)
         .build();
 
-    let subject_content = Content::builder()
-        .data("Error with what bin service")
-        .charset("UTF-8")
+    // Scraper failures come with what the page looked like, which needs attachments
+    let email_content = match err.downcast_ref::<ScrapeFailure>() {
+        Some(failure) => {
+            let raw = scrape_failure_email(from_email_address, to_email_address, failure);
+            EmailContent::builder()
+                .raw(RawMessage::builder().data(Blob::new(raw)).build())
+                .build()
+        }
+        None => {
+            let subject_content = Content::builder()
+                .data(ERROR_SUBJECT)
+                .charset("UTF-8")
+                .build();
+
+            let body_content = Content::builder()
+                .data(format!("Error: {}", err))
+                .charset("UTF-8")
+                .build();
+
+            let body = Body::builder().text(body_content).build();
+
+            let msg = Message::builder()
+                .subject(subject_content)
+                .body(body)
+                .build();
+
+            EmailContent::builder().simple(msg).build()
+        }
+    };
+    if let Err(e) = aws_client
+        .send_email()
+        .from_email_address(from_email_address)
+        .destination(destination_email)
+        .content(email_content)
+        .send()
+        .await
+    {
+        log::error!("Error sending error email (haha): {}", e);
+    } else {
+        info!("Error email sent to {}", to_email_address);
+    }
+}
+
+/// Lets the admin know errors in `area` have stopped
+pub async fn send_recovery_email(
+    aws_client: &aws_sdk_sesv2::Client,
+    from_email_address: &str,
+    to_email_address: &str,
+    area: &str,
+    body: String,
+) {
+    let destination_email = Destination::builder()
+        .to_addresses(to_email_address)
         .build();
 
-    let body_content = Content::builder()
-        .data(format!("Error: {}", err))
+    let subject_content = Content::builder()
+        .data(format!("Recovered: {} with what bin service", area))
         .charset("UTF-8")
         .build();
 
+    let body_content = Content::builder().data(body).charset("UTF-8").build();
+
     let body = Body::builder().text(body_content).build();
 
     let msg = Message::builder()
@@ -49,8 +242,6 @@ pub async fn send_error_email(
         .body(body)
         .build();
 
-    dbg!(&msg);
-
     let email_content = EmailContent::builder().simple(msg).build();
     if let Err(e) = aws_client
         .send_email()
@@ -60,33 +251,79 @@ pub async fn send_error_email(
         .send()
         .await
     {
-        log::error!("Error sending error email (haha): {}", e);
+        log::error!("Error sending recovery email: {}", e);
     } else {
-        info!("Error email sent to {}", to_email_address);
+        info!("Recovery email sent to {}", to_email_address);
     }
 }
 
+/// A multipart MIME message with the failure's details in the body, and the screenshot and
+/// page source attached
+fn scrape_failure_email(
+    from_email_address: &str,
+    to_email_address: &str,
+    failure: &ScrapeFailure,
+) -> String {
+    let boundary = "what-bin-is-it-scrape-failure";
+    let mut email = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nMIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n",
+        from_email_address, to_email_address, ERROR_SUBJECT, boundary
+    );
+    email.push_str(&format!(
+        "--{}\r\nContent-Type: text/plain; charset=UTF-8\r\n\r\nStep: {}\r\nURL: {}\r\nError: {:#}\r\n",
+        boundary,
+        failure.step,
+        failure.url.as_deref().unwrap_or("unknown"),
+        failure.error
+    ));
+
+    let mut attachments: Vec<(&str, &str, &[u8])> = Vec::new();
+    if let Some(screenshot) = &failure.screenshot {
+        attachments.push(("screenshot.png", "image/png", screenshot));
+    }
+    if let Some(page_source) = &failure.page_source {
+        attachments.push(("page.html", "text/html", page_source.as_bytes()));
+    }
+    for (filename, content_type, data) in attachments {
+        email.push_str(&format!(
+            "--{}\r\nContent-Type: {}; name=\"{}\"\r\nContent-Disposition: attachment; filename=\"{}\"\r\nContent-Transfer-Encoding: base64\r\n\r\n",
+            boundary, content_type, filename, filename
+        ));
+        // Lines in an email can't be longer than 76 characters
+        let encoded = STANDARD.encode(data);
+        for line in encoded.as_bytes().chunks(76) {
+            email.push_str(std::str::from_utf8(line).unwrap());
+            email.push_str("\r\n");
+        }
+    }
+    email.push_str(&format!("--{}--\r\n", boundary));
+    return email;
+}
+
 fn build_bin_email_to_send(
     next_bin_collection: &NextBinCollection,
-    person: &User,
+    text: &ReminderText,
+    to_email_address: &str,
     aws_client: &aws_sdk_sesv2::Client,
     from_email_address: &str,
 ) -> aws_sdk_sesv2::operation::send_email::builders::SendEmailFluentBuilder {
-    let mut bin_email_body = String::new();
-
-    let subject = bins_subject(&next_bin_collection);
-
-    for bin_day in &next_bin_collection.bins {
-        let s = format!(
-            "{} bin is being collected on {}\n",
-            bin_day.bin, bin_day.date
-        );
-        bin_email_body.push_str(&s);
+    let mut bin_email_body = reminder_body(next_bin_collection, text.locale);
+    if let Some(done_link) = &text.done_link {
+        bin_email_body.push_str(&format!(
+            "\n{}: {}\n",
+            locale::let_everyone_know(text.locale),
+            done_link
+        ));
     }
 
-    let destination_email = Destination::builder().to_addresses(&person.email).build();
+    let destination_email = Destination::builder()
+        .to_addresses(to_email_address)
+        .build();
 
-    let subject_content = Content::builder().data(subject).charset("UTF-8").build();
+    let subject_content = Content::builder()
+        .data(&text.subject)
+        .charset("UTF-8")
+        .build();
 
     let body_content = Content::builder()
         .data(bin_email_body)
@@ -100,8 +337,6 @@ fn build_bin_email_to_send(
         .body(body)
         .build();
 
-    dbg!(&msg);
-
     let email_content = EmailContent::builder().simple(msg).build();
     aws_client
         .send_email()
@@ -110,22 +345,46 @@ fn build_bin_email_to_send(
         .content(email_content)
 }
 
-fn bins_subject(next_bin_collection: &NextBinCollection) -> String {
-    let mut subject = if next_bin_collection.bins.len() == 1 {
-        format!("{} bin", next_bin_collection.bins[0].bin)
-    } else {
-        let mut string = String::new();
-        for (i, bin_day) in next_bin_collection.bins.iter().enumerate() {
-            if i < next_bin_collection.bins.len() - 1 {
-                string.push_str(&format!("{}, ", bin_day.bin));
-            } else {
-                string.push_str(&format!("{} bins", bin_day.bin));
-            }
+/// One line per bin being collected, saying if the date's only projected
+pub fn reminder_body(next_bin_collection: &NextBinCollection, locale: Locale) -> String {
+    let mut body = String::new();
+    for bin_day in &next_bin_collection.bins {
+        body.push_str(&locale::bin_collected_on(bin_day.bin, bin_day.date, locale));
+        if let Some(confidence) = bin_day.projected {
+            body.push(' ');
+            body.push_str(&locale::projected(confidence, locale));
         }
-        string
+        body.push('\n');
+    }
+    return body;
+}
+
+/// `day_before` is whether the reminder goes out the night before, rather than on the day
+pub fn bins_subject(
+    next_bin_collection: &NextBinCollection,
+    day_before: bool,
+    locale: Locale,
+) -> String {
+    let bins: Vec<_> = next_bin_collection
+        .bins
+        .iter()
+        .map(|bin_day| bin_day.bin)
+        .collect();
+    return locale::bins_out(&bins, day_before, locale);
+}
+
+/// `bins_subject`, plus whose turn it is if the household has a rota
+pub fn reminder_subject(
+    next_bin_collection: &NextBinCollection,
+    on_duty: Option<&str>,
+    day_before: bool,
+    locale: Locale,
+) -> String {
+    let subject = bins_subject(next_bin_collection, day_before, locale);
+    return match on_duty {
+        Some(name) => locale::on_duty(name, &subject, locale),
+        None => subject,
     };
-    subject.push_str(" out tonight");
-    return subject;
 }
 
 #[cfg(test)]
@@ -140,25 +399,59 @@ mod tests {
     #[test]
     fn bins_subject_handles_multiple_and_single_bins() {
         let date = "2023-07-31";
-        let date = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").unwrap();
+        let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
         let blue_bin = NextBinCollectionDay {
             bin: Bin::Blue,
             date,
+            projected: None,
         };
         let brown_bin = NextBinCollectionDay {
             bin: Bin::Brown,
             date,
+            projected: None,
         };
 
         let mut next_bin_collection = NextBinCollection {
             bins: vec![blue_bin, brown_bin],
         };
-        let subject = bins_subject(&next_bin_collection);
+        let subject = bins_subject(&next_bin_collection, true, Locale::En);
         assert_eq!(subject, "Blue, Brown bins out tonight");
 
         next_bin_collection.bins.pop();
 
-        let subject = bins_subject(&next_bin_collection);
+        let subject = bins_subject(&next_bin_collection, true, Locale::En);
         assert_eq!(subject, "Blue bin out tonight");
+
+        let subject = reminder_subject(&next_bin_collection, Some("Alex"), true, Locale::En);
+        assert_eq!(subject, "Alex's turn: Blue bin out tonight");
+
+        let subject = reminder_subject(&next_bin_collection, None, false, Locale::En);
+        assert_eq!(subject, "Blue bin out this morning");
+
+        let subject = reminder_subject(&next_bin_collection, Some("Alex"), true, Locale::Pl);
+        assert_eq!(
+            subject,
+            "Dyżur: Alex. Wystaw dziś wieczorem kosz: niebieski"
+        );
+    }
+    #[test]
+    fn scrape_failure_email_attaches_what_was_captured() {
+        let failure = ScrapeFailure {
+            step: "enter postcode".to_string(),
+            url: Some("https://www.northlanarkshire.gov.uk/bin-collection-dates".to_string()),
+            screenshot: Some(vec![0x89, b'P', b'N', b'G']),
+            page_source: None,
+            error: scraper::ScrapeError::CookieBannerMissing,
+        };
+        let email = scrape_failure_email("from@example.com", "to@example.com", &failure);
+
+        assert!(email.starts_with("From: from@example.com\r\nTo: to@example.com\r\n"));
+        assert!(email.contains("Step: enter postcode\r\n"));
+        assert!(email.contains("URL: https://www.northlanarkshire.gov.uk/bin-collection-dates\r\n"));
+        assert!(email.contains("filename=\"screenshot.png\""));
+        assert!(email.contains("\r\n\r\niVBORw==\r\n"));
+        // Only attaches what there is
+        assert!(!email.contains("page.html"));
+        assert!(email.ends_with("--what-bin-is-it-scrape-failure--\r\n"));
     }
 }
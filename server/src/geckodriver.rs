@@ -0,0 +1,321 @@
+//! Runs geckodriver as a child of the server, rather than trusting a separate service to be up.
+//! Each driver is checked before every run, restarted if it's died or got stuck, and restarted
+//! anyway once it's been running for a while, as it tends to go bad after a few days.
+
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Error};
+use log::{error, info, warn};
+use serde::Deserialize;
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+
+/// How long a new driver gets to start answering
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct GeckodriverConfig {
+    /// The geckodriver binary
+    pub path: String,
+    /// Each driver gets a new directory in here for its browser profiles
+    pub profiles_dir: PathBuf,
+    pub count: usize,
+    /// Drivers older than this are restarted before the next run
+    pub max_age: Duration,
+}
+
+struct ManagedDriver {
+    child: Child,
+    port: u16,
+    profile_dir: PathBuf,
+    started: Instant,
+}
+
+impl ManagedDriver {
+    fn url(&self) -> String {
+        return format!("http://127.0.0.1:{}", self.port);
+    }
+
+    async fn stop(mut self) {
+        if let Err(e) = self.child.kill().await {
+            warn!("Could not kill geckodriver on port {}: {}", self.port, e);
+        }
+        if let Err(e) = std::fs::remove_dir_all(&self.profile_dir) {
+            warn!(
+                "Could not remove geckodriver profiles {}: {}",
+                self.profile_dir.display(),
+                e
+            );
+        }
+        info!("Stopped geckodriver on port {}", self.port);
+    }
+}
+
+#[derive(Deserialize)]
+struct StatusResponse {
+    value: Status,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    /// False while it has a session open, which between runs means one was never closed
+    ready: bool,
+}
+
+/// The drivers the server has started. A slot is empty when its driver couldn't be started,
+/// and it's tried again before the next run
+#[derive(Clone)]
+pub struct Geckodrivers {
+    config: Arc<GeckodriverConfig>,
+    drivers: Arc<Mutex<Vec<Option<ManagedDriver>>>>,
+}
+
+impl Geckodrivers {
+    pub async fn start(config: GeckodriverConfig, http_client: &reqwest::Client) -> Geckodrivers {
+        let mut drivers = Vec::new();
+        for _ in 0..config.count.max(1) {
+            drivers.push(start_driver(&config, http_client).await.ok());
+        }
+        return Geckodrivers {
+            config: Arc::new(config),
+            drivers: Arc::new(Mutex::new(drivers)),
+        };
+    }
+
+    /// Makes sure every driver is healthy and not overdue a restart, and returns the URLs of
+    /// the ones that are. Only call between runs, as a driver being used by a run isn't ready,
+    /// which is why `open_scrape_pool` calls this under the run lock
+    pub async fn urls(&self, http_client: &reqwest::Client) -> Vec<String> {
+        let mut drivers = self.drivers.lock().await;
+        let mut urls = Vec::new();
+        for slot in drivers.iter_mut() {
+            if let Some(driver) = slot.take() {
+                match check_driver(driver, &self.config, http_client).await {
+                    Ok(driver) => *slot = Some(driver),
+                    Err(e) => warn!("Restarting geckodriver: {}", e),
+                }
+            }
+            if slot.is_none() {
+                *slot = start_driver(&self.config, http_client).await.ok();
+            }
+            if let Some(driver) = slot {
+                urls.push(driver.url());
+            }
+        }
+        return urls;
+    }
+
+    pub async fn shutdown(&self) {
+        let mut drivers = self.drivers.lock().await;
+        for driver in drivers.iter_mut().filter_map(|slot| slot.take()) {
+            driver.stop().await;
+        }
+    }
+}
+
+/// Passes the driver back if it's fine to use, otherwise stops it
+async fn check_driver(
+    mut driver: ManagedDriver,
+    config: &GeckodriverConfig,
+    http_client: &reqwest::Client,
+) -> Result<ManagedDriver, Error> {
+    let exited = driver.child.try_wait().ok().flatten();
+    let mut problem = restart_reason(exited, driver.started.elapsed(), config.max_age);
+    // Only worth asking a driver that's still meant to be used
+    if problem.is_none() {
+        problem = status_problem(driver_status(&driver.url(), http_client).await);
+    }
+    return match problem {
+        None => Ok(driver),
+        Some(problem) => {
+            let port = driver.port;
+            driver.stop().await;
+            Err(anyhow!("geckodriver on port {} {}", port, problem))
+        }
+    };
+}
+
+/// Why a driver needs restarting, going by its process alone
+fn restart_reason(exited: Option<ExitStatus>, age: Duration, max_age: Duration) -> Option<String> {
+    if let Some(status) = exited {
+        return Some(format!("exited with {}", status));
+    }
+    if age >= max_age {
+        return Some(format!("running for {}h", age.as_secs() / 3600));
+    }
+    return None;
+}
+
+/// Why a driver needs restarting, going by what it said at `/status`
+fn status_problem(ready: Result<bool, Error>) -> Option<String> {
+    return match ready {
+        Ok(true) => None,
+        Ok(false) => Some("not ready, so a browser was left open".to_string()),
+        Err(e) => Some(format!("not responding: {}", e)),
+    };
+}
+
+async fn driver_status(url: &str, http_client: &reqwest::Client) -> Result<bool, Error> {
+    let status: StatusResponse = http_client
+        .get(format!("{}/status", url))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    return Ok(status.value.ready);
+}
+
+async fn start_driver(
+    config: &GeckodriverConfig,
+    http_client: &reqwest::Client,
+) -> Result<ManagedDriver, Error> {
+    let result = spawn_driver(config, http_client, STARTUP_TIMEOUT).await;
+    if let Err(e) = &result {
+        error!("Could not start geckodriver: {}", e);
+    }
+    return result;
+}
+
+async fn spawn_driver(
+    config: &GeckodriverConfig,
+    http_client: &reqwest::Client,
+    startup_timeout: Duration,
+) -> Result<ManagedDriver, Error> {
+    let port = free_port()?;
+    let profile_dir = config.profiles_dir.join(format!(
+        "{}-{}",
+        port,
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    ));
+    std::fs::create_dir_all(&profile_dir)?;
+
+    let child = Command::new(&config.path)
+        .arg("--port")
+        .arg(port.to_string())
+        .arg("--profile-root")
+        .arg(&profile_dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // In case the server goes down without shutting the drivers down first
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut driver = ManagedDriver {
+        child,
+        port,
+        profile_dir,
+        started: Instant::now(),
+    };
+
+    let deadline = Instant::now() + startup_timeout;
+    loop {
+        if let Ok(Some(status)) = driver.child.try_wait() {
+            driver.stop().await;
+            return Err(anyhow!("geckodriver exited on startup with {}", status));
+        }
+        if let Ok(true) = driver_status(&driver.url(), http_client).await {
+            break;
+        }
+        if Instant::now() >= deadline {
+            driver.stop().await;
+            return Err(anyhow!(
+                "geckodriver didn't answer within {:?}",
+                startup_timeout
+            ));
+        }
+        tokio::time::sleep(STARTUP_POLL_INTERVAL).await;
+    }
+    info!("Started geckodriver on port {}", port);
+    return Ok(driver);
+}
+
+/// A port nothing's listening on right now. Something else could take it before geckodriver
+/// does, in which case it fails to start and gets another port before the next run
+fn free_port() -> Result<u16, Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    return Ok(listener.local_addr()?.port());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn drivers_are_restarted_when_they_exit_get_old_or_stop_answering() {
+        assert_eq!(restart_reason(None, Duration::from_secs(60), MAX_AGE), None);
+        assert_eq!(
+            restart_reason(None, MAX_AGE, MAX_AGE),
+            Some("running for 24h".to_string())
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            let exited = ExitStatus::from_raw(0);
+            assert!(restart_reason(Some(exited), Duration::ZERO, MAX_AGE)
+                .unwrap()
+                .starts_with("exited with"));
+        }
+
+        assert_eq!(status_problem(Ok(true)), None);
+        assert_eq!(
+            status_problem(Ok(false)),
+            Some("not ready, so a browser was left open".to_string())
+        );
+        assert!(status_problem(Err(anyhow!("connection refused")))
+            .unwrap()
+            .starts_with("not responding"));
+    }
+
+    /// A geckodriver that runs `script` instead
+    #[cfg(unix)]
+    fn stub_driver(name: &str, script: &str) -> GeckodriverConfig {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("geckodriver-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("geckodriver");
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        return GeckodriverConfig {
+            path: path.to_string_lossy().to_string(),
+            profiles_dir: dir.join("profiles"),
+            count: 1,
+            max_age: MAX_AGE,
+        };
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn drivers_that_exit_or_never_answer_fail_to_start() {
+        let http_client = reqwest::Client::new();
+
+        let exits = stub_driver("exits", "exit 3");
+        let err = spawn_driver(&exits, &http_client, STARTUP_TIMEOUT)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("geckodriver exited on startup"));
+
+        let hangs = stub_driver("hangs", "exec sleep 30");
+        let err = spawn_driver(&hangs, &http_client, Duration::from_millis(500))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("geckodriver didn't answer"));
+
+        // Neither leaves its browser profiles behind
+        for config in [exits, hangs] {
+            let profiles = std::fs::read_dir(&config.profiles_dir).unwrap();
+            assert_eq!(profiles.count(), 0);
+            std::fs::remove_dir_all(config.profiles_dir.parent().unwrap()).unwrap();
+        }
+    }
+}
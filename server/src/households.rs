@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Error};
+use chrono::NaiveDate;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+
+/// An address that gets scraped once, shared by every user and webhook that belongs to it
+#[derive(Debug, Clone)]
+pub struct Household {
+    pub id: i64,
+    /// Just for the admin pages, e.g "The flat"
+    pub name: String,
+    pub postcode: String,
+    pub address: String,
+    /// When to send a second, louder reminder if nobody has said the bins are out, in the same
+    /// form as a user's `reminder_offset_minutes`. `None` means never
+    pub escalation_offset_minutes: Option<i64>,
+    /// Someone outside the household who also gets the second reminder
+    pub escalation_email: Option<String>,
+    pub last_escalated_for: Option<NaiveDate>,
+    /// Why the council site couldn't find the address on the last scrape, if it couldn't
+    pub address_problem: Option<String>,
+}
+
+const HOUSEHOLD_COLUMNS: &str = "id, name, postcode, address, escalation_offset_minutes, escalation_email, last_escalated_for, address_problem";
+
+fn household_from_row(row: SqliteRow) -> Household {
+    return Household {
+        id: row.get("id"),
+        name: row.get("name"),
+        postcode: row.get("postcode"),
+        address: row.get("address"),
+        escalation_offset_minutes: row.get("escalation_offset_minutes"),
+        escalation_email: row.get("escalation_email"),
+        last_escalated_for: row.get("last_escalated_for"),
+        address_problem: row.get("address_problem"),
+    };
+}
+
+pub async fn create_household(
+    pool: &SqlitePool,
+    name: &str,
+    postcode: &str,
+    address: &str,
+) -> Result<Household, Error> {
+    let name = match name.trim() {
+        "" => address,
+        name => name,
+    };
+    let id = sqlx::query("INSERT INTO households (name, postcode, address) VALUES (?1, ?2, ?3)")
+        .bind(name)
+        .bind(postcode)
+        .bind(address)
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+
+    return Ok(Household {
+        id,
+        name: name.to_string(),
+        postcode: postcode.to_string(),
+        address: address.to_string(),
+        escalation_offset_minutes: None,
+        escalation_email: None,
+        last_escalated_for: None,
+        address_problem: None,
+    });
+}
+
+pub async fn get_household(pool: &SqlitePool, id: i64) -> Result<Household, Error> {
+    let household = sqlx::query(&format!(
+        "SELECT {} FROM households WHERE id = ?1",
+        HOUSEHOLD_COLUMNS
+    ))
+    .bind(id)
+    .map(household_from_row)
+    .fetch_optional(pool)
+    .await?;
+
+    return household.ok_or_else(|| anyhow!("No household with id {}", id));
+}
+
+pub async fn get_all_households(pool: &SqlitePool) -> Result<Vec<Household>, Error> {
+    let households = sqlx::query(&format!(
+        "SELECT {} FROM households ORDER BY name",
+        HOUSEHOLD_COLUMNS
+    ))
+    .map(household_from_row)
+    .fetch_all(pool)
+    .await?;
+
+    return Ok(households);
+}
+
+pub async fn set_escalation(
+    pool: &SqlitePool,
+    household_id: i64,
+    escalation_offset_minutes: Option<i64>,
+    escalation_email: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE households SET escalation_offset_minutes = ?1, escalation_email = ?2 WHERE id = ?3",
+    )
+    .bind(escalation_offset_minutes)
+    .bind(escalation_email)
+    .bind(household_id)
+    .execute(pool)
+    .await?;
+    return Ok(());
+}
+
+pub async fn set_household_escalated_for(
+    pool: &SqlitePool,
+    household_id: i64,
+    collection_date: NaiveDate,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE households SET last_escalated_for = ?1 WHERE id = ?2")
+        .bind(collection_date)
+        .bind(household_id)
+        .execute(pool)
+        .await?;
+    return Ok(());
+}
+
+/// `None` once the address has been found again
+pub async fn set_address_problem(
+    pool: &SqlitePool,
+    household_id: i64,
+    address_problem: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE households SET address_problem = ?1 WHERE id = ?2")
+        .bind(address_problem)
+        .bind(household_id)
+        .execute(pool)
+        .await?;
+    return Ok(());
+}
@@ -0,0 +1,222 @@
+//! Groups errors into incidents so a broken council site is one alert, not one per run. An
+//! incident is alerted about when it starts, and again when a later run of the same area works.
+
+use anyhow::Error;
+use log::{error, info};
+use scraper::ScrapeFailure;
+use sqlx::{Row, SqlitePool};
+
+use crate::canary::CanaryFailure;
+use crate::email_sender::{send_error_email, send_recovery_email};
+use crate::layout::LayoutChanged;
+use crate::AppState;
+
+/// What was being done when an error happened. Successful runs only resolve their own area
+pub const SCRAPE: &str = "scrape";
+pub const REMINDERS: &str = "reminders";
+pub const SCHEDULE_CHANGES: &str = "schedule changes";
+pub const ESCALATIONS: &str = "escalations";
+pub const CANARY: &str = "canary";
+pub const LAYOUT: &str = "site layout";
+
+#[derive(Debug)]
+pub struct Incident {
+    pub fingerprint: String,
+    pub summary: String,
+    pub first_seen: String,
+    pub occurrences: i64,
+}
+
+/// A short description of what went wrong, without anything that changes between runs
+fn error_kind(err: &Error) -> &'static str {
+    if let Some(failure) = err.downcast_ref::<CanaryFailure>() {
+        return failure.kind();
+    }
+    if err.downcast_ref::<LayoutChanged>().is_some() {
+        return "site layout changed";
+    }
+    if err.downcast_ref::<reqwest::Error>().is_some() {
+        return "http";
+    }
+    if err.downcast_ref::<sqlx::Error>().is_some() {
+        return "database";
+    }
+    return "other";
+}
+
+/// Errors with the same fingerprint are the same incident. Scraper failures count as scrape
+/// errors wherever they're reported from, and include the step they failed at
+pub fn fingerprint(area: &'static str, err: &Error) -> (&'static str, String) {
+    if let Some(failure) = err.downcast_ref::<ScrapeFailure>() {
+        return (
+            SCRAPE,
+            format!("{}/{}/{}", SCRAPE, failure.step, failure.error.kind()),
+        );
+    }
+    return (area, format!("{}/{}", area, error_kind(err)));
+}
+
+/// Records an occurrence of the error, returning whether it's started a new incident
+pub async fn record_occurrence(
+    pool: &SqlitePool,
+    area: &str,
+    fingerprint: &str,
+    summary: &str,
+) -> Result<bool, Error> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let updated = sqlx::query(
+        "UPDATE incidents SET last_seen = ?1, occurrences = occurrences + 1 WHERE fingerprint = ?2 AND resolved_at IS NULL",
+    )
+    .bind(&now)
+    .bind(fingerprint)
+    .execute(pool)
+    .await?;
+    if updated.rows_affected() > 0 {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "INSERT INTO incidents (area, fingerprint, summary, first_seen, last_seen) VALUES (?1, ?2, ?3, ?4, ?4)",
+    )
+    .bind(area)
+    .bind(fingerprint)
+    .bind(summary)
+    .bind(&now)
+    .execute(pool)
+    .await?;
+    return Ok(true);
+}
+
+/// Closes every open incident in the area, returning them
+pub async fn resolve_incidents(pool: &SqlitePool, area: &str) -> Result<Vec<Incident>, Error> {
+    let incidents = sqlx::query(
+        "UPDATE incidents SET resolved_at = ?1 WHERE area = ?2 AND resolved_at IS NULL RETURNING fingerprint, summary, first_seen, occurrences",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(area)
+    .map(|row: sqlx::sqlite::SqliteRow| Incident {
+        fingerprint: row.get("fingerprint"),
+        summary: row.get("summary"),
+        first_seen: row.get("first_seen"),
+        occurrences: row.get("occurrences"),
+    })
+    .fetch_all(pool)
+    .await?;
+    return Ok(incidents);
+}
+
+/// Emails the error, unless it's part of an incident that's already been alerted about
+pub(crate) async fn report_error(app_state: &AppState, area: &'static str, err: Error) {
+    if let Some(failure) = err.downcast_ref::<ScrapeFailure>() {
+        if failure.error.is_address_problem() {
+            // Nothing's broken, and the household has been told
+            info!("Not alerting about an address problem: {}", failure);
+            return;
+        }
+    }
+    let (area, fingerprint) = fingerprint(area, &err);
+    match record_occurrence(&app_state.pool, area, &fingerprint, &err.to_string()).await {
+        Ok(false) => {
+            info!("Already alerted about {}: {}", fingerprint, err);
+            return;
+        }
+        Ok(true) => info!("New incident {}", fingerprint),
+        // Better to alert twice than not at all
+        Err(e) => error!("Could not record incident {}: {}", fingerprint, e),
+    }
+    send_error_email(
+        &app_state.aws_client,
+        &app_state.from_email_address,
+        &app_state.error_email_address,
+        err,
+    )
+    .await;
+}
+
+/// Call after a run of the area worked, to let the admin know any incidents in it are over
+pub(crate) async fn report_success(app_state: &AppState, area: &str) {
+    let incidents = match resolve_incidents(&app_state.pool, area).await {
+        Ok(incidents) => incidents,
+        Err(e) => {
+            error!("Could not resolve {} incidents: {}", area, e);
+            return;
+        }
+    };
+    if incidents.is_empty() {
+        return;
+    }
+
+    let mut body = String::new();
+    for incident in &incidents {
+        body.push_str(&format!(
+            "{} - {} time(s) since {}\nFirst error: {}\n\n",
+            incident.fingerprint, incident.occurrences, incident.first_seen, incident.summary
+        ));
+    }
+    send_recovery_email(
+        &app_state.aws_client,
+        &app_state.from_email_address,
+        &app_state.error_email_address,
+        area,
+        body,
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::ScrapeError;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn repeated_errors_are_one_incident_until_resolved() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+
+        let failure: Error = ScrapeFailure {
+            step: "enter postcode".to_string(),
+            url: None,
+            screenshot: None,
+            page_source: None,
+            error: ScrapeError::SelectorMissing {
+                name: "postcode input".to_string(),
+                selector: "#address-finder-postcode-search-text".to_string(),
+            },
+        }
+        .into();
+        // Reported from a manual run, but it's still a scrape error
+        let (area, fingerprint) = fingerprint(REMINDERS, &failure);
+        assert_eq!(area, SCRAPE);
+        assert_eq!(fingerprint, "scrape/enter postcode/postcode input missing");
+
+        let summary = failure.to_string();
+        assert!(record_occurrence(&pool, area, &fingerprint, &summary)
+            .await
+            .unwrap());
+        assert!(!record_occurrence(&pool, area, &fingerprint, &summary)
+            .await
+            .unwrap());
+        // A different kind of failure is its own incident
+        assert!(
+            record_occurrence(&pool, REMINDERS, "reminders/http", "Bad gateway")
+                .await
+                .unwrap()
+        );
+
+        let resolved = resolve_incidents(&pool, SCRAPE).await.unwrap();
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].occurrences, 2);
+        assert!(resolve_incidents(&pool, SCRAPE).await.unwrap().is_empty());
+
+        // Happening again after recovering starts a new incident
+        assert!(record_occurrence(&pool, area, &fingerprint, &summary)
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,223 @@
+//! Spots the council site being redesigned by comparing the dates page's fingerprint with the
+//! last known good one for the same address. A change is alerted about until the site changes
+//! back, or the new layout is accepted on the health page once the scrape flow's been checked.
+
+use anyhow::Error;
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect};
+use log::error;
+use scraper::PageFingerprint;
+use sqlx::{Row, SqlitePool};
+
+use crate::canary::HEALTH_ROUTE;
+use crate::incidents::{self, report_error};
+use crate::{escape_html, AppState};
+
+pub const ACCEPT_LAYOUT_ROUTE: &str = "/health/accept_layout";
+
+/// An address whose dates page doesn't look like it used to
+#[derive(Debug)]
+pub struct LayoutChanged {
+    pub postcode: String,
+    pub address: String,
+    pub changes: Vec<String>,
+}
+
+impl std::fmt::Display for LayoutChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(
+            f,
+            "Site layout changed for {}, {}: {}",
+            self.address,
+            self.postcode,
+            self.changes.join("; ")
+        );
+    }
+}
+
+impl std::error::Error for LayoutChanged {}
+
+/// Records the fingerprint from a scrape of the address, and alerts if the layout has changed.
+/// Returns whether it had
+pub(crate) async fn check_layout(
+    app_state: &AppState,
+    postcode: &str,
+    address: &str,
+    fingerprint: Option<&PageFingerprint>,
+) -> bool {
+    let Some(fingerprint) = fingerprint else {
+        return false;
+    };
+    let changes = match record_layout(&app_state.pool, postcode, address, fingerprint).await {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!("Could not check the layout for {}: {}", address, e);
+            return false;
+        }
+    };
+    if changes.is_empty() {
+        return false;
+    }
+    let changed = LayoutChanged {
+        postcode: postcode.to_string(),
+        address: address.to_string(),
+        changes,
+    };
+    report_error(app_state, incidents::LAYOUT, changed.into()).await;
+    return true;
+}
+
+/// Stores the fingerprint and returns how it differs from the last known good one. The first
+/// fingerprint for an address is known good
+pub async fn record_layout(
+    pool: &SqlitePool,
+    postcode: &str,
+    address: &str,
+    fingerprint: &PageFingerprint,
+) -> Result<Vec<String>, Error> {
+    let known_good: Option<String> = sqlx::query(
+        "SELECT fingerprint FROM page_layouts WHERE postcode = ?1 AND address = ?2 AND known_good = 1 ORDER BY id DESC LIMIT 1",
+    )
+    .bind(postcode)
+    .bind(address)
+    .map(|row: sqlx::sqlite::SqliteRow| row.get("fingerprint"))
+    .fetch_optional(pool)
+    .await?;
+    let changes = match known_good {
+        Some(known_good) => fingerprint.changes_from(&serde_json::from_str(&known_good)?),
+        None => Vec::new(),
+    };
+
+    sqlx::query(
+        "INSERT INTO page_layouts (postcode, address, seen_at, fingerprint, known_good, changes) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(postcode)
+    .bind(address)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(serde_json::to_string(fingerprint)?)
+    .bind(changes.is_empty())
+    .bind((!changes.is_empty()).then(|| changes.join("; ")))
+    .execute(pool)
+    .await?;
+    return Ok(changes);
+}
+
+/// Makes the latest fingerprint for every address known good
+pub async fn accept_latest_layouts(pool: &SqlitePool) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE page_layouts SET known_good = 1 WHERE id IN (SELECT MAX(id) FROM page_layouts GROUP BY postcode, address)",
+    )
+    .execute(pool)
+    .await?;
+    return Ok(());
+}
+
+#[derive(Debug)]
+pub struct ChangedLayout {
+    pub postcode: String,
+    pub address: String,
+    pub seen_at: String,
+    pub changes: String,
+}
+
+/// Addresses whose latest fingerprint didn't match
+pub async fn get_changed_layouts(pool: &SqlitePool) -> Result<Vec<ChangedLayout>, Error> {
+    let changed = sqlx::query(
+        "SELECT postcode, address, seen_at, changes FROM page_layouts WHERE known_good = 0 AND id IN (SELECT MAX(id) FROM page_layouts GROUP BY postcode, address)",
+    )
+    .map(|row: sqlx::sqlite::SqliteRow| ChangedLayout {
+        postcode: row.get("postcode"),
+        address: row.get("address"),
+        seen_at: row.get("seen_at"),
+        changes: row.get("changes"),
+    })
+    .fetch_all(pool)
+    .await?;
+    return Ok(changed);
+}
+
+/// The layout part of the health page
+pub(crate) async fn layout_html(pool: &SqlitePool) -> String {
+    let mut html = "<h2>Site layout</h2>".to_string();
+    let changed = get_changed_layouts(pool).await.unwrap();
+    if changed.is_empty() {
+        html.push_str("<p>The dates page looks like it always has.</p>");
+        return html;
+    }
+    html.push_str("<ul>");
+    for layout in &changed {
+        html.push_str(&format!(
+            "<li>{}, {} - changed at {}: {}</li>",
+            escape_html(&layout.address),
+            escape_html(&layout.postcode),
+            layout.seen_at,
+            escape_html(&layout.changes)
+        ));
+    }
+    html.push_str(&format!(
+        "</ul><p>Once the scrape flow's been checked against the new layout, accept it so it's compared with from now on.</p>
+        <form method='post' action='{}'><input type='submit' value='Accept the new layout'></form>",
+        ACCEPT_LAYOUT_ROUTE
+    ));
+    return html;
+}
+
+/// The incident is left for the next run to resolve, in case the site's still changing
+pub(crate) async fn submit_accept_layout(State(app_state): State<AppState>) -> impl IntoResponse {
+    accept_latest_layouts(&app_state.pool).await.unwrap();
+    return Redirect::to(HEALTH_ROUTE);
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::SectionShape;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    fn fingerprint(dates: usize) -> PageFingerprint {
+        return PageFingerprint {
+            sections: vec![SectionShape {
+                class: "waste-type--general-waste".to_string(),
+                dates,
+            }],
+            form_fields: vec!["edit-submit".to_string()],
+        };
+    }
+
+    #[tokio::test]
+    async fn changes_are_reported_until_accepted() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+        let record = |dates| {
+            let pool = pool.clone();
+            return async move {
+                return record_layout(&pool, "ML6 0AA", "1 Main Street", &fingerprint(dates))
+                    .await
+                    .unwrap();
+            };
+        };
+
+        assert!(record(3).await.is_empty());
+        assert!(record(2).await.is_empty());
+        // Still compared with the last known good one, so it keeps being reported
+        assert_eq!(record(0).await.len(), 1);
+        assert_eq!(record(0).await.len(), 1);
+        assert_eq!(get_changed_layouts(&pool).await.unwrap().len(), 1);
+
+        accept_latest_layouts(&pool).await.unwrap();
+        assert!(get_changed_layouts(&pool).await.unwrap().is_empty());
+        assert!(record(0).await.is_empty());
+        // Another address has its own known good layout
+        assert!(
+            record_layout(&pool, "ML1 1AA", "2 High Street", &fingerprint(0))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
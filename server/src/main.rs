@@ -1,18 +1,17 @@
-// TODO: If geckodriver continues to be a pain in prod, maybe setup cronjob to restart geckodriver
-// every day or something?
 #![allow(clippy::needless_return)]
 
 use anyhow::Error;
+use std::env;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
-use std::{dbg, env};
 
 use aws_config::meta::region::RegionProviderChain;
 use aws_sdk_sesv2::Client;
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::http::Request;
+use axum::http::StatusCode;
 use axum::middleware::Next;
 use axum::response::{Html, IntoResponse, Redirect, Response};
 use axum::routing::{get, post};
@@ -22,12 +21,15 @@ use axum::TypedHeader;
 use axum_extra::extract::cookie::Cookie;
 use axum_extra::extract::CookieJar;
 use axum_macros::debug_handler;
-use clokwerk::AsyncScheduler;
-use clokwerk::Job;
-use log::info;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use log::{error, info, warn};
 use rand::distributions::Alphanumeric;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
+use scraper::{
+    Browser, BrowserConfig, Politeness, RateLimiter, ScrapeError, ScrapeFailure, ScrapePool,
+    ScrapeReport, StepTimeouts,
+};
 use serde::Deserialize;
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::sqlite::SqliteRow;
@@ -35,12 +37,48 @@ use sqlx::Row;
 use sqlx::SqlitePool;
 use tokio::sync::Mutex;
 
-use bin_stuff::next_bin_collection_date;
+use bin_stuff::cadence::next_collection_with_projections;
+use bin_stuff::locale::{self, Locale};
+use bin_stuff::schedule_changes::{diff_schedules, ScheduleChange};
+use bin_stuff::NextBinCollection;
 use bin_stuff::User;
+use bin_stuff::{Bin, BinDates};
+use chrono::{NaiveDate, NaiveTime};
 
-use crate::email_sender::{email_user, send_error_email};
+use crate::acknowledgements::AcknowledgeLinks;
+use crate::bounces::SnsVerifier;
+use crate::canary::{parse_canary_addresses, CanaryConfig};
+use crate::email_sender::{
+    email_address_problem, email_schedule_changes, email_user, reminder_subject,
+    schedule_changes_subject, ReminderText,
+};
+use crate::geckodriver::{GeckodriverConfig, Geckodrivers};
+use crate::households::{
+    create_household, get_all_households, get_household, set_address_problem, set_escalation,
+    Household,
+};
+use crate::push_sender::{push_message_to_user, push_to_user, VapidConfig};
+use crate::scrape_flow::ScrapeFlowSource;
+use crate::sms_sender::{sms_usage_this_month, text_user, validate_phone_number, SmsConfig};
+use crate::webhook_sender::{
+    check_webhook_url, post_schedule_changes, post_to_webhook, Webhook, WebhookKind,
+};
 
+pub mod acknowledgements;
+pub mod bounces;
+pub mod canary;
 pub mod email_sender;
+pub mod geckodriver;
+pub mod households;
+pub mod incidents;
+pub mod layout;
+pub mod push_sender;
+pub mod pwa;
+pub mod rota;
+pub mod scheduler;
+pub mod scrape_flow;
+pub mod sms_sender;
+pub mod webhook_sender;
 
 // TODO: Dry run without emails
 
@@ -56,16 +94,43 @@ pub mod email_sender;
 struct AppState {
     pool: SqlitePool,
     aws_client: Client,
+    http_client: reqwest::Client,
+    sms_config: Option<SmsConfig>,
+    vapid_config: Option<VapidConfig>,
+    acknowledge_links: Option<AcknowledgeLinks>,
+    sns_verifier: Option<SnsVerifier>,
     from_email_address: String,
     error_email_address: String,
-    geckodriver_url: String,
+    /// Every geckodriver or chromedriver to scrape with
+    driver_urls: Vec<String>,
+    /// Started by the server, in which case they're used instead of `driver_urls`
+    geckodrivers: Option<Geckodrivers>,
+    /// How many addresses are scraped at once
+    scrape_concurrency: usize,
+    browser: BrowserConfig,
+    scrape_flow: ScrapeFlowSource,
+    step_timeouts: StepTimeouts,
+    /// Every lookup on the council site waits on this, whichever run it's for
+    scrape_limiter: Arc<RateLimiter>,
+    /// Where screenshots and page source from failed scrapes are kept
+    failures_dir: PathBuf,
+    /// Known addresses scraped daily to check the council site still works
+    canary: Option<CanaryConfig>,
     admin_password: String,
     current_session_id: Arc<Mutex<Option<String>>>,
+    /// Held by whichever run has the browsers open, from checking the drivers until the
+    /// pool's closed
+    scrape_run_lock: Arc<Mutex<()>>,
 }
 
-const USERS_ROUTE: &'static str = "/users";
-const CREATE_USER_ROUTE: &'static str = "/create_user";
-const RUN_SCRAPER_NOW_ROUTE: &'static str = "/run";
+const USERS_ROUTE: &str = "/users";
+const CREATE_USER_ROUTE: &str = "/create_user";
+const RUN_SCRAPER_NOW_ROUTE: &str = "/run";
+const WEBHOOKS_ROUTE: &str = "/webhooks";
+const CREATE_WEBHOOK_ROUTE: &str = "/create_webhook";
+const HOUSEHOLDS_ROUTE: &str = "/households";
+const CREATE_HOUSEHOLD_ROUTE: &str = "/create_household";
+const HOUSEHOLD_ESCALATION_ROUTE: &str = "/households/escalation";
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -83,20 +148,197 @@ async fn main() -> Result<(), Error> {
     let _aws_secret_access_key =
         env::var("AWS_SECRET_ACCESS_KEY").expect("AWS_SECRET_ACCESS_KEY must be specified");
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be specified");
-    let geckodriver_url_default = "http://127.0.0.1:4444".to_string();
-    let geckodriver_url = match env::var("GECKODRIVER_URL") {
-        Ok(url) => url,
+
+    let browser = BrowserConfig::from_env()?;
+    info!("Scraping with {:?}", browser.browser);
+    match &browser.user_agent {
+        Some(user_agent) => info!("Scraping as {}", user_agent),
+        None => info!("Scraping with the browser's own user agent"),
+    }
+
+    // Named for geckodriver, but it's chromedriver's address when scraping with Chrome
+    let driver_urls: Vec<String> = match env::var("GECKODRIVER_URL") {
+        Ok(urls) => urls
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect(),
         Err(_) => {
+            let driver_url_default = browser.browser.default_driver_url();
             info!(
                 "GECKODRIVER_URL was not specified. Defaulting to {}",
-                geckodriver_url_default
+                driver_url_default
             );
-            geckodriver_url_default
+            vec![driver_url_default.to_string()]
         }
     };
+    let scrape_concurrency = match env::var("SCRAPE_CONCURRENCY") {
+        Ok(concurrency) => concurrency
+            .parse()
+            .expect("SCRAPE_CONCURRENCY must be a number"),
+        Err(_) => driver_urls.len(),
+    };
+
+    let step_timeouts: StepTimeouts = match env::var("SCRAPE_STEP_TIMEOUTS") {
+        Ok(timeouts) => timeouts
+            .parse()
+            .expect("SCRAPE_STEP_TIMEOUTS must be like default=10,select address=30"),
+        Err(_) => StepTimeouts::default(),
+    };
+
+    let default_politeness = Politeness::default();
+    let politeness = Politeness {
+        min_delay: match env::var("SCRAPE_MIN_DELAY_SECONDS") {
+            Ok(seconds) => Duration::from_secs(
+                seconds
+                    .parse()
+                    .expect("SCRAPE_MIN_DELAY_SECONDS must be a number"),
+            ),
+            Err(_) => default_politeness.min_delay,
+        },
+        jitter: match env::var("SCRAPE_JITTER_SECONDS") {
+            Ok(seconds) => Duration::from_secs(
+                seconds
+                    .parse()
+                    .expect("SCRAPE_JITTER_SECONDS must be a number"),
+            ),
+            Err(_) => default_politeness.jitter,
+        },
+        max_per_hour: match env::var("SCRAPE_MAX_PER_HOUR") {
+            Ok(max) => match max.parse().expect("SCRAPE_MAX_PER_HOUR must be a number") {
+                0 => None,
+                max => Some(max),
+            },
+            Err(_) => default_politeness.max_per_hour,
+        },
+    };
+    info!("Scraping politely with {:?}", politeness);
+    let scrape_limiter = Arc::new(RateLimiter::new(politeness));
+
+    let scrape_flow_path = env::var("SCRAPE_FLOW_FILE").ok().map(PathBuf::from);
+    if scrape_flow_path.is_none() {
+        info!("SCRAPE_FLOW_FILE was not specified. Using the built in scrape flow");
+    }
+    let scrape_flow = ScrapeFlowSource::load(scrape_flow_path, &step_timeouts)?;
+
+    // Otherwise geckodriver is expected to be running already, at GECKODRIVER_URL
+    let geckodriver_config = match env::var("GECKODRIVER_PATH") {
+        Ok(_) if browser.browser != Browser::Firefox => {
+            panic!("GECKODRIVER_PATH can only be used when scraping with Firefox")
+        }
+        Ok(path) => Some(GeckodriverConfig {
+            path,
+            profiles_dir: PathBuf::from(
+                env::var("GECKODRIVER_PROFILES_DIR").unwrap_or("geckodriver-profiles".to_string()),
+            ),
+            count: scrape_concurrency,
+            max_age: Duration::from_secs(
+                env::var("GECKODRIVER_RESTART_HOURS")
+                    .map(|hours| {
+                        hours
+                            .parse::<u64>()
+                            .expect("GECKODRIVER_RESTART_HOURS must be a whole number")
+                    })
+                    .unwrap_or(24)
+                    * 60
+                    * 60,
+            ),
+        }),
+        Err(_) => {
+            info!("GECKODRIVER_PATH was not specified. Using the geckodriver at GECKODRIVER_URL");
+            None
+        }
+    };
+
+    let failures_dir = PathBuf::from(env::var("FAILURES_DIR").unwrap_or("failures".to_string()));
 
     let admin_password = env::var("ADMIN_PASSWORD").expect("ADMIN_PASSWORD must be specified");
 
+    // UK local time that every address gets scraped at each day
+    let scrape_time = match env::var("SCRAPE_TIME") {
+        Ok(time) => NaiveTime::parse_from_str(&time, "%H:%M").expect("SCRAPE_TIME must be HH:MM"),
+        Err(_) => {
+            info!("SCRAPE_TIME was not specified. Defaulting to 12:00");
+            NaiveTime::from_hms_opt(12, 0, 0).unwrap()
+        }
+    };
+
+    let canary = match env::var("CANARY_ADDRESSES") {
+        Ok(addresses) => {
+            let addresses = parse_canary_addresses(&addresses).expect(
+                "CANARY_ADDRESSES must be like ML6 0AA|1 Main Street;ML1 1AA|2 High Street",
+            );
+            let time = match env::var("CANARY_TIME") {
+                Ok(time) => {
+                    NaiveTime::parse_from_str(&time, "%H:%M").expect("CANARY_TIME must be HH:MM")
+                }
+                Err(_) => NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            };
+            Some(CanaryConfig { addresses, time })
+        }
+        Err(_) => {
+            info!("CANARY_ADDRESSES was not specified. No canary scrapes will be run");
+            None
+        }
+    };
+
+    let sms_config = match (
+        env::var("SMS_ACCOUNT_SID"),
+        env::var("SMS_AUTH_TOKEN"),
+        env::var("SMS_FROM_NUMBER"),
+    ) {
+        (Ok(account_sid), Ok(auth_token), Ok(from_number)) => Some(SmsConfig {
+            api_url: env::var("SMS_API_URL").unwrap_or("https://api.twilio.com".to_string()),
+            account_sid,
+            auth_token,
+            from_number,
+            monthly_cap: env::var("SMS_MONTHLY_CAP")
+                .map(|cap| cap.parse().expect("SMS_MONTHLY_CAP must be a number"))
+                .unwrap_or(100),
+            default_cost_per_message: env::var("SMS_COST_PER_MESSAGE")
+                .map(|cost| cost.parse().expect("SMS_COST_PER_MESSAGE must be a number"))
+                .unwrap_or(0.04),
+        }),
+        _ => {
+            info!("SMS_ACCOUNT_SID, SMS_AUTH_TOKEN and SMS_FROM_NUMBER not all specified. SMS reminders are disabled");
+            None
+        }
+    };
+
+    let vapid_config = match env::var("VAPID_PRIVATE_KEY") {
+        Ok(private_key) => {
+            let subject = env::var("VAPID_SUBJECT")
+                .expect("VAPID_SUBJECT must be specified when VAPID_PRIVATE_KEY is");
+            Some(VapidConfig::new(&private_key, subject)?)
+        }
+        Err(_) => {
+            info!("VAPID_PRIVATE_KEY was not specified. Push notifications are disabled");
+            None
+        }
+    };
+
+    // Needed to put "Done" links in reminders, as they're opened from outside
+    let acknowledge_links = match env::var("PUBLIC_URL") {
+        Ok(public_url) => {
+            let secret = env::var("ACK_LINK_SECRET")
+                .expect("ACK_LINK_SECRET must be specified when PUBLIC_URL is");
+            Some(AcknowledgeLinks::new(&public_url, &secret))
+        }
+        Err(_) => {
+            info!("PUBLIC_URL was not specified. Reminders won't have \"Done\" links");
+            None
+        }
+    };
+
+    // The SNS topic SES publishes bounces and complaints to
+    let sns_verifier = match env::var("SES_NOTIFICATIONS_TOPIC_ARN") {
+        Ok(topic_arn) => Some(SnsVerifier::new(&topic_arn)),
+        Err(_) => {
+            info!("SES_NOTIFICATIONS_TOPIC_ARN was not specified. Bounces and complaints won't stop emails");
+            None
+        }
+    };
+
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
     let config = aws_config::from_env().region(region_provider).load().await;
     let aws_client = Client::new(&config);
@@ -113,29 +355,103 @@ async fn main() -> Result<(), Error> {
         println!("Found {:?}", person.email);
     }
 
+    let http_client = reqwest::Client::new();
+    let geckodrivers = match geckodriver_config {
+        Some(config) => Some(Geckodrivers::start(config, &http_client).await),
+        None => None,
+    };
+
     let app_state = AppState {
         pool,
         aws_client,
+        http_client,
+        sms_config,
+        vapid_config,
+        acknowledge_links,
+        sns_verifier,
         from_email_address,
         error_email_address,
-        geckodriver_url,
+        driver_urls,
+        geckodrivers: geckodrivers.clone(),
+        scrape_concurrency,
+        browser,
+        scrape_flow,
+        step_timeouts,
+        scrape_limiter,
+        failures_dir,
+        canary,
         admin_password,
         current_session_id: Arc::new(Mutex::new(None)),
+        scrape_run_lock: Arc::new(Mutex::new(())),
     };
     let scheduler_app_state = app_state.clone();
 
     let unprotected_routes = Router::new()
         .route("/signin", get(sign_in_page))
         .route("/signin", post(sign_in_handler))
+        .route(pwa::APP_ROUTE, get(pwa::app_page))
+        .route(pwa::APP_MANIFEST_ROUTE, get(pwa::manifest))
+        .route(pwa::APP_SERVICE_WORKER_ROUTE, get(pwa::service_worker))
+        .route(pwa::SUBSCRIBE_SCRIPT_ROUTE, get(pwa::subscribe_script))
+        .route(pwa::APP_ICON_ROUTE, get(pwa::icon))
+        .route(pwa::APP_SUBSCRIBE_ROUTE, post(pwa::subscribe))
+        .route(pwa::APP_PREFERENCES_ROUTE, post(pwa::update_preferences))
+        .route(pwa::APP_ACKNOWLEDGE_ROUTE, post(pwa::acknowledge))
+        .route(
+            acknowledgements::DONE_ROUTE,
+            get(acknowledgements::done_page).post(acknowledgements::submit_done),
+        )
+        .route(
+            bounces::SES_NOTIFICATIONS_ROUTE,
+            post(bounces::ses_notification),
+        )
         .with_state(app_state.clone());
 
     let auth_protected_routes = Router::new()
         .route("/", get(root_page))
         .route(USERS_ROUTE, get(show_all_users_page))
+        .route(
+            bounces::RESUME_EMAILS_ROUTE,
+            post(bounces::submit_resume_emails),
+        )
         .route(
             CREATE_USER_ROUTE,
             get(show_create_user_form).post(submit_user_form),
         )
+        .route(HOUSEHOLDS_ROUTE, get(show_all_households_page))
+        .route(
+            CREATE_HOUSEHOLD_ROUTE,
+            get(show_create_household_form).post(submit_household_form),
+        )
+        .route(HOUSEHOLD_ESCALATION_ROUTE, post(submit_escalation_form))
+        .route(
+            acknowledgements::ACKNOWLEDGEMENTS_ROUTE,
+            get(acknowledgements::history_page),
+        )
+        .route(
+            rota::ROTA_ROUTE,
+            get(rota::rota_page).post(rota::submit_rota_form),
+        )
+        .route(rota::ROTA_SKIP_ROUTE, post(rota::skip))
+        .route(
+            scrape_flow::SCRAPE_FLOW_ROUTE,
+            get(scrape_flow::scrape_flow_page),
+        )
+        .route(
+            scrape_flow::RELOAD_SCRAPE_FLOW_ROUTE,
+            post(scrape_flow::submit_reload_scrape_flow),
+        )
+        .route(rota::ROTA_SWAP_ROUTE, post(rota::swap))
+        .route(canary::HEALTH_ROUTE, get(canary::health_page))
+        .route(
+            layout::ACCEPT_LAYOUT_ROUTE,
+            post(layout::submit_accept_layout),
+        )
+        .route(WEBHOOKS_ROUTE, get(show_all_webhooks_page))
+        .route(
+            CREATE_WEBHOOK_ROUTE,
+            get(show_create_webhook_form).post(submit_webhook_form),
+        )
         .route(RUN_SCRAPER_NOW_ROUTE, get(run_scraper_and_email_handler)) // Probably shouldn't be a get request,
         // but :shrug:
         .layer(axum::middleware::from_fn_with_state(
@@ -155,79 +471,446 @@ async fn main() -> Result<(), Error> {
         info!("run-now file not found, will not force a run immediately");
     }
 
-    let mut scheduler = AsyncScheduler::new();
-
-    scheduler
-        .every(clokwerk::Interval::Sunday)
-        // Assuming UTC time
-        .at("6:00 pm")
-        .run(move || scrape_and_email_stuff(scheduler_app_state.clone()));
-
-    let mut scheduler_poll_interval = tokio::time::interval(Duration::from_secs(60));
-    tokio::spawn(async move {
-        loop {
-            scheduler_poll_interval.tick().await;
-            info!("Running any pending scheduled jobs");
-            scheduler.run_pending().await;
-        }
-    });
+    tokio::spawn(scheduler::run_scheduler(scheduler_app_state, scrape_time));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
 
     info!("Listening on {}", &addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
 
+    if let Some(geckodrivers) = geckodrivers {
+        geckodrivers.shutdown().await;
+    }
+    return Ok(());
+}
+
+/// Ctrl+C, or systemd stopping the service
+async fn shutdown_signal() {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("Could not listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+    info!("Shutting down");
+}
+
+/// Scrapes the dates for a household and stores them, letting everyone in the household know
+/// if the dates have changed since the last scrape
+async fn scrape_and_store(
+    app_state: &AppState,
+    scrape_pool: &ScrapePool,
+    household: &Household,
+) -> Result<ScrapeReport, anyhow::Error> {
+    let report = match scrape_pool
+        .scrape(&household.postcode, &household.address)
+        .await
+    {
+        Ok(report) => report,
+        Err(failure) if failure.error.is_address_problem() => {
+            tell_household_about_address_problem(app_state, household, &failure.error).await?;
+            return Err(failure.into());
+        }
+        Err(failure) => {
+            match failure.save(&app_state.failures_dir) {
+                Ok(dir) => info!("Saved scrape failure to {}", dir.display()),
+                Err(e) => error!("Could not save scrape failure: {}", e),
+            }
+            return Err(failure.into());
+        }
+    };
+    if household.address_problem.is_some() {
+        info!("Found the address for {} again", household.name);
+        set_address_problem(&app_state.pool, household.id, None).await?;
+    }
+    let previous_bins = get_stored_bin_dates(&app_state.pool, household.id).await?;
+    store_bin_dates(&app_state.pool, household.id, &report.bins).await?;
+
+    let today = chrono::Utc::now()
+        .with_timezone(&scheduler::TIMEZONE)
+        .date_naive();
+    let changes = diff_schedules(&previous_bins, &report.bins, today);
+    if !changes.is_empty() {
+        info!("{} schedule changes for {}", changes.len(), household.name);
+        match notify_schedule_changes(app_state, household, &changes).await {
+            Ok(()) => incidents::report_success(app_state, incidents::SCHEDULE_CHANGES).await,
+            Err(e) => incidents::report_error(app_state, incidents::SCHEDULE_CHANGES, e).await,
+        }
+    }
+    for warning in &report.warnings {
+        warn!("{}: {}", household.name, warning);
+    }
+    return Ok(report);
+}
+
+/// Emails everyone in the household the first time the council site can't find their address,
+/// as it's theirs to fix rather than the admin's
+async fn tell_household_about_address_problem(
+    app_state: &AppState,
+    household: &Household,
+    problem: &ScrapeError,
+) -> Result<(), anyhow::Error> {
+    let problem_text = problem.to_string();
+    if household.address_problem.as_ref() == Some(&problem_text) {
+        return Ok(());
+    }
+    info!("Address problem for {}: {}", household.name, problem_text);
+    set_address_problem(&app_state.pool, household.id, Some(&problem_text)).await?;
+
+    let users = get_all_users(&app_state.pool).await?;
+    for user in users.iter().filter(|u| u.household_id == household.id) {
+        if user.gets_emails() {
+            email_address_problem(
+                user,
+                problem,
+                &app_state.aws_client,
+                &app_state.from_email_address,
+            )
+            .await?;
+        }
+    }
     return Ok(());
 }
 
-async fn actually_scrape_and_email(app_state: &AppState) -> Result<(), anyhow::Error> {
+async fn notify_schedule_changes(
+    app_state: &AppState,
+    household: &Household,
+    changes: &[ScheduleChange],
+) -> Result<(), anyhow::Error> {
+    let users = get_all_users(&app_state.pool).await?;
+    for user in users.iter().filter(|u| u.household_id == household.id) {
+        if user.gets_emails() {
+            email_schedule_changes(
+                user,
+                changes,
+                &app_state.aws_client,
+                &app_state.from_email_address,
+            )
+            .await?;
+        }
+        if let Some(vapid_config) = &app_state.vapid_config {
+            let body: Vec<String> = changes
+                .iter()
+                .map(|change| locale::schedule_change(change, user.locale))
+                .collect();
+            push_message_to_user(
+                user,
+                &schedule_changes_subject(changes, user.locale),
+                &body.join("\n"),
+                None,
+                vapid_config,
+                &app_state.http_client,
+                &app_state.pool,
+            )
+            .await?;
+        }
+    }
+
+    let webhooks = get_all_webhooks(&app_state.pool).await?;
+    for webhook in webhooks.iter().filter(|w| w.household_id == household.id) {
+        post_schedule_changes(webhook, changes, &app_state.http_client).await?;
+    }
+    return Ok(());
+}
+
+/// Starts the browsers for a run of scrapes, once any other run has closed its own. Checking
+/// the drivers can restart them, so that's done under the lock too
+async fn open_scrape_pool(app_state: &AppState) -> Result<ScrapePool, ScrapeFailure> {
+    let run_lock = app_state.scrape_run_lock.clone().lock_owned().await;
+    let driver_urls = match &app_state.geckodrivers {
+        Some(geckodrivers) => geckodrivers.urls(&app_state.http_client).await,
+        None => app_state.driver_urls.clone(),
+    };
+    return ScrapePool::open(
+        &driver_urls,
+        app_state.scrape_concurrency,
+        &app_state.browser,
+        app_state.scrape_flow.current(),
+        &app_state.step_timeouts,
+        app_state.scrape_limiter.clone(),
+    )
+    .await
+    .map(|scrape_pool| scrape_pool.holding(run_lock));
+}
+
+/// Whether scraping failed because the household's address is wrong, rather than the site
+pub(crate) fn is_address_problem(err: &anyhow::Error) -> bool {
+    return err
+        .downcast_ref::<ScrapeFailure>()
+        .is_some_and(|failure| failure.error.is_address_problem());
+}
+
+/// The same collection the scheduler would remind about next, so marking it as reminded
+/// doesn't skip or repeat a reminder. `None` if there's nothing coming up
+async fn scrape_next_bin_collection(
+    app_state: &AppState,
+    scrape_pool: &ScrapePool,
+    household: &Household,
+) -> Result<Option<NextBinCollection>, anyhow::Error> {
+    let bins = scrape_and_store(app_state, scrape_pool, household)
+        .await?
+        .bins;
+    let today = chrono::Utc::now()
+        .with_timezone(&scheduler::TIMEZONE)
+        .date_naive();
+    return Ok(next_collection_with_projections(
+        &bins,
+        today,
+        scheduler::MIN_REMINDER_CONFIDENCE,
+    ));
+}
+
+/// An error from a run started by hand, with the incident area it belongs to
+struct RunError {
+    area: &'static str,
+    error: anyhow::Error,
+}
+
+impl RunError {
+    fn scrape(error: impl Into<anyhow::Error>) -> RunError {
+        return RunError {
+            area: incidents::SCRAPE,
+            error: error.into(),
+        };
+    }
+}
+
+/// Anything that isn't scraping is part of sending the reminders
+impl From<anyhow::Error> for RunError {
+    fn from(error: anyhow::Error) -> RunError {
+        return RunError {
+            area: incidents::REMINDERS,
+            error,
+        };
+    }
+}
+
+/// Returns how many households were scraped
+async fn actually_scrape_and_email(app_state: &AppState) -> Result<usize, RunError> {
     let people_to_notify = get_all_users(&app_state.pool).await?;
-    for user in &people_to_notify {
-        // TODO: Email user if the service failed?
-        info!("Beginning scraping for {}", user.email);
-        // TODO: Store the scraped date somewhere?
-        //  What's the use? lets us separate emails i guess
-        let bins = scraper::get_stuff(
-            &user.postcode,
-            &user.address,
-            Some(app_state.geckodriver_url.clone()),
-        )
-        .await?;
-        let today = chrono::Utc::now().date_naive();
-        let next_bin_collection = next_bin_collection_date(
-            &bins,
-            today,
-            chrono::Weekday::Mon, // Assuming monday is collection date for now
-        );
+    let webhooks = get_all_webhooks(&app_state.pool).await?;
+    let households: Vec<Household> = get_all_households(&app_state.pool)
+        .await?
+        .into_iter()
+        .filter(|h| {
+            people_to_notify.iter().any(|u| u.household_id == h.id)
+                || webhooks.iter().any(|w| w.household_id == h.id)
+        })
+        .collect();
+    if households.is_empty() {
+        return Ok(0);
+    }
+
+    let scrape_pool = open_scrape_pool(app_state)
+        .await
+        .map_err(RunError::scrape)?;
+    // Stops at the first household that fails, like doing them one at a time would. A wrong
+    // address is the household's to fix, so only that household is skipped
+    let runs: Vec<_> = households
+        .iter()
+        .map(|household| {
+            scrape_and_email_household(
+                app_state,
+                &scrape_pool,
+                &people_to_notify,
+                &webhooks,
+                household,
+            )
+        })
+        .collect();
+    let result = stream::iter(runs)
+        .buffered(scrape_pool.size())
+        .try_collect::<Vec<bool>>()
+        .await;
+    scrape_pool.close().await;
+    return result.map(|scraped| scraped.into_iter().filter(|scraped| *scraped).count());
+}
+
+async fn scrape_and_email_household(
+    app_state: &AppState,
+    scrape_pool: &ScrapePool,
+    people_to_notify: &[User],
+    webhooks: &[Webhook],
+    household: &Household,
+) -> Result<bool, RunError> {
+    // TODO: Email user if the service failed?
+    info!("Beginning scraping for {}", household.name);
+    let next_bin_collection =
+        match scrape_next_bin_collection(app_state, scrape_pool, household).await {
+            Ok(Some(next_bin_collection)) => next_bin_collection,
+            Ok(None) => {
+                info!("No upcoming collections for {}", household.name);
+                return Ok(true);
+            }
+            Err(e) if is_address_problem(&e) => {
+                // The household has already been told
+                info!("Skipping {}: {}", household.name, e);
+                return Ok(false);
+            }
+            Err(e) => return Err(RunError::scrape(e)),
+        };
+    let on_duty = match next_bin_collection.bins.first() {
+        Some(bin_day) => rota::on_duty(&app_state.pool, household.id, bin_day.date).await?,
+        None => None,
+    };
+    let on_duty_name = on_duty
+        .and_then(|id| people_to_notify.iter().find(|u| u._id == id))
+        .map(|u| u.display_name());
+    for user in people_to_notify
+        .iter()
+        .filter(|u| u.household_id == household.id)
+    {
+        let text = reminder_text(
+            app_state,
+            household.id,
+            &next_bin_collection,
+            on_duty_name,
+            Some(user),
+            user.reminder_offset_minutes,
+        )?;
+        send_reminders_to_user(app_state, user, &next_bin_collection, &text).await?;
+        // So the scheduler doesn't send the same reminder again
+        if let Some(bin_day) = next_bin_collection.bins.first() {
+            set_user_reminded_for(&app_state.pool, user, bin_day.date).await?;
+        }
+    }
+    for webhook in webhooks.iter().filter(|w| w.household_id == household.id) {
+        let text = reminder_text(
+            app_state,
+            household.id,
+            &next_bin_collection,
+            on_duty_name,
+            None,
+            scheduler::DEFAULT_REMINDER_OFFSET_MINUTES,
+        )?;
+        post_to_webhook(webhook, &next_bin_collection, &text, &app_state.http_client).await?;
+        if let Some(bin_day) = next_bin_collection.bins.first() {
+            set_webhook_reminded_for(&app_state.pool, webhook, bin_day.date).await?;
+        }
+    }
+    return Ok(true);
+}
+
+/// The reminder for one recipient in their language, with a "Done" link if the server knows
+/// its public URL. `recipient` is `None` for shared channels, which get English.
+/// `offset_minutes` is when it's being sent, relative to the start of the collection day
+fn reminder_text(
+    app_state: &AppState,
+    household_id: i64,
+    next_bin_collection: &NextBinCollection,
+    on_duty: Option<&str>,
+    recipient: Option<&User>,
+    offset_minutes: i64,
+) -> Result<ReminderText, anyhow::Error> {
+    let locale = recipient.map(|user| user.locale).unwrap_or_default();
+    let user_id = recipient.map(|user| user._id);
+    let done_link = match (
+        &app_state.acknowledge_links,
+        next_bin_collection.bins.first(),
+    ) {
+        (Some(acknowledge_links), Some(bin_day)) => {
+            Some(acknowledge_links.link(household_id, bin_day.date, user_id)?)
+        }
+        _ => None,
+    };
+    return Ok(ReminderText {
+        subject: reminder_subject(next_bin_collection, on_duty, offset_minutes < 0, locale),
+        done_link,
+        locale,
+    });
+}
+
+/// Sends the reminder over every channel the user has
+async fn send_reminders_to_user(
+    app_state: &AppState,
+    user: &User,
+    next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
+) -> Result<(), anyhow::Error> {
+    if user.gets_emails() {
         info!("Beginning emailing for {}", user.email);
         // TODO: Keep track of users that have successfully been sent an email so a retry doesn't
         // happen unexpectedly
         email_user(
             user,
-            &next_bin_collection,
+            next_bin_collection,
+            text,
             &app_state.aws_client,
             &app_state.from_email_address,
         )
-        .await?
+        .await?;
+    }
+
+    if let Some(vapid_config) = &app_state.vapid_config {
+        push_to_user(
+            user,
+            next_bin_collection,
+            text,
+            vapid_config,
+            &app_state.http_client,
+            &app_state.pool,
+        )
+        .await?;
+    }
+
+    if let Some(sms_config) = &app_state.sms_config {
+        text_user(
+            user,
+            next_bin_collection,
+            text,
+            sms_config,
+            &app_state.http_client,
+            &app_state.pool,
+        )
+        .await?;
     }
     return Ok(());
 }
 
+async fn set_user_reminded_for(
+    pool: &SqlitePool,
+    user: &User,
+    collection_date: NaiveDate,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE emails SET last_reminded_for = ?1 WHERE id = ?2")
+        .bind(collection_date)
+        .bind(user._id)
+        .execute(pool)
+        .await?;
+    return Ok(());
+}
+
+async fn set_webhook_reminded_for(
+    pool: &SqlitePool,
+    webhook: &Webhook,
+    collection_date: NaiveDate,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE webhooks SET last_reminded_for = ?1 WHERE id = ?2")
+        .bind(collection_date)
+        .bind(webhook._id)
+        .execute(pool)
+        .await?;
+    return Ok(());
+}
+
 async fn scrape_and_email_stuff(app_state: AppState) {
     info!("Running email stuff now");
 
     // TODO: A dry run option would be nice to run an earlier scrape to alert for errors earlier
-    if let Err(e) = actually_scrape_and_email(&app_state).await {
-        send_error_email(
-            &app_state.aws_client,
-            &app_state.from_email_address,
-            &app_state.error_email_address,
-            e,
-        )
-        .await;
+    match actually_scrape_and_email(&app_state).await {
+        Ok(scraped) => {
+            // Like the scheduled scrape, only a run where every household was scraped counts
+            // as the scraper having recovered
+            if scraped > 0 {
+                incidents::report_success(&app_state, incidents::SCRAPE).await;
+            }
+            incidents::report_success(&app_state, incidents::REMINDERS).await;
+        }
+        Err(e) => incidents::report_error(&app_state, e.area, e.error).await,
     }
 }
 
@@ -237,52 +920,427 @@ async fn submit_user_form(
     Form(input): Form<CreateUser>,
 ) -> impl IntoResponse {
     let pool = app_state.pool;
-    let user = create_user(&pool, input).await.unwrap();
+    if let Err(e) = create_user(&pool, input).await {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Could not create user: {}", e),
+        )
+            .into_response();
+    }
     let redirect = Redirect::to(USERS_ROUTE).into_response();
     return redirect.into_response();
 }
 
 async fn create_user(pool: &SqlitePool, input: CreateUser) -> Result<User, Error> {
-    let id = sqlx::query("INSERT INTO emails (email, postcode, address) VALUES (?1, ?2, ?3)")
-        .bind(&input.email)
-        .bind(&input.postcode)
-        .bind(&input.address)
-        .execute(pool)
-        .await?
-        .last_insert_rowid();
+    // Empty form fields come through as empty strings
+    let phone_number = match input.phone_number.trim() {
+        "" => None,
+        phone_number => Some(phone_number.to_string()),
+    };
+    if let Some(phone_number) = &phone_number {
+        validate_phone_number(phone_number)?;
+    }
+
+    let name = match input.name.trim() {
+        "" => None,
+        name => Some(name.to_string()),
+    };
+
+    let locale = match input.locale.trim() {
+        "" => Locale::default(),
+        locale => locale.parse().map_err(Error::msg)?,
+    };
+    scheduler::check_reminder_offset(input.reminder_offset_minutes)?;
+
+    let app_token = generate_token(32);
+    let id = sqlx::query(
+        "INSERT INTO emails (email, name, household_id, phone_number, app_token, reminder_offset_minutes, locale) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )
+    .bind(&input.email)
+    .bind(&name)
+    .bind(input.household_id)
+    .bind(&phone_number)
+    .bind(&app_token)
+    .bind(input.reminder_offset_minutes)
+    .bind(locale.as_str())
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
 
     return Ok(User {
         _id: id,
         email: input.email,
-        postcode: input.postcode,
-        address: input.address,
+        name,
+        household_id: input.household_id,
+        phone_number,
+        app_token,
+        email_reminders: true,
+        reminder_offset_minutes: input.reminder_offset_minutes,
+        last_reminded_for: None,
+        locale,
+        email_suppressed_reason: None,
     });
 }
 
+const USER_COLUMNS: &str = "id, email, name, household_id, phone_number, app_token, email_reminders, reminder_offset_minutes, last_reminded_for, locale, email_suppressed_reason";
+
+fn user_from_row(row: SqliteRow) -> User {
+    return User {
+        _id: row.get("id"),
+        email: row.get("email"),
+        name: row.get("name"),
+        household_id: row.get("household_id"),
+        phone_number: row.get("phone_number"),
+        app_token: row.get("app_token"),
+        email_reminders: row.get("email_reminders"),
+        reminder_offset_minutes: row.get("reminder_offset_minutes"),
+        last_reminded_for: row.get("last_reminded_for"),
+        // Anything unrecognised falls back to English rather than losing the user
+        locale: row.get::<String, _>("locale").parse().unwrap_or_default(),
+        email_suppressed_reason: row.get("email_suppressed_reason"),
+    };
+}
+
+async fn get_user_by_app_token(pool: &SqlitePool, app_token: &str) -> Result<Option<User>, Error> {
+    let user = sqlx::query(&format!(
+        "SELECT {} FROM emails WHERE app_token = ?1",
+        USER_COLUMNS
+    ))
+    .bind(app_token)
+    .map(user_from_row)
+    .fetch_optional(pool)
+    .await?;
+
+    return Ok(user);
+}
+
+/// Replaces whatever was scraped for this household last time
+async fn store_bin_dates(
+    pool: &SqlitePool,
+    household_id: i64,
+    bins: &[BinDates],
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query("DELETE FROM bin_dates WHERE household_id = ?1")
+        .bind(household_id)
+        .execute(&mut *transaction)
+        .await?;
+    for bin_dates in bins {
+        for date in &bin_dates.dates {
+            sqlx::query("INSERT INTO bin_dates (household_id, bin, date) VALUES (?1, ?2, ?3)")
+                .bind(household_id)
+                .bind(bin_dates.bin.to_string())
+                .bind(date)
+                .execute(&mut *transaction)
+                .await?;
+        }
+    }
+    transaction.commit().await?;
+    return Ok(());
+}
+
+async fn get_stored_bin_dates(
+    pool: &SqlitePool,
+    household_id: i64,
+) -> Result<Vec<BinDates>, Error> {
+    let rows = sqlx::query("SELECT bin, date FROM bin_dates WHERE household_id = ?1 ORDER BY date")
+        .bind(household_id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut bins: Vec<BinDates> = Vec::new();
+    for row in rows {
+        let bin: Bin = row.get::<String, _>("bin").parse().map_err(Error::msg)?;
+        let date: NaiveDate = row.get("date");
+        match bins.iter_mut().find(|bin_dates| bin_dates.bin == bin) {
+            Some(bin_dates) => bin_dates.dates.push(date),
+            None => bins.push(BinDates {
+                bin,
+                dates: vec![date],
+            }),
+        }
+    }
+    return Ok(bins);
+}
+
+fn generate_token(length: usize) -> String {
+    let rng = StdRng::from_entropy();
+    return rng
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect();
+}
+
+/// For putting anything a user or the council site gave us into a page
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
 async fn get_all_users(pool: &SqlitePool) -> Result<Vec<User>, Error> {
     // TODO: Paging at some point
-    let users = sqlx::query("SELECT id, email, postcode, address FROM emails")
-        .map(|row: SqliteRow| User {
-            _id: row.get("id"),
-            email: row.get("email"),
-            postcode: row.get("postcode"),
-            address: row.get("address"),
-        })
+    let users = sqlx::query(&format!("SELECT {} FROM emails", USER_COLUMNS))
+        .map(user_from_row)
         .fetch_all(pool)
         .await?;
 
     return Ok(users);
 }
 
+#[debug_handler]
+async fn submit_webhook_form(
+    State(app_state): State<AppState>,
+    Form(input): Form<CreateWebhook>,
+) -> impl IntoResponse {
+    let pool = app_state.pool;
+    if let Err(e) = create_webhook(&pool, input).await {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Could not create webhook: {}", e),
+        )
+            .into_response();
+    }
+    let redirect = Redirect::to(WEBHOOKS_ROUTE).into_response();
+    return redirect.into_response();
+}
+
+async fn create_webhook(pool: &SqlitePool, input: CreateWebhook) -> Result<Webhook, Error> {
+    let kind: WebhookKind = input.kind.parse()?;
+    check_webhook_url(&input.url)?;
+    // So it's an error now rather than a webhook that never gets posted to
+    get_household(pool, input.household_id).await?;
+    let id = sqlx::query("INSERT INTO webhooks (kind, url, household_id) VALUES (?1, ?2, ?3)")
+        .bind(kind.as_str())
+        .bind(&input.url)
+        .bind(input.household_id)
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+
+    return Ok(Webhook {
+        _id: id,
+        kind,
+        url: input.url,
+        household_id: input.household_id,
+        last_reminded_for: None,
+    });
+}
+
+async fn get_all_webhooks(pool: &SqlitePool) -> Result<Vec<Webhook>, Error> {
+    let rows = sqlx::query("SELECT id, kind, url, household_id, last_reminded_for FROM webhooks")
+        .fetch_all(pool)
+        .await?;
+
+    let mut webhooks = Vec::new();
+    for row in rows {
+        let kind: String = row.get("kind");
+        webhooks.push(Webhook {
+            _id: row.get("id"),
+            kind: kind.parse()?,
+            url: row.get("url"),
+            household_id: row.get("household_id"),
+            last_reminded_for: row.get("last_reminded_for"),
+        });
+    }
+
+    return Ok(webhooks);
+}
+
+async fn show_all_webhooks_page(State(app_state): State<AppState>) -> Html<String> {
+    let webhooks = get_all_webhooks(&app_state.pool).await.unwrap();
+    let households = get_all_households(&app_state.pool).await.unwrap();
+    let descriptions: Vec<String> = webhooks
+        .iter()
+        .map(|w| {
+            format!(
+                "{} - {}",
+                w.kind.as_str(),
+                escape_html(household_name(&households, w.household_id))
+            )
+        })
+        .collect();
+    let mut html = "<ul><li>".to_string();
+
+    let output = descriptions.join("</li><li>");
+    html.push_str(&output);
+    html.push_str("</li></ul>");
+
+    return Html(html);
+}
+
+fn household_name(households: &[Household], household_id: i64) -> &str {
+    return households
+        .iter()
+        .find(|h| h.id == household_id)
+        .map(|h| h.name.as_str())
+        .unwrap_or("Unknown household");
+}
+
+async fn show_all_households_page(State(app_state): State<AppState>) -> Html<String> {
+    let households = get_all_households(&app_state.pool).await.unwrap();
+    let users = get_all_users(&app_state.pool).await.unwrap();
+    let webhooks = get_all_webhooks(&app_state.pool).await.unwrap();
+
+    let mut html = "<ul>".to_string();
+    for household in &households {
+        html.push_str(&format!(
+            "<li>{} - {}, {} - <a href='{}?household_id={}'>rota</a> - <a href='{}?household_id={}'>history</a><ul>",
+            escape_html(&household.name),
+            escape_html(&household.address),
+            escape_html(&household.postcode),
+            rota::ROTA_ROUTE,
+            household.id,
+            acknowledgements::ACKNOWLEDGEMENTS_ROUTE,
+            household.id
+        ));
+        if let Some(address_problem) = &household.address_problem {
+            html.push_str(&format!(
+                "<li><strong>Address problem: {}</strong></li>",
+                escape_html(address_problem)
+            ));
+        }
+        html.push_str(&format!(
+            r#"<li><form action="{}?household_id={}" method="post">
+                If nobody has said the bins are out, remind everyone again
+                <select name="escalation_offset_minutes">{}</select>
+                and also email <input type="text" name="escalation_email" value="{}">
+                <input type="submit" value="Save">
+            </form></li>"#,
+            HOUSEHOLD_ESCALATION_ROUTE,
+            household.id,
+            escalation_offset_options(household.escalation_offset_minutes),
+            escape_html(household.escalation_email.as_deref().unwrap_or(""))
+        ));
+        for user in users.iter().filter(|u| u.household_id == household.id) {
+            html.push_str(&format!("<li>{}</li>", escape_html(&user.email)));
+        }
+        for webhook in webhooks.iter().filter(|w| w.household_id == household.id) {
+            html.push_str(&format!("<li>{} webhook</li>", webhook.kind.as_str()));
+        }
+        html.push_str("</ul></li>");
+    }
+    html.push_str("</ul>");
+
+    return Html(html);
+}
+
+#[debug_handler]
+async fn submit_household_form(
+    State(app_state): State<AppState>,
+    Form(input): Form<CreateHousehold>,
+) -> impl IntoResponse {
+    if let Err(e) = create_household(
+        &app_state.pool,
+        &input.name,
+        &input.postcode,
+        &input.address,
+    )
+    .await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Could not create household: {}", e),
+        )
+            .into_response();
+    }
+    return Redirect::to(HOUSEHOLDS_ROUTE).into_response();
+}
+
+#[debug_handler]
+async fn submit_escalation_form(
+    State(app_state): State<AppState>,
+    Query(query): Query<HouseholdQuery>,
+    Form(input): Form<Escalation>,
+) -> impl IntoResponse {
+    // Empty form fields come through as empty strings
+    let escalation_offset_minutes = match input.escalation_offset_minutes.trim() {
+        "" => None,
+        offset => match offset.parse().map(scheduler::check_escalation_offset) {
+            Ok(Ok(offset)) => Some(offset),
+            _ => {
+                return (StatusCode::BAD_REQUEST, "Invalid escalation time").into_response();
+            }
+        },
+    };
+    let escalation_email = match input.escalation_email.trim() {
+        "" => None,
+        email => Some(email),
+    };
+    if let Err(e) = set_escalation(
+        &app_state.pool,
+        query.household_id,
+        escalation_offset_minutes,
+        escalation_email,
+    )
+    .await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Could not save escalation: {}", e),
+        )
+            .into_response();
+    }
+    return Redirect::to(HOUSEHOLDS_ROUTE).into_response();
+}
+
 async fn show_all_users_page(State(app_state): State<AppState>) -> Html<String> {
     let users = get_all_users(&app_state.pool).await.unwrap();
-    let user_emails: Vec<&str> = users.iter().map(|u| u.email.as_str()).collect();
+    let households = get_all_households(&app_state.pool).await.unwrap();
+    let user_descriptions: Vec<String> = users
+        .iter()
+        .map(|u| {
+            let mut description = escape_html(&u.email);
+            if let Some(phone_number) = &u.phone_number {
+                description.push_str(&format!(" ({})", escape_html(phone_number)));
+            }
+            description.push_str(&format!(
+                " - {}",
+                escape_html(household_name(&households, u.household_id))
+            ));
+            description.push_str(&format!(
+                " - <a href='{}?token={}'>app link</a>",
+                pwa::APP_ROUTE,
+                u.app_token
+            ));
+            if let Some(reason) = &u.email_suppressed_reason {
+                description.push_str(&format!(
+                    r#" - <strong>not emailed: {}</strong>
+                    <form action="{}?user_id={}" method="post" style="display:inline">
+                        <input type="submit" value="Resume emails">
+                    </form>"#,
+                    escape_html(reason),
+                    bounces::RESUME_EMAILS_ROUTE,
+                    u._id
+                ));
+            }
+            description
+        })
+        .collect();
     let mut html = "<ul><li>".to_string();
 
-    let output = user_emails.join("</li><li>");
+    let output = user_descriptions.join("</li><li>");
     html.push_str(&output);
     html.push_str("</li></ul>");
 
+    if let Some(sms_config) = &app_state.sms_config {
+        let (sent, cost) = sms_usage_this_month(&app_state.pool).await.unwrap();
+        html.push_str(&format!(
+            "<p>SMS this month: {}/{} sent, costing {:.2}</p>",
+            sent, sms_config.monthly_cap, cost
+        ));
+    }
+
     return Html(html);
 }
 
@@ -319,9 +1377,32 @@ async fn root_page(TypedHeader(cookie): TypedHeader<axum::headers::Cookie>) -> i
             "<li><a href='{}'>Run scraper and emails now</a></li>",
             RUN_SCRAPER_NOW_ROUTE
         );
+        let households_page_link =
+            format!("<li><a href='{}'>Households</a></li>", HOUSEHOLDS_ROUTE);
+        let create_household_link = format!(
+            "<li><a href='{}'>Create Household</a></li>",
+            CREATE_HOUSEHOLD_ROUTE
+        );
+        html.push_str(&households_page_link);
+        html.push_str(&create_household_link);
         html.push_str(&users_page_link);
         html.push_str(&create_user_link);
+        let webhooks_page_link = format!("<li><a href='{}'>Webhooks</a></li>", WEBHOOKS_ROUTE);
+        let create_webhook_link = format!(
+            "<li><a href='{}'>Create Webhook</a></li>",
+            CREATE_WEBHOOK_ROUTE
+        );
+        html.push_str(&webhooks_page_link);
+        html.push_str(&create_webhook_link);
         html.push_str(&run_link);
+        html.push_str(&format!(
+            "<li><a href='{}'>Scrape flow</a></li>",
+            scrape_flow::SCRAPE_FLOW_ROUTE
+        ));
+        html.push_str(&format!(
+            "<li><a href='{}'>Health</a></li>",
+            canary::HEALTH_ROUTE
+        ));
         html.push_str("</ul>");
         return Html(html).into_response();
     } else {
@@ -337,12 +1418,7 @@ async fn sign_in_handler(
 ) -> (CookieJar, impl IntoResponse) {
     // TODO: If session_id already set, do we need to do anything different?
     if input.password == app_state.admin_password {
-        let rng = StdRng::from_entropy();
-        let session_id: String = rng
-            .sample_iter(&Alphanumeric)
-            .take(32)
-            .map(char::from)
-            .collect();
+        let session_id = generate_token(32);
 
         let cookies = cookies.add(Cookie::new("session_id", session_id.clone()));
 
@@ -358,8 +1434,12 @@ async fn sign_in_handler(
     }
 }
 
+/// Starts a run in the background, as it can take hours at a polite rate
 async fn run_scraper_and_email_handler(State(app_state): State<AppState>) -> impl IntoResponse {
-    scrape_and_email_stuff(app_state.clone()).await;
+    if app_state.scrape_run_lock.try_lock().is_err() {
+        return (StatusCode::CONFLICT, "A scrape is already running").into_response();
+    }
+    tokio::spawn(scrape_and_email_stuff(app_state));
     let redirect = Redirect::to("/").into_response();
     return redirect;
 }
@@ -387,7 +1467,7 @@ async fn sign_in_page() -> Html<&'static str> {
     )
 }
 
-async fn show_create_user_form() -> Html<&'static str> {
+async fn show_create_household_form() -> Html<&'static str> {
     Html(
         r#"
         <!doctype html>
@@ -395,10 +1475,10 @@ async fn show_create_user_form() -> Html<&'static str> {
             <head></head>
             <body>
 
-                    <form action="/create_user" method="post" style="display:flex; flex-direction:column; flex-wrap: wrap">
-                        <label for="email">
-                            Enter the email:
-                            <input type="text" name="email">
+                    <form action="/create_household" method="post" style="display:flex; flex-direction:column; flex-wrap: wrap">
+                        <label for="name">
+                            Enter a name for the household (optional, defaults to the address):
+                            <input type="text" name="name">
                         </label>
 
                         <label for="postcode">
@@ -411,7 +1491,7 @@ async fn show_create_user_form() -> Html<&'static str> {
                             <input type="text" name="address">
                         </label>
 
-                        <input type="submit" value="Create user">
+                        <input type="submit" value="Create household">
                     </form>
                 </div>
             </body>
@@ -420,13 +1500,218 @@ async fn show_create_user_form() -> Html<&'static str> {
     )
 }
 
+async fn show_create_user_form(State(app_state): State<AppState>) -> Html<String> {
+    let households = get_all_households(&app_state.pool).await.unwrap();
+    let html = format!(
+        r#"
+        <!doctype html>
+        <html>
+            <head></head>
+            <body>
+
+                    <form action="/create_user" method="post" style="display:flex; flex-direction:column; flex-wrap: wrap">
+                        <label for="email">
+                            Enter the email:
+                            <input type="text" name="email">
+                        </label>
+
+                        <label for="name">
+                            Enter their name (optional, used for the rota):
+                            <input type="text" name="name">
+                        </label>
+
+                        <label for="household_id">
+                            Choose the household:
+                            <select name="household_id">
+                                {}
+                            </select>
+                        </label>
+
+                        <label for="phone_number">
+                            Enter a phone number for SMS reminders (optional, e.g +447700900123):
+                            <input type="tel" name="phone_number">
+                        </label>
+
+                        <label for="reminder_offset_minutes">
+                            When should reminders be sent?
+                            <select name="reminder_offset_minutes">
+                                {}
+                            </select>
+                        </label>
+
+                        <label for="locale">
+                            Which language should reminders be in?
+                            <select name="locale">
+                                {}
+                            </select>
+                        </label>
+
+                        <input type="submit" value="Create user">
+                    </form>
+                </div>
+            </body>
+        </html>
+        "#,
+        household_options(&households),
+        reminder_offset_options(scheduler::DEFAULT_REMINDER_OFFSET_MINUTES),
+        locale_options(Locale::default())
+    );
+    return Html(html);
+}
+
+fn household_options(households: &[Household]) -> String {
+    let mut options = String::new();
+    for household in households {
+        options.push_str(&format!(
+            "<option value=\"{}\">{} ({}, {})</option>",
+            household.id,
+            escape_html(&household.name),
+            escape_html(&household.address),
+            escape_html(&household.postcode)
+        ));
+    }
+    return options;
+}
+
+/// `<option>`s for picking when to escalate, including never
+fn escalation_offset_options(selected_offset: Option<i64>) -> String {
+    let mut options = format!(
+        "<option value=\"\"{}>Never</option>",
+        if selected_offset.is_none() {
+            " selected"
+        } else {
+            ""
+        }
+    );
+    for (offset, description) in scheduler::ESCALATION_OFFSET_OPTIONS {
+        let selected = if Some(offset) == selected_offset {
+            " selected"
+        } else {
+            ""
+        };
+        options.push_str(&format!(
+            "<option value=\"{}\"{}>{}</option>",
+            offset, selected, description
+        ));
+    }
+    return options;
+}
+
+/// `<option>`s for picking a reminder time, with `selected_offset` already selected
+fn reminder_offset_options(selected_offset: i64) -> String {
+    let mut options = String::new();
+    for (offset, description) in scheduler::REMINDER_OFFSET_OPTIONS {
+        let selected = if offset == selected_offset {
+            " selected"
+        } else {
+            ""
+        };
+        options.push_str(&format!(
+            "<option value=\"{}\"{}>{}</option>",
+            offset, selected, description
+        ));
+    }
+    return options;
+}
+
+fn locale_options(selected_locale: Locale) -> String {
+    let mut options = String::new();
+    for locale in Locale::ALL {
+        let selected = if locale == selected_locale {
+            " selected"
+        } else {
+            ""
+        };
+        options.push_str(&format!(
+            "<option value=\"{}\"{}>{}</option>",
+            locale.as_str(),
+            selected,
+            locale.native_name()
+        ));
+    }
+    return options;
+}
+
+async fn show_create_webhook_form(State(app_state): State<AppState>) -> Html<String> {
+    let households = get_all_households(&app_state.pool).await.unwrap();
+    let html = format!(
+        r#"
+        <!doctype html>
+        <html>
+            <head></head>
+            <body>
+
+                    <form action="/create_webhook" method="post" style="display:flex; flex-direction:column; flex-wrap: wrap">
+                        <label for="kind">
+                            Choose the service:
+                            <select name="kind">
+                                <option value="discord">Discord</option>
+                                <option value="slack">Slack</option>
+                            </select>
+                        </label>
+
+                        <label for="url">
+                            Enter the incoming webhook URL:
+                            <input type="text" name="url">
+                        </label>
+
+                        <label for="household_id">
+                            Choose the household:
+                            <select name="household_id">
+                                {}
+                            </select>
+                        </label>
+
+                        <input type="submit" value="Create webhook">
+                    </form>
+                </div>
+            </body>
+        </html>
+        "#,
+        household_options(&households)
+    );
+    return Html(html);
+}
+
 #[derive(Deserialize, Debug)]
 struct CreateUser {
     email: String,
+    #[serde(default)]
+    name: String,
+    household_id: i64,
+    #[serde(default)]
+    phone_number: String,
+    reminder_offset_minutes: i64,
+    #[serde(default)]
+    locale: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CreateWebhook {
+    kind: String,
+    url: String,
+    household_id: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct CreateHousehold {
+    name: String,
     postcode: String,
     address: String,
 }
 
+#[derive(Deserialize, Debug)]
+struct HouseholdQuery {
+    household_id: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Escalation {
+    escalation_offset_minutes: String,
+    #[serde(default)]
+    escalation_email: String,
+}
+
 #[derive(Deserialize, Debug)]
 struct SignInDetails {
     password: String,
@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Error};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::derive::Deriver;
+use openssl::ec::{EcGroup, EcKey, EcPoint, PointConversionForm};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private, Public};
+use openssl::sign::Signer;
+use openssl::symm::{encrypt_aead, Cipher};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+
+use bin_stuff::{NextBinCollection, User};
+use log::info;
+
+use crate::email_sender::{reminder_body, ReminderText};
+
+/// Application server keys used to sign push requests (RFC 8292)
+#[derive(Clone)]
+pub struct VapidConfig {
+    private_key: EcKey<Private>,
+    /// Uncompressed public key, base64url encoded. Handed to the browser as the applicationServerKey
+    pub public_key: String,
+    /// Contact for the push service, i.e mailto:admin@example.com
+    pub subject: String,
+}
+
+impl VapidConfig {
+    /// `private_key` is the base64url encoded raw private scalar, as generated by most web push tooling
+    pub fn new(private_key: &str, subject: String) -> Result<VapidConfig, Error> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let private_number = BigNum::from_slice(&URL_SAFE_NO_PAD.decode(private_key)?)?;
+        let ctx = BigNumContext::new()?;
+        let mut public_point = EcPoint::new(&group)?;
+        public_point.mul_generator(&group, &private_number, &ctx)?;
+        let private_key = EcKey::from_private_components(&group, &private_number, &public_point)?;
+        private_key.check_key()?;
+        let public_key = URL_SAFE_NO_PAD.encode(uncompressed_public_key(&private_key)?);
+
+        return Ok(VapidConfig {
+            private_key,
+            public_key,
+            subject,
+        });
+    }
+
+    /// Value for the Authorization header of a push to `endpoint`
+    fn authorization(&self, endpoint: &str) -> Result<String, Error> {
+        let audience = reqwest::Url::parse(endpoint)?
+            .origin()
+            .ascii_serialization();
+        let expires_at = chrono::Utc::now() + chrono::Duration::hours(12);
+        let header = URL_SAFE_NO_PAD.encode(json!({ "typ": "JWT", "alg": "ES256" }).to_string());
+        let claims = URL_SAFE_NO_PAD.encode(
+            json!({
+                "aud": audience,
+                "exp": expires_at.timestamp(),
+                "sub": self.subject,
+            })
+            .to_string(),
+        );
+        let signing_input = format!("{}.{}", header, claims);
+
+        // JWTs want the raw r || s signature rather than the DER encoding openssl gives us
+        let digest = hash(MessageDigest::sha256(), signing_input.as_bytes())?;
+        let signature = EcdsaSig::sign(&digest, &self.private_key)?;
+        let mut raw_signature = signature.r().to_vec_padded(32)?;
+        raw_signature.extend(signature.s().to_vec_padded(32)?);
+
+        return Ok(format!(
+            "vapid t={}.{}, k={}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(raw_signature),
+            self.public_key
+        ));
+    }
+}
+
+/// What the browser hands us from `pushManager.subscribe()`
+#[derive(Deserialize, Debug)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PushSubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+pub async fn save_push_subscription(
+    pool: &SqlitePool,
+    user: &User,
+    subscription: &PushSubscription,
+) -> Result<(), Error> {
+    // Make sure we can actually encrypt to these keys before storing them
+    decode_subscription_keys(&subscription.keys)?;
+    sqlx::query(
+        "INSERT INTO push_subscriptions (user_id, endpoint, p256dh, auth) VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT (endpoint) DO UPDATE SET user_id = ?1, p256dh = ?3, auth = ?4",
+    )
+    .bind(user._id)
+    .bind(&subscription.endpoint)
+    .bind(&subscription.keys.p256dh)
+    .bind(&subscription.keys.auth)
+    .execute(pool)
+    .await?;
+    return Ok(());
+}
+
+async fn get_push_subscriptions(
+    pool: &SqlitePool,
+    user: &User,
+) -> Result<Vec<PushSubscription>, Error> {
+    let subscriptions =
+        sqlx::query("SELECT endpoint, p256dh, auth FROM push_subscriptions WHERE user_id = ?1")
+            .bind(user._id)
+            .map(|row: SqliteRow| PushSubscription {
+                endpoint: row.get("endpoint"),
+                keys: PushSubscriptionKeys {
+                    p256dh: row.get("p256dh"),
+                    auth: row.get("auth"),
+                },
+            })
+            .fetch_all(pool)
+            .await?;
+    return Ok(subscriptions);
+}
+
+pub async fn push_to_user(
+    user: &User,
+    next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
+    vapid_config: &VapidConfig,
+    http_client: &reqwest::Client,
+    pool: &SqlitePool,
+) -> Result<(), Error> {
+    let body = reminder_body(next_bin_collection, text.locale);
+    return push_message_to_user(
+        user,
+        &text.subject,
+        body.trim_end(),
+        text.done_link.as_deref(),
+        vapid_config,
+        http_client,
+        pool,
+    )
+    .await;
+}
+
+/// Sends a notification to every device the user has subscribed on.
+/// Tapping the notification opens `url` if there is one
+pub async fn push_message_to_user(
+    user: &User,
+    title: &str,
+    body: &str,
+    url: Option<&str>,
+    vapid_config: &VapidConfig,
+    http_client: &reqwest::Client,
+    pool: &SqlitePool,
+) -> Result<(), Error> {
+    let payload = json!({
+        "title": title,
+        "body": body,
+        "url": url,
+    })
+    .to_string();
+
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    for subscription in get_push_subscriptions(pool, user).await? {
+        let (user_agent_public_key, auth_secret) = decode_subscription_keys(&subscription.keys)?;
+        // Fresh key and salt for every message
+        let encrypted = encrypt_payload(
+            payload.as_bytes(),
+            &user_agent_public_key,
+            &auth_secret,
+            &EcKey::generate(&group)?,
+            &random_salt(),
+        )?;
+
+        let response = http_client
+            .post(&subscription.endpoint)
+            .header(
+                "Authorization",
+                vapid_config.authorization(&subscription.endpoint)?,
+            )
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .header("TTL", "86400")
+            .header("Urgency", "high")
+            .body(encrypted)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::GONE {
+            info!(
+                "Push subscription for {} has expired, removing it",
+                user.email
+            );
+            sqlx::query("DELETE FROM push_subscriptions WHERE endpoint = ?1")
+                .bind(&subscription.endpoint)
+                .execute(pool)
+                .await?;
+            continue;
+        }
+        response.error_for_status()?;
+        info!("Push notification sent to {}", user.email);
+    }
+    return Ok(());
+}
+
+fn decode_subscription_keys(keys: &PushSubscriptionKeys) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let user_agent_public_key = URL_SAFE_NO_PAD.decode(keys.p256dh.trim_end_matches('='))?;
+    let auth_secret = URL_SAFE_NO_PAD.decode(keys.auth.trim_end_matches('='))?;
+    if user_agent_public_key.len() != 65 || auth_secret.len() != 16 {
+        return Err(anyhow!("Push subscription keys are the wrong length"));
+    }
+    return Ok((user_agent_public_key, auth_secret));
+}
+
+fn random_salt() -> [u8; 16] {
+    let mut salt = [0; 16];
+    openssl::rand::rand_bytes(&mut salt).expect("Could not generate a salt");
+    return salt;
+}
+
+fn uncompressed_public_key<T: openssl::pkey::HasPublic>(key: &EcKey<T>) -> Result<Vec<u8>, Error> {
+    let mut ctx = BigNumContext::new()?;
+    let bytes =
+        key.public_key()
+            .to_bytes(key.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)?;
+    return Ok(bytes);
+}
+
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> Result<Vec<u8>, Error> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    for part in data {
+        signer.update(part)?;
+    }
+    return Ok(signer.sign_to_vec()?);
+}
+
+/// Encrypts a push message body as described in RFC 8291, using the aes128gcm
+/// content coding from RFC 8188 with a single record
+fn encrypt_payload(
+    payload: &[u8],
+    user_agent_public_key: &[u8],
+    auth_secret: &[u8],
+    application_server_key: &EcKey<Private>,
+    salt: &[u8; 16],
+) -> Result<Vec<u8>, Error> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let mut ctx = BigNumContext::new()?;
+    let user_agent_point = EcPoint::from_bytes(&group, user_agent_public_key, &mut ctx)?;
+    let user_agent_key: PKey<Public> =
+        PKey::from_ec_key(EcKey::from_public_key(&group, &user_agent_point)?)?;
+    let application_server_public_key = uncompressed_public_key(application_server_key)?;
+
+    let application_server_pkey = PKey::from_ec_key(application_server_key.clone())?;
+    let mut deriver = Deriver::new(&application_server_pkey)?;
+    deriver.set_peer(&user_agent_key)?;
+    let ecdh_secret = deriver.derive_to_vec()?;
+
+    // Combine the ECDH secret with the subscription's auth secret
+    let prk_key = hmac_sha256(auth_secret, &[&ecdh_secret])?;
+    let input_keying_material = hmac_sha256(
+        &prk_key,
+        &[
+            b"WebPush: info\x00",
+            user_agent_public_key,
+            &application_server_public_key,
+            b"\x01",
+        ],
+    )?;
+
+    let prk = hmac_sha256(salt, &[&input_keying_material])?;
+    let content_encryption_key =
+        hmac_sha256(&prk, &[b"Content-Encoding: aes128gcm\x00\x01"])?[..16].to_vec();
+    let nonce = hmac_sha256(&prk, &[b"Content-Encoding: nonce\x00\x01"])?[..12].to_vec();
+
+    // 0x02 marks the last (and only) record, no extra padding
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+    let mut tag = [0; 16];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_128_gcm(),
+        &content_encryption_key,
+        Some(&nonce),
+        &[],
+        &plaintext,
+        &mut tag,
+    )?;
+
+    let record_size: u32 = 4096;
+    let mut body = salt.to_vec();
+    body.extend(record_size.to_be_bytes());
+    body.push(application_server_public_key.len() as u8);
+    body.extend(&application_server_public_key);
+    body.extend(ciphertext);
+    body.extend(tag);
+    return Ok(body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(s: &str) -> Vec<u8> {
+        return URL_SAFE_NO_PAD.decode(s).unwrap();
+    }
+
+    /// Example from section 5 of RFC 8291
+    #[test]
+    fn encrypts_rfc_8291_example() {
+        let application_server_key = VapidConfig::new(
+            "yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw",
+            "".to_string(),
+        )
+        .unwrap()
+        .private_key;
+        let salt: [u8; 16] = decode("DGv6ra1nlYgDCS1FRnbzlw").try_into().unwrap();
+
+        let body = encrypt_payload(
+            b"When I grow up, I want to be a watermelon",
+            &decode("BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4"),
+            &decode("BTBZMqHH6r4Tts7J_aSIgg"),
+            &application_server_key,
+            &salt,
+        )
+        .unwrap();
+
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(body),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+        );
+    }
+}
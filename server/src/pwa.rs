@@ -0,0 +1,423 @@
+//! A small installable web app for recipients. Each user gets their own link with an
+//! `app_token`, which shows their upcoming bins and lets them subscribe to push notifications.
+
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Form, Json};
+use serde::Deserialize;
+use serde_json::json;
+
+use bin_stuff::cadence::{
+    next_collection_with_projections, upcoming_collections, Confidence, PROJECTION_WEEKS,
+};
+use bin_stuff::locale::{self, bin_collected_on, long_date, Locale};
+use bin_stuff::User;
+
+use crate::acknowledgements::{acknowledge as acknowledge_collection, is_acknowledged};
+use crate::households::get_household;
+use crate::push_sender::{save_push_subscription, PushSubscription};
+use crate::rota::get_rota;
+use crate::scheduler::{check_reminder_offset, MIN_REMINDER_CONFIDENCE};
+use crate::{
+    escape_html, get_all_users, get_stored_bin_dates, get_user_by_app_token, locale_options,
+    reminder_offset_options, AppState,
+};
+
+pub const APP_ROUTE: &str = "/app";
+pub const APP_MANIFEST_ROUTE: &str = "/app/manifest.webmanifest";
+pub const APP_SERVICE_WORKER_ROUTE: &str = "/app/service-worker.js";
+pub const APP_ICON_ROUTE: &str = "/app/icon.svg";
+pub const SUBSCRIBE_SCRIPT_ROUTE: &str = "/app/subscribe.js";
+pub const APP_SUBSCRIBE_ROUTE: &str = "/app/subscribe";
+pub const APP_PREFERENCES_ROUTE: &str = "/app/preferences";
+pub const APP_ACKNOWLEDGE_ROUTE: &str = "/app/acknowledge";
+
+#[derive(Deserialize, Debug)]
+pub struct AppQuery {
+    token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Preferences {
+    // Checkboxes are only sent when ticked
+    email_reminders: Option<String>,
+    reminder_offset_minutes: i64,
+    #[serde(default)]
+    locale: String,
+}
+
+async fn find_user(app_state: &AppState, token: &str) -> Result<User, Response> {
+    match get_user_by_app_token(&app_state.pool, token).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Unknown link").into_response()),
+        Err(e) => {
+            log::error!("Error looking up app user: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+pub(crate) async fn app_page(
+    State(app_state): State<AppState>,
+    Query(query): Query<AppQuery>,
+) -> Response {
+    let user = match find_user(&app_state, &query.token).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let household = get_household(&app_state.pool, user.household_id)
+        .await
+        .unwrap();
+    let bins = get_stored_bin_dates(&app_state.pool, household.id)
+        .await
+        .unwrap();
+
+    let today = chrono::Utc::now().date_naive();
+    let until = today + chrono::Duration::weeks(PROJECTION_WEEKS);
+    let upcoming = upcoming_collections(&bins, today, until, Confidence::Low);
+
+    let mut upcoming_html = String::new();
+    if upcoming.is_empty() {
+        upcoming_html.push_str("<p>No collections found yet, check back after the next run.</p>");
+    } else {
+        upcoming_html.push_str("<ul>");
+        for bin_day in &upcoming {
+            let line = bin_collected_on(bin_day.bin, bin_day.date, user.locale);
+            upcoming_html.push_str(&match bin_day.projected {
+                Some(confidence) => format!(
+                    "<li><em>{} {}</em></li>",
+                    line,
+                    locale::projected(confidence, user.locale)
+                ),
+                None => format!("<li>{}</li>", line),
+            });
+        }
+        upcoming_html.push_str("</ul>");
+    }
+
+    let next_collection_html =
+        match next_collection_with_projections(&bins, today, MIN_REMINDER_CONFIDENCE) {
+            Some(next_collection) => {
+                next_collection_html(
+                    &app_state,
+                    &user,
+                    next_collection.bins[0].date,
+                    &query.token,
+                )
+                .await
+            }
+            None => String::new(),
+        };
+
+    let push_html = match &app_state.vapid_config {
+        Some(vapid_config) => format!(
+            r#"<button id="subscribe">Send me notifications</button>
+            <p id="push-status"></p>
+            <script>
+                const applicationServerKey = "{}";
+                const subscribeUrl = "{}?token={}";
+            </script>
+            <script src="{}"></script>"#,
+            vapid_config.public_key, APP_SUBSCRIBE_ROUTE, query.token, SUBSCRIBE_SCRIPT_ROUTE
+        ),
+        None => "<p>Notifications aren't set up on this server.</p>".to_string(),
+    };
+
+    let html = format!(
+        r#"
+        <!doctype html>
+        <html>
+            <head>
+                <meta name="viewport" content="width=device-width, initial-scale=1">
+                <link rel="manifest" href="{manifest}?token={token}">
+                <title>What bin is it</title>
+            </head>
+            <body>
+                <h1>Upcoming bins</h1>
+                <p>{address}, {postcode}</p>
+                {upcoming}
+                {next_collection}
+                {push}
+                <form action="{preferences}?token={token}" method="post">
+                    <label for="email_reminders">
+                        <input type="checkbox" name="email_reminders" {email_checked}>
+                        Also send me emails
+                    </label>
+                    <label for="reminder_offset_minutes">
+                        Remind me
+                        <select name="reminder_offset_minutes">{reminder_options}</select>
+                    </label>
+                    <label for="locale">
+                        Language
+                        <select name="locale">{locale_options}</select>
+                    </label>
+                    <input type="submit" value="Save">
+                </form>
+            </body>
+        </html>
+        "#,
+        manifest = APP_MANIFEST_ROUTE,
+        token = query.token,
+        address = escape_html(&household.address),
+        postcode = escape_html(&household.postcode),
+        upcoming = upcoming_html,
+        next_collection = next_collection_html,
+        push = push_html,
+        preferences = APP_PREFERENCES_ROUTE,
+        email_checked = if user.email_reminders { "checked" } else { "" },
+        reminder_options = reminder_offset_options(user.reminder_offset_minutes),
+        locale_options = locale_options(user.locale),
+    );
+    return Html(html).into_response();
+}
+
+/// Whose turn it is for the next collection if there's a rota, and a button to say it's done
+async fn next_collection_html(
+    app_state: &AppState,
+    user: &User,
+    collection_date: chrono::NaiveDate,
+    token: &str,
+) -> String {
+    let mut html = String::new();
+    if let Some(rota) = get_rota(&app_state.pool, user.household_id).await.unwrap() {
+        let users = get_all_users(&app_state.pool).await.unwrap();
+        let on_duty_id = rota.members[rota.turn_for_collection(collection_date)];
+        let on_duty = match users.iter().find(|u| u._id == on_duty_id) {
+            Some(on_duty) if on_duty._id == user._id => "Your".to_string(),
+            Some(on_duty) => format!("{}'s", escape_html(on_duty.display_name())),
+            None => "Nobody's".to_string(),
+        };
+        html.push_str(&format!(
+            "<p>{} turn for {}</p>",
+            on_duty,
+            long_date(collection_date, user.locale)
+        ));
+    }
+    if is_acknowledged(&app_state.pool, user.household_id, collection_date)
+        .await
+        .unwrap()
+    {
+        html.push_str("<p>The bins are out.</p>");
+    } else {
+        html.push_str(&format!(
+            r#"<form action="{}?token={}" method="post">
+                <input type="submit" value="Done, the bins are out">
+            </form>"#,
+            APP_ACKNOWLEDGE_ROUTE, token
+        ));
+    }
+    return html;
+}
+
+pub async fn manifest(Query(query): Query<AppQuery>) -> impl IntoResponse {
+    let manifest = json!({
+        "name": "What bin is it",
+        "short_name": "Bins",
+        "start_url": format!("{}?token={}", APP_ROUTE, query.token),
+        "scope": APP_ROUTE,
+        "display": "standalone",
+        "background_color": "#ffffff",
+        "theme_color": "#1f6fd1",
+        "icons": [
+            { "src": APP_ICON_ROUTE, "sizes": "any", "type": "image/svg+xml" }
+        ],
+    });
+    return (
+        [(header::CONTENT_TYPE, "application/manifest+json")],
+        manifest.to_string(),
+    );
+}
+
+pub async fn service_worker() -> impl IntoResponse {
+    return ([(header::CONTENT_TYPE, "text/javascript")], SERVICE_WORKER);
+}
+
+pub async fn subscribe_script() -> impl IntoResponse {
+    return (
+        [(header::CONTENT_TYPE, "text/javascript")],
+        SUBSCRIBE_SCRIPT,
+    );
+}
+
+pub async fn icon() -> impl IntoResponse {
+    return ([(header::CONTENT_TYPE, "image/svg+xml")], ICON);
+}
+
+pub(crate) async fn subscribe(
+    State(app_state): State<AppState>,
+    Query(query): Query<AppQuery>,
+    Json(subscription): Json<PushSubscription>,
+) -> Response {
+    let user = match find_user(&app_state, &query.token).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if let Err(e) = save_push_subscription(&app_state.pool, &user, &subscription).await {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Could not save subscription: {}", e),
+        )
+            .into_response();
+    }
+    return StatusCode::CREATED.into_response();
+}
+
+pub(crate) async fn update_preferences(
+    State(app_state): State<AppState>,
+    Query(query): Query<AppQuery>,
+    Form(preferences): Form<Preferences>,
+) -> Response {
+    let user = match find_user(&app_state, &query.token).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if let Err(e) = check_reminder_offset(preferences.reminder_offset_minutes) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    let locale: Locale = preferences.locale.parse().unwrap_or(user.locale);
+    sqlx::query(
+        "UPDATE emails SET email_reminders = ?1, reminder_offset_minutes = ?2, locale = ?3 WHERE id = ?4",
+    )
+    .bind(preferences.email_reminders.is_some())
+    .bind(preferences.reminder_offset_minutes)
+    .bind(locale.as_str())
+    .bind(user._id)
+    .execute(&app_state.pool)
+    .await
+    .unwrap();
+    return Redirect::to(&format!("{}?token={}", APP_ROUTE, query.token)).into_response();
+}
+
+/// Marks the household's next collection as done, so nobody else gets chased about it
+pub(crate) async fn acknowledge(
+    State(app_state): State<AppState>,
+    Query(query): Query<AppQuery>,
+) -> Response {
+    let user = match find_user(&app_state, &query.token).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let bins = get_stored_bin_dates(&app_state.pool, user.household_id)
+        .await
+        .unwrap();
+    let today = chrono::Utc::now()
+        .with_timezone(&crate::scheduler::TIMEZONE)
+        .date_naive();
+    if let Some(next_collection) =
+        next_collection_with_projections(&bins, today, MIN_REMINDER_CONFIDENCE)
+    {
+        acknowledge_collection(
+            &app_state.pool,
+            user.household_id,
+            next_collection.bins[0].date,
+            Some(user._id),
+            "app",
+        )
+        .await
+        .unwrap();
+    }
+    return Redirect::to(&format!("{}?token={}", APP_ROUTE, query.token)).into_response();
+}
+
+const SERVICE_WORKER: &str = r#"
+self.addEventListener("push", (event) => {
+    const data = event.data ? event.data.json() : { title: "What bin is it", body: "" };
+    event.waitUntil(
+        self.registration.showNotification(data.title, {
+            body: data.body,
+            icon: "/app/icon.svg",
+            data: { url: data.url },
+        })
+    );
+});
+
+self.addEventListener("notificationclick", (event) => {
+    event.notification.close();
+    const url = event.notification.data && event.notification.data.url;
+    if (url) {
+        event.waitUntil(clients.openWindow(url));
+        return;
+    }
+    event.waitUntil(clients.matchAll({ type: "window" }).then((windows) => {
+        if (windows.length > 0) {
+            return windows[0].focus();
+        }
+    }));
+});
+"#;
+
+const SUBSCRIBE_SCRIPT: &str = r#"
+function urlBase64ToUint8Array(base64String) {
+    const padding = "=".repeat((4 - (base64String.length % 4)) % 4);
+    const base64 = (base64String + padding).replace(/-/g, "+").replace(/_/g, "/");
+    const raw = atob(base64);
+    return Uint8Array.from([...raw].map((c) => c.charCodeAt(0)));
+}
+
+const status = document.getElementById("push-status");
+document.getElementById("subscribe").addEventListener("click", async () => {
+    if (!("serviceWorker" in navigator) || !("PushManager" in window)) {
+        status.textContent = "This browser doesn't support notifications";
+        return;
+    }
+    const registration = await navigator.serviceWorker.register("/app/service-worker.js");
+    const permission = await Notification.requestPermission();
+    if (permission !== "granted") {
+        status.textContent = "Notifications were not allowed";
+        return;
+    }
+    const subscription = await registration.pushManager.subscribe({
+        userVisibleOnly: true,
+        applicationServerKey: urlBase64ToUint8Array(applicationServerKey),
+    });
+    const response = await fetch(subscribeUrl, {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify(subscription),
+    });
+    status.textContent = response.ok ? "Notifications are on" : "Could not turn on notifications";
+});
+"#;
+
+const ICON: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 64 64">
+<rect x="14" y="16" width="36" height="42" rx="4" fill="#1f6fd1"/>
+<rect x="10" y="8" width="44" height="8" rx="2" fill="#174f96"/>
+</svg>"##;
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::FromRequest;
+    use axum::http::Request;
+
+    use crate::scheduler::check_escalation_offset;
+
+    use super::*;
+
+    async fn post_preferences(body: String) -> Preferences {
+        let request = Request::post(APP_PREFERENCES_ROUTE)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap();
+        let Form(preferences) = Form::<Preferences>::from_request(request, &())
+            .await
+            .unwrap();
+        return preferences;
+    }
+
+    #[tokio::test]
+    async fn only_offered_reminder_times_are_accepted() {
+        let offered = post_preferences("reminder_offset_minutes=-360&locale=en".to_string()).await;
+        assert!(check_reminder_offset(offered.reminder_offset_minutes).is_ok());
+
+        // Far enough out to overflow working out when the reminder's due
+        let crafted = post_preferences(format!("reminder_offset_minutes={}", i64::MAX)).await;
+        assert!(check_reminder_offset(crafted.reminder_offset_minutes).is_err());
+        assert!(check_reminder_offset(-5).is_err());
+
+        assert!(check_escalation_offset(-2 * 60).is_ok());
+        // A reminder time, but not one of the escalation times
+        assert!(check_escalation_offset(-6 * 60).is_err());
+    }
+}
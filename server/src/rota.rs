@@ -0,0 +1,433 @@
+//! An optional bin duty rota per household. Members take turns one collection at a time,
+//! and only the person whose turn it is gets reminded unless nobody says it's done.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use sqlx::{Row, SqlitePool};
+
+use crate::households::get_household;
+use crate::{escape_html, get_all_users, AppState};
+
+pub const ROTA_ROUTE: &str = "/rota";
+pub const ROTA_SKIP_ROUTE: &str = "/rota/skip";
+pub const ROTA_SWAP_ROUTE: &str = "/rota/swap";
+
+/// How long after their own reminder time everyone else gets reminded, if whoever's turn it is
+/// hasn't said the bins are out
+pub const ROTA_ESCALATION_DELAY_MINUTES: i64 = 2 * 60;
+
+#[derive(Debug)]
+pub struct Rota {
+    /// User ids, in the order they take turns
+    pub members: Vec<i64>,
+    pub turn: usize,
+    /// The collection `turn` was worked out for
+    pub turn_for: Option<NaiveDate>,
+}
+
+impl Rota {
+    /// Index into `members` of whoever's turn it is for the collection on `collection_date`.
+    /// The turn moves on once for each new collection
+    pub fn turn_for_collection(&self, collection_date: NaiveDate) -> usize {
+        let turn = match self.turn_for {
+            Some(date) if collection_date > date => self.turn + 1,
+            _ => self.turn,
+        };
+        return turn % self.members.len();
+    }
+}
+
+/// `None` if the household doesn't have a rota
+pub async fn get_rota(pool: &SqlitePool, household_id: i64) -> Result<Option<Rota>, Error> {
+    let members: Vec<i64> =
+        sqlx::query("SELECT user_id FROM rota_members WHERE household_id = ?1 ORDER BY position")
+            .bind(household_id)
+            .map(|row: sqlx::sqlite::SqliteRow| row.get("user_id"))
+            .fetch_all(pool)
+            .await?;
+    if members.is_empty() {
+        return Ok(None);
+    }
+
+    let row = sqlx::query("SELECT rota_turn, rota_turn_for FROM households WHERE id = ?1")
+        .bind(household_id)
+        .fetch_one(pool)
+        .await?;
+    let turn: i64 = row.get("rota_turn");
+    return Ok(Some(Rota {
+        members,
+        turn: turn as usize,
+        turn_for: row.get("rota_turn_for"),
+    }));
+}
+
+/// The user id of whoever's turn it is for the collection, moving the rota on if this is a new collection
+pub async fn on_duty(
+    pool: &SqlitePool,
+    household_id: i64,
+    collection_date: NaiveDate,
+) -> Result<Option<i64>, Error> {
+    let rota = match get_rota(pool, household_id).await? {
+        Some(rota) => rota,
+        None => return Ok(None),
+    };
+    let turn = rota.turn_for_collection(collection_date);
+    if rota.turn_for.is_none_or(|date| collection_date > date) {
+        sqlx::query("UPDATE households SET rota_turn = ?1, rota_turn_for = ?2 WHERE id = ?3")
+            .bind(turn as i64)
+            .bind(collection_date)
+            .bind(household_id)
+            .execute(pool)
+            .await?;
+    }
+    return Ok(Some(rota.members[turn]));
+}
+
+/// Replaces the rota with `user_ids` in order, starting again from the first of them.
+/// Everyone in it has to belong to the household, and can only be in it once
+pub async fn set_rota(pool: &SqlitePool, household_id: i64, user_ids: &[i64]) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    let household_members: Vec<i64> = sqlx::query("SELECT id FROM emails WHERE household_id = ?1")
+        .bind(household_id)
+        .map(|row: sqlx::sqlite::SqliteRow| row.get("id"))
+        .fetch_all(&mut *transaction)
+        .await?;
+    for (i, user_id) in user_ids.iter().enumerate() {
+        if !household_members.contains(user_id) {
+            return Err(anyhow!("User {} is not in the household", user_id));
+        }
+        if user_ids[..i].contains(user_id) {
+            return Err(anyhow!("User {} is in the rota more than once", user_id));
+        }
+    }
+    sqlx::query("DELETE FROM rota_members WHERE household_id = ?1")
+        .bind(household_id)
+        .execute(&mut *transaction)
+        .await?;
+    for (position, user_id) in user_ids.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO rota_members (household_id, user_id, position) VALUES (?1, ?2, ?3)",
+        )
+        .bind(household_id)
+        .bind(user_id)
+        .bind(position as i64)
+        .execute(&mut *transaction)
+        .await?;
+    }
+    sqlx::query("UPDATE households SET rota_turn = 0, rota_turn_for = NULL WHERE id = ?1")
+        .bind(household_id)
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await?;
+    return Ok(());
+}
+
+/// Passes the next collection on to the person after whoever's turn it would have been
+pub async fn skip_turn(pool: &SqlitePool, household_id: i64) -> Result<(), Error> {
+    if get_rota(pool, household_id).await?.is_none() {
+        return Err(anyhow!("Household {} has no rota", household_id));
+    }
+    sqlx::query("UPDATE households SET rota_turn = rota_turn + 1 WHERE id = ?1")
+        .bind(household_id)
+        .execute(pool)
+        .await?;
+    return Ok(());
+}
+
+/// Swaps two members' places in the household's rota
+pub async fn swap_members(
+    pool: &SqlitePool,
+    household_id: i64,
+    first_user_id: i64,
+    second_user_id: i64,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    let mut positions = Vec::new();
+    for user_id in [first_user_id, second_user_id] {
+        let position: Option<i64> = sqlx::query(
+            "SELECT position FROM rota_members WHERE household_id = ?1 AND user_id = ?2",
+        )
+        .bind(household_id)
+        .bind(user_id)
+        .map(|row: sqlx::sqlite::SqliteRow| row.get("position"))
+        .fetch_optional(&mut *transaction)
+        .await?;
+        positions.push(position.ok_or_else(|| anyhow!("User {} is not in the rota", user_id))?);
+    }
+    for (user_id, position) in [
+        (first_user_id, positions[1]),
+        (second_user_id, positions[0]),
+    ] {
+        sqlx::query(
+            "UPDATE rota_members SET position = ?1 WHERE household_id = ?2 AND user_id = ?3",
+        )
+        .bind(position)
+        .bind(household_id)
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    return Ok(());
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RotaQuery {
+    household_id: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SwapMembers {
+    first: i64,
+    second: i64,
+}
+
+pub(crate) async fn rota_page(
+    State(app_state): State<AppState>,
+    Query(query): Query<RotaQuery>,
+) -> Html<String> {
+    let household = get_household(&app_state.pool, query.household_id)
+        .await
+        .unwrap();
+    let users = get_all_users(&app_state.pool).await.unwrap();
+    let members: Vec<_> = users
+        .iter()
+        .filter(|u| u.household_id == household.id)
+        .collect();
+    let rota = get_rota(&app_state.pool, household.id).await.unwrap();
+
+    let mut html = format!("<h1>Rota for {}</h1>", escape_html(&household.name));
+    match &rota {
+        Some(rota) => {
+            html.push_str("<ol>");
+            for (i, user_id) in rota.members.iter().enumerate() {
+                let name = members
+                    .iter()
+                    .find(|u| u._id == *user_id)
+                    .map(|u| u.display_name())
+                    .unwrap_or("Unknown");
+                let current = match rota.turn_for {
+                    _ if i != rota.turn % rota.members.len() => String::new(),
+                    Some(date) => format!(" (turn for {})", date.format("%-d %b")),
+                    None => " (first up)".to_string(),
+                };
+                html.push_str(&format!("<li>{}{}</li>", escape_html(name), current));
+            }
+            html.push_str("</ol>");
+            html.push_str(&format!(
+                r#"<form action="{}?household_id={}" method="post">
+                    <input type="submit" value="Skip whoever's turn is next">
+                </form>"#,
+                ROTA_SKIP_ROUTE, household.id
+            ));
+
+            let mut options = String::new();
+            for user_id in &rota.members {
+                if let Some(user) = members.iter().find(|u| u._id == *user_id) {
+                    options.push_str(&format!(
+                        "<option value=\"{}\">{}</option>",
+                        user._id,
+                        escape_html(user.display_name())
+                    ));
+                }
+            }
+            html.push_str(&format!(
+                r#"<form action="{}?household_id={}" method="post">
+                    Swap <select name="first">{options}</select>
+                    with <select name="second">{options}</select>
+                    <input type="submit" value="Swap">
+                </form>"#,
+                ROTA_SWAP_ROUTE,
+                household.id,
+                options = options
+            ));
+        }
+        None => html.push_str("<p>No rota, everyone gets every reminder.</p>"),
+    }
+
+    html.push_str(&format!(
+        r#"<h2>Change the rota</h2>
+        <p>Number the members in the order they take turns. Leave blank to leave someone out, or all blank for no rota.</p>
+        <form action="{}?household_id={}" method="post" style="display:flex; flex-direction:column; flex-wrap: wrap">"#,
+        ROTA_ROUTE, household.id
+    ));
+    for user in &members {
+        let position = rota
+            .as_ref()
+            .and_then(|rota| rota.members.iter().position(|id| *id == user._id))
+            .map(|position| (position + 1).to_string())
+            .unwrap_or_default();
+        html.push_str(&format!(
+            r#"<label>{} <input type="number" min="1" name="position_{}" value="{}"></label>"#,
+            escape_html(user.display_name()),
+            user._id,
+            position
+        ));
+    }
+    html.push_str(r#"<input type="submit" value="Save rota"></form>"#);
+
+    return Html(html);
+}
+
+/// Form fields are `position_<user id>`, as the number of members varies
+pub(crate) async fn submit_rota_form(
+    State(app_state): State<AppState>,
+    Query(query): Query<RotaQuery>,
+    Form(input): Form<HashMap<String, String>>,
+) -> Response {
+    let mut positions: Vec<(i64, i64)> = Vec::new();
+    for (field, value) in &input {
+        let user_id = match field.strip_prefix("position_") {
+            Some(user_id) => user_id,
+            None => continue,
+        };
+        if value.trim().is_empty() {
+            continue;
+        }
+        match (user_id.parse(), value.trim().parse()) {
+            (Ok(user_id), Ok(position)) => positions.push((position, user_id)),
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid position {} for {}", value, field),
+                )
+                    .into_response()
+            }
+        }
+    }
+    positions.sort();
+    let user_ids: Vec<i64> = positions.into_iter().map(|(_, user_id)| user_id).collect();
+
+    if let Err(e) = set_rota(&app_state.pool, query.household_id, &user_ids).await {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Could not save rota: {}", e),
+        )
+            .into_response();
+    }
+    return Redirect::to(&format!(
+        "{}?household_id={}",
+        ROTA_ROUTE, query.household_id
+    ))
+    .into_response();
+}
+
+pub(crate) async fn skip(
+    State(app_state): State<AppState>,
+    Query(query): Query<RotaQuery>,
+) -> Response {
+    if let Err(e) = skip_turn(&app_state.pool, query.household_id).await {
+        return (StatusCode::BAD_REQUEST, format!("Could not skip: {}", e)).into_response();
+    }
+    return Redirect::to(&format!(
+        "{}?household_id={}",
+        ROTA_ROUTE, query.household_id
+    ))
+    .into_response();
+}
+
+pub(crate) async fn swap(
+    State(app_state): State<AppState>,
+    Query(query): Query<RotaQuery>,
+    Form(input): Form<SwapMembers>,
+) -> Response {
+    if let Err(e) = swap_members(
+        &app_state.pool,
+        query.household_id,
+        input.first,
+        input.second,
+    )
+    .await
+    {
+        return (StatusCode::BAD_REQUEST, format!("Could not swap: {}", e)).into_response();
+    }
+    return Redirect::to(&format!(
+        "{}?household_id={}",
+        ROTA_ROUTE, query.household_id
+    ))
+    .into_response();
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        return NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    }
+
+    #[test]
+    fn turns_move_on_once_per_collection() {
+        let rota = Rota {
+            members: vec![10, 20, 30],
+            turn: 2,
+            turn_for: Some(date("2024-01-08")),
+        };
+        // Same collection keeps the same person, however many times it's asked
+        assert_eq!(rota.turn_for_collection(date("2024-01-08")), 2);
+        // Next collection wraps round to the start
+        assert_eq!(rota.turn_for_collection(date("2024-01-15")), 0);
+
+        let new_rota = Rota {
+            members: vec![10, 20],
+            turn: 0,
+            turn_for: None,
+        };
+        assert_eq!(new_rota.turn_for_collection(date("2024-01-08")), 0);
+
+        // Skipped twice, so past the end of the members
+        let skipped = Rota {
+            members: vec![10, 20],
+            turn: 3,
+            turn_for: Some(date("2024-01-08")),
+        };
+        assert_eq!(skipped.turn_for_collection(date("2024-01-08")), 1);
+    }
+
+    #[tokio::test]
+    async fn only_household_members_can_be_in_the_rota() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO households (id, name, postcode, address) VALUES (1, 'Flat', 'ML6 0AA', '1 Main Street'), (2, 'House', 'ML1 1AA', '2 High Street')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO emails (id, email, household_id, app_token) VALUES (10, 'a@example.com', 1, 'a'), (20, 'b@example.com', 1, 'b'), (30, 'c@example.com', 2, 'c')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(skip_turn(&pool, 1).await.is_err());
+        // Someone from another household
+        assert!(set_rota(&pool, 1, &[10, 30]).await.is_err());
+        assert!(set_rota(&pool, 1, &[10, 10]).await.is_err());
+        assert!(get_rota(&pool, 1).await.unwrap().is_none());
+
+        set_rota(&pool, 1, &[10, 20]).await.unwrap();
+        set_rota(&pool, 2, &[30]).await.unwrap();
+        assert!(swap_members(&pool, 1, 10, 30).await.is_err());
+        swap_members(&pool, 1, 10, 20).await.unwrap();
+        assert_eq!(
+            get_rota(&pool, 1).await.unwrap().unwrap().members,
+            vec![20, 10]
+        );
+        skip_turn(&pool, 1).await.unwrap();
+    }
+}
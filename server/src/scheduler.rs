@@ -0,0 +1,624 @@
+//! Works out when each user's next reminder is due, rather than running one job for everybody.
+//! All times are worked out in UK local time, so "6pm" is 6pm whether it's GMT or BST.
+
+use std::time::Duration;
+
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures_util::{stream, StreamExt};
+use log::{error, info};
+
+use bin_stuff::cadence::{next_collection_with_projections, Confidence};
+use bin_stuff::locale::still_not_out;
+use bin_stuff::{BinDates, NextBinCollection, User};
+
+use crate::acknowledgements::is_acknowledged;
+use crate::canary::run_canary;
+use crate::email_sender::email_reminder_to;
+use crate::households::{get_all_households, set_household_escalated_for, Household};
+use crate::incidents::{self, report_error, report_success};
+use crate::layout::check_layout;
+use crate::rota::{on_duty, ROTA_ESCALATION_DELAY_MINUTES};
+use crate::webhook_sender::post_to_webhook;
+use crate::webhook_sender::Webhook;
+use crate::{
+    get_all_users, get_all_webhooks, get_stored_bin_dates, is_address_problem, open_scrape_pool,
+    reminder_text, scrape_and_store, send_reminders_to_user, set_user_reminded_for,
+    set_webhook_reminded_for, AppState,
+};
+
+pub const TIMEZONE: Tz = chrono_tz::Europe::London;
+
+/// 6pm the night before
+pub const DEFAULT_REMINDER_OFFSET_MINUTES: i64 = -6 * 60;
+
+/// Reminder times offered in the forms, as minutes relative to midnight at the start of the collection day
+pub const REMINDER_OFFSET_OPTIONS: [(i64, &str); 4] = [
+    (-6 * 60, "6pm the night before"),
+    (-4 * 60, "8pm the night before"),
+    (6 * 60 + 30, "6:30am on the day"),
+    (7 * 60, "7am on the day"),
+];
+
+/// Times offered for the second reminder when nobody has said the bins are out
+pub const ESCALATION_OFFSET_OPTIONS: [(i64, &str); 3] = [
+    (-3 * 60, "9pm the night before"),
+    (-2 * 60, "10pm the night before"),
+    (6 * 60 + 30, "6:30am on the day"),
+];
+
+/// Only the offered reminder times are accepted from forms, as anything else could be too far
+/// out to work out a time for
+pub fn check_reminder_offset(offset_minutes: i64) -> Result<i64, anyhow::Error> {
+    if !REMINDER_OFFSET_OPTIONS
+        .iter()
+        .any(|(offset, _)| *offset == offset_minutes)
+    {
+        return Err(anyhow::anyhow!("Unknown reminder time {}", offset_minutes));
+    }
+    return Ok(offset_minutes);
+}
+
+/// Like `check_reminder_offset`, for the escalation times
+pub fn check_escalation_offset(offset_minutes: i64) -> Result<i64, anyhow::Error> {
+    if !ESCALATION_OFFSET_OPTIONS
+        .iter()
+        .any(|(offset, _)| *offset == offset_minutes)
+    {
+        return Err(anyhow::anyhow!(
+            "Unknown escalation time {}",
+            offset_minutes
+        ));
+    }
+    return Ok(offset_minutes);
+}
+
+/// How late a reminder can be (i.e the server was down) before it's not worth sending
+const MAX_REMINDER_LATENESS_HOURS: i64 = 12;
+
+#[derive(Debug)]
+pub enum Reminder {
+    NotDue,
+    Due(NextBinCollection),
+    /// Too late to bother sending, but should be marked as done so we move on to the next one
+    Missed(NaiveDate),
+}
+
+fn local_to_utc(local: NaiveDateTime) -> DateTime<Utc> {
+    return match TIMEZONE.from_local_datetime(&local) {
+        LocalResult::Single(time) => time.with_timezone(&Utc),
+        // When the clocks go back, use the first of the two
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        // Skipped over when the clocks go forward, so the hour after is the closest real time
+        LocalResult::None => local_to_utc(local + chrono::Duration::hours(1)),
+    };
+}
+
+pub fn reminder_due_at(collection_date: NaiveDate, offset_minutes: i64) -> DateTime<Utc> {
+    let start_of_day = collection_date.and_time(NaiveTime::MIN);
+    return local_to_utc(start_of_day + chrono::Duration::minutes(offset_minutes));
+}
+
+/// The next time after `after` that the local clock reads `time`
+pub fn next_daily_run(after: DateTime<Utc>, time: NaiveTime) -> DateTime<Utc> {
+    let local_date = after.with_timezone(&TIMEZONE).date_naive();
+    let today = local_to_utc(local_date.and_time(time));
+    if today > after {
+        return today;
+    }
+    return local_to_utc(local_date.succ_opt().unwrap().and_time(time));
+}
+
+/// A collection that's only projected from the bin's cadence needs at least this confidence to
+/// be reminded about
+pub const MIN_REMINDER_CONFIDENCE: Confidence = Confidence::Medium;
+
+pub fn due_reminder(
+    bins: &[BinDates],
+    offset_minutes: i64,
+    last_reminded_for: Option<NaiveDate>,
+    now: DateTime<Utc>,
+) -> Reminder {
+    let today = now.with_timezone(&TIMEZONE).date_naive();
+    let from_date = match last_reminded_for {
+        Some(date) if date >= today => date.succ_opt().unwrap(),
+        _ => today,
+    };
+    let next_collection =
+        match next_collection_with_projections(bins, from_date, MIN_REMINDER_CONFIDENCE) {
+            Some(next_collection) => next_collection,
+            None => return Reminder::NotDue,
+        };
+
+    let collection_date = next_collection.bins[0].date;
+    let due_at = reminder_due_at(collection_date, offset_minutes);
+    if now < due_at {
+        return Reminder::NotDue;
+    }
+    if now - due_at > chrono::Duration::hours(MAX_REMINDER_LATENESS_HOURS) {
+        return Reminder::Missed(collection_date);
+    }
+    return Reminder::Due(next_collection);
+}
+
+/// Scrapes every address once a day at `scrape_time`, and checks for due reminders every minute.
+/// Scrapes are spawned rather than awaited, as at a polite rate they can take hours, and the
+/// run lock stops them overlapping
+pub(crate) async fn run_scheduler(app_state: AppState, scrape_time: NaiveTime) {
+    spawn_scrape(&app_state, true);
+    let mut next_scrape_at = next_daily_run(Utc::now(), scrape_time);
+    info!("Next scrape at {}", next_scrape_at);
+    let mut next_canary_at = app_state
+        .canary
+        .as_ref()
+        .map(|canary| next_daily_run(Utc::now(), canary.time));
+
+    let mut poll_interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        poll_interval.tick().await;
+        if Utc::now() >= next_scrape_at {
+            spawn_scrape(&app_state, false);
+            next_scrape_at = next_daily_run(Utc::now(), scrape_time);
+            info!("Next scrape at {}", next_scrape_at);
+        }
+        if let (Some(canary), Some(canary_at)) = (&app_state.canary, next_canary_at) {
+            if Utc::now() >= canary_at {
+                let app_state = app_state.clone();
+                let addresses = canary.addresses.clone();
+                tokio::spawn(async move { run_canary(&app_state, &addresses).await });
+                next_canary_at = Some(next_daily_run(Utc::now(), canary.time));
+            }
+        }
+        if let Err(e) = send_due_reminders(&app_state).await {
+            error!("Error checking for due reminders: {}", e);
+        }
+        if let Err(e) = send_due_escalations(&app_state).await {
+            error!("Error checking for due escalations: {}", e);
+        }
+    }
+}
+
+fn spawn_scrape(app_state: &AppState, only_missing: bool) {
+    let app_state = app_state.clone();
+    tokio::spawn(async move { scrape_addresses(&app_state, only_missing).await });
+}
+
+/// Scrapes and stores the dates for every household with someone to remind.
+/// With `only_missing`, only households we have no dates for yet are scraped
+async fn scrape_addresses(app_state: &AppState, only_missing: bool) {
+    let households = match households_to_scrape(app_state).await {
+        Ok(households) => households,
+        Err(e) => {
+            error!("Error getting households to scrape: {}", e);
+            return;
+        }
+    };
+
+    let mut to_scrape = Vec::new();
+    for household in households {
+        if only_missing {
+            match get_stored_bin_dates(&app_state.pool, household.id).await {
+                Ok(bins) if !bins.is_empty() => continue,
+                _ => {}
+            }
+        }
+        to_scrape.push(household);
+    }
+    if to_scrape.is_empty() {
+        return;
+    }
+
+    // The browsers are shared by every household, rather than starting one each
+    let scrape_pool = match open_scrape_pool(app_state).await {
+        Ok(scrape_pool) => scrape_pool,
+        Err(failure) => {
+            report_error(app_state, incidents::SCRAPE, failure.into()).await;
+            return;
+        }
+    };
+    let mut report = RunReport::default();
+    {
+        // Several at once, but the results still come back in household order
+        let scrapes: Vec<_> = to_scrape
+            .iter()
+            .map(|household| async {
+                info!("Scheduled scrape for {}", household.name);
+                scrape_and_store(app_state, &scrape_pool, household).await
+            })
+            .collect();
+        let mut results = stream::iter(scrapes).buffered(scrape_pool.size());
+        let mut households = to_scrape.iter();
+        while let Some(result) = results.next().await {
+            let household = households.next().expect("One result per household");
+            match result {
+                Ok(scraped) => {
+                    report.scraped += 1;
+                    report.date_warnings += scraped.warnings.len();
+                    let fingerprint = scraped.fingerprint.as_ref();
+                    if check_layout(
+                        app_state,
+                        &household.postcode,
+                        &household.address,
+                        fingerprint,
+                    )
+                    .await
+                    {
+                        report.layout_changes += 1;
+                    }
+                }
+                Err(e) => {
+                    // A wrong address is for the household to fix, and doesn't mean the site's
+                    // broken
+                    if is_address_problem(&e) {
+                        report.address_problems += 1;
+                    } else {
+                        report.failed += 1;
+                    }
+                    report_error(app_state, incidents::SCRAPE, e).await;
+                    // Every other household would fail the same way
+                    if scrape_pool.size() == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    scrape_pool.close().await;
+    info!("{}", report);
+    // Only a run where every household worked counts as recovered, so one bad address doesn't
+    // open and close an incident every day
+    if report.scraped > 0 && report.failed == 0 {
+        report_success(app_state, incidents::SCRAPE).await;
+    }
+    if report.scraped > 0 && report.layout_changes == 0 {
+        report_success(app_state, incidents::LAYOUT).await;
+    }
+}
+
+/// Totals for a scheduled scrape, logged once it's finished. Each date warning has already been
+/// logged against its household
+#[derive(Default)]
+struct RunReport {
+    scraped: usize,
+    failed: usize,
+    address_problems: usize,
+    date_warnings: usize,
+    /// Households whose dates page didn't look like it used to
+    layout_changes: usize,
+}
+
+impl std::fmt::Display for RunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(
+            f,
+            "Scrape run finished: {} scraped, {} failed, {} with address problems, {} dates skipped, {} layout changes",
+            self.scraped, self.failed, self.address_problems, self.date_warnings, self.layout_changes
+        );
+    }
+}
+
+/// Households with at least one user or webhook, as there's no point scraping the rest
+async fn households_to_scrape(app_state: &AppState) -> Result<Vec<Household>, anyhow::Error> {
+    let users = get_all_users(&app_state.pool).await?;
+    let webhooks = get_all_webhooks(&app_state.pool).await?;
+    let households = get_all_households(&app_state.pool).await?;
+    return Ok(households
+        .into_iter()
+        .filter(|h| {
+            users.iter().any(|u| u.household_id == h.id)
+                || webhooks.iter().any(|w| w.household_id == h.id)
+        })
+        .collect());
+}
+
+async fn send_due_reminders(app_state: &AppState) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    let mut sent = false;
+    let mut failed = false;
+    let users = get_all_users(&app_state.pool).await?;
+    for user in &users {
+        let bins = get_stored_bin_dates(&app_state.pool, user.household_id).await?;
+        match due_reminder(
+            &bins,
+            user.reminder_offset_minutes,
+            user.last_reminded_for,
+            now,
+        ) {
+            Reminder::NotDue => {}
+            Reminder::Missed(date) => {
+                info!("Missed the reminder for {} on {}", user.email, date);
+                set_user_reminded_for(&app_state.pool, user, date).await?;
+            }
+            Reminder::Due(next_bin_collection) => {
+                let date = next_bin_collection.bins[0].date;
+                if is_acknowledged(&app_state.pool, user.household_id, date).await? {
+                    info!("Bins already out for {}, not reminding", user.email);
+                    set_user_reminded_for(&app_state.pool, user, date).await?;
+                    continue;
+                }
+                let on_duty = on_duty(&app_state.pool, user.household_id, date)
+                    .await?
+                    .and_then(|id| users.iter().find(|u| u._id == id));
+                let text = reminder_text(
+                    app_state,
+                    user.household_id,
+                    &next_bin_collection,
+                    on_duty.map(|u| u.display_name()),
+                    Some(user),
+                    user.reminder_offset_minutes,
+                )?;
+                if let Some(on_duty) = on_duty {
+                    // Everyone else only hears about it if it's not been done a while later
+                    let escalate_at = reminder_due_at(date, user.reminder_offset_minutes)
+                        + chrono::Duration::minutes(ROTA_ESCALATION_DELAY_MINUTES);
+                    if on_duty._id != user._id && now < escalate_at {
+                        continue;
+                    }
+                }
+
+                info!("Reminder due for {}", user.email);
+                // Marked as reminded even if sending fails, so a broken channel doesn't
+                // get retried (and alerted about) every minute
+                set_user_reminded_for(&app_state.pool, user, date).await?;
+                match send_reminders_to_user(app_state, user, &next_bin_collection, &text).await {
+                    Ok(()) => sent = true,
+                    Err(e) => {
+                        failed = true;
+                        report_error(app_state, incidents::REMINDERS, e).await;
+                    }
+                }
+            }
+        }
+    }
+
+    for webhook in get_all_webhooks(&app_state.pool).await? {
+        let bins = get_stored_bin_dates(&app_state.pool, webhook.household_id).await?;
+        match due_reminder(
+            &bins,
+            DEFAULT_REMINDER_OFFSET_MINUTES,
+            webhook.last_reminded_for,
+            now,
+        ) {
+            Reminder::NotDue => {}
+            Reminder::Missed(date) => {
+                set_webhook_reminded_for(&app_state.pool, &webhook, date).await?;
+            }
+            Reminder::Due(next_bin_collection) => {
+                let date = next_bin_collection.bins[0].date;
+                let on_duty = on_duty(&app_state.pool, webhook.household_id, date)
+                    .await?
+                    .and_then(|id| users.iter().find(|u| u._id == id));
+                if on_duty.is_some() {
+                    // A shared channel is everyone, so it's treated like everyone else in a rota
+                    if is_acknowledged(&app_state.pool, webhook.household_id, date).await? {
+                        set_webhook_reminded_for(&app_state.pool, &webhook, date).await?;
+                        continue;
+                    }
+                    let escalate_at = reminder_due_at(date, DEFAULT_REMINDER_OFFSET_MINUTES)
+                        + chrono::Duration::minutes(ROTA_ESCALATION_DELAY_MINUTES);
+                    if now < escalate_at {
+                        continue;
+                    }
+                }
+                set_webhook_reminded_for(&app_state.pool, &webhook, date).await?;
+                let text = reminder_text(
+                    app_state,
+                    webhook.household_id,
+                    &next_bin_collection,
+                    on_duty.map(|u| u.display_name()),
+                    None,
+                    DEFAULT_REMINDER_OFFSET_MINUTES,
+                )?;
+                match post_to_webhook(
+                    &webhook,
+                    &next_bin_collection,
+                    &text,
+                    &app_state.http_client,
+                )
+                .await
+                {
+                    Ok(()) => sent = true,
+                    Err(e) => {
+                        failed = true;
+                        report_error(app_state, incidents::REMINDERS, e).await;
+                    }
+                }
+            }
+        }
+    }
+    if sent && !failed {
+        report_success(app_state, incidents::REMINDERS).await;
+    }
+    return Ok(());
+}
+
+/// Sends a second reminder to the whole household (and their escalation contact) if nobody
+/// has said the bins are out by the household's escalation time
+async fn send_due_escalations(app_state: &AppState) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    let users = get_all_users(&app_state.pool).await?;
+    let webhooks = get_all_webhooks(&app_state.pool).await?;
+    for household in get_all_households(&app_state.pool).await? {
+        let offset_minutes = match household.escalation_offset_minutes {
+            Some(offset_minutes) => offset_minutes,
+            None => continue,
+        };
+        let bins = get_stored_bin_dates(&app_state.pool, household.id).await?;
+        let next_bin_collection =
+            match due_reminder(&bins, offset_minutes, household.last_escalated_for, now) {
+                Reminder::NotDue => continue,
+                Reminder::Missed(date) => {
+                    set_household_escalated_for(&app_state.pool, household.id, date).await?;
+                    continue;
+                }
+                Reminder::Due(next_bin_collection) => next_bin_collection,
+            };
+        let date = next_bin_collection.bins[0].date;
+        set_household_escalated_for(&app_state.pool, household.id, date).await?;
+        if is_acknowledged(&app_state.pool, household.id, date).await? {
+            continue;
+        }
+
+        info!(
+            "Nobody has put the bins out at {}, reminding everyone again",
+            household.name
+        );
+        match escalate(
+            app_state,
+            &household,
+            &next_bin_collection,
+            offset_minutes,
+            &users,
+            &webhooks,
+        )
+        .await
+        {
+            Ok(()) => report_success(app_state, incidents::ESCALATIONS).await,
+            Err(e) => report_error(app_state, incidents::ESCALATIONS, e).await,
+        }
+    }
+    return Ok(());
+}
+
+/// `offset_minutes` is the household's escalation time
+async fn escalate(
+    app_state: &AppState,
+    household: &Household,
+    next_bin_collection: &NextBinCollection,
+    offset_minutes: i64,
+    users: &[User],
+    webhooks: &[Webhook],
+) -> Result<(), anyhow::Error> {
+    let date = next_bin_collection.bins[0].date;
+    let on_duty = on_duty(&app_state.pool, household.id, date)
+        .await?
+        .and_then(|id| users.iter().find(|u| u._id == id))
+        .map(|u| u.display_name());
+
+    for user in users.iter().filter(|u| u.household_id == household.id) {
+        let mut text = reminder_text(
+            app_state,
+            household.id,
+            next_bin_collection,
+            on_duty,
+            Some(user),
+            offset_minutes,
+        )?;
+        text.subject = still_not_out(&text.subject, text.locale);
+        send_reminders_to_user(app_state, user, next_bin_collection, &text).await?;
+    }
+    for webhook in webhooks.iter().filter(|w| w.household_id == household.id) {
+        let mut text = reminder_text(
+            app_state,
+            household.id,
+            next_bin_collection,
+            on_duty,
+            None,
+            offset_minutes,
+        )?;
+        text.subject = still_not_out(&text.subject, text.locale);
+        post_to_webhook(webhook, next_bin_collection, &text, &app_state.http_client).await?;
+    }
+    if let Some(escalation_email) = &household.escalation_email {
+        let mut text = reminder_text(
+            app_state,
+            household.id,
+            next_bin_collection,
+            on_duty,
+            None,
+            offset_minutes,
+        )?;
+        // They won't know which house it is otherwise
+        text.subject = format!(
+            "{} ({})",
+            still_not_out(&text.subject, text.locale),
+            household.address
+        );
+        email_reminder_to(
+            escalation_email,
+            next_bin_collection,
+            &text,
+            &app_state.aws_client,
+            &app_state.from_email_address,
+        )
+        .await?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use bin_stuff::Bin;
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        return NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        return NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc();
+    }
+
+    #[test]
+    fn reminder_times_follow_uk_daylight_saving() {
+        // GMT in winter
+        assert_eq!(
+            reminder_due_at(date("2024-01-08"), DEFAULT_REMINDER_OFFSET_MINUTES),
+            utc("2024-01-07 18:00")
+        );
+        // BST in summer, so 6pm local is 5pm UTC
+        assert_eq!(
+            reminder_due_at(date("2024-07-01"), DEFAULT_REMINDER_OFFSET_MINUTES),
+            utc("2024-06-30 17:00")
+        );
+        // Morning of the day the clocks go forward
+        assert_eq!(
+            reminder_due_at(date("2024-03-31"), 6 * 60 + 30),
+            utc("2024-03-31 05:30")
+        );
+    }
+
+    #[test]
+    fn next_daily_run_is_in_local_time() {
+        let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        assert_eq!(
+            next_daily_run(utc("2024-07-01 10:00"), noon),
+            utc("2024-07-01 11:00")
+        );
+        assert_eq!(
+            next_daily_run(utc("2024-07-01 11:00"), noon),
+            utc("2024-07-02 11:00")
+        );
+    }
+
+    #[test]
+    fn reminders_are_due_once_per_collection() {
+        let bins = [BinDates {
+            bin: Bin::Blue,
+            dates: vec![date("2024-01-08"), date("2024-01-15")],
+        }];
+
+        let before = due_reminder(&bins, -360, None, utc("2024-01-07 17:59"));
+        assert!(matches!(before, Reminder::NotDue));
+
+        let due = due_reminder(&bins, -360, None, utc("2024-01-07 18:00"));
+        match due {
+            Reminder::Due(next) => assert_eq!(next.bins[0].date, date("2024-01-08")),
+            _ => panic!("Expected a reminder to be due, got {:?}", due),
+        }
+
+        let already_sent = due_reminder(
+            &bins,
+            -360,
+            Some(date("2024-01-08")),
+            utc("2024-01-07 18:01"),
+        );
+        assert!(matches!(already_sent, Reminder::NotDue));
+
+        let missed = due_reminder(&bins, -360, None, utc("2024-01-08 07:00"));
+        assert!(matches!(missed, Reminder::Missed(d) if d == date("2024-01-08")));
+    }
+}
@@ -0,0 +1,136 @@
+//! The flow the scraper follows on the council site, loaded from SCRAPE_FLOW_FILE if it's set.
+//! After editing the file it can be reloaded from the admin page, without a restart.
+
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Error;
+use axum::extract::State;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use log::{info, warn};
+use scraper::{ScrapeFlow, StepTimeouts};
+
+use crate::{escape_html, AppState};
+
+pub const SCRAPE_FLOW_ROUTE: &str = "/scrape_flow";
+pub const RELOAD_SCRAPE_FLOW_ROUTE: &str = "/scrape_flow/reload";
+
+#[derive(Clone)]
+pub struct ScrapeFlowSource {
+    /// `None` for the built in flow
+    path: Option<PathBuf>,
+    current: Arc<RwLock<Arc<ScrapeFlow>>>,
+}
+
+impl ScrapeFlowSource {
+    pub fn load(path: Option<PathBuf>, timeouts: &StepTimeouts) -> Result<ScrapeFlowSource, Error> {
+        let flow = match &path {
+            Some(path) => ScrapeFlow::load(path)?,
+            None => ScrapeFlow::default(),
+        };
+        warn_about_timeouts(&flow, timeouts);
+        return Ok(ScrapeFlowSource {
+            path,
+            current: Arc::new(RwLock::new(Arc::new(flow))),
+        });
+    }
+
+    /// The flow for a run to use from start to finish
+    pub fn current(&self) -> Arc<ScrapeFlow> {
+        return self.current.read().unwrap().clone();
+    }
+
+    /// Swaps in the file's flow if it's valid, otherwise keeps the one already loaded
+    pub fn reload(&self, timeouts: &StepTimeouts) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let flow = ScrapeFlow::load(path)?;
+        warn_about_timeouts(&flow, timeouts);
+        *self.current.write().unwrap() = Arc::new(flow);
+        info!("Reloaded the scrape flow from {}", path.display());
+        return Ok(());
+    }
+}
+
+/// A timeout for a step the flow doesn't have is probably a step that's been renamed
+fn warn_about_timeouts(flow: &ScrapeFlow, timeouts: &StepTimeouts) {
+    let steps = flow.step_names();
+    for step in timeouts.per_step.keys() {
+        if !steps.contains(&step.as_str()) {
+            warn!(
+                "SCRAPE_STEP_TIMEOUTS has a timeout for \"{}\", which isn't a step in the flow",
+                step
+            );
+        }
+    }
+}
+
+pub(crate) async fn scrape_flow_page(State(app_state): State<AppState>) -> Html<String> {
+    let source = &app_state.scrape_flow;
+    let flow = source.current();
+
+    let mut html = "<h1>Scrape flow</h1>".to_string();
+    match &source.path {
+        Some(path) => html.push_str(&format!(
+            "<p>Loaded from {}. Runs already going carry on with the flow they started with.</p>
+            <form method='post' action='{}'><input type='submit' value='Reload'></form>",
+            path.display(),
+            RELOAD_SCRAPE_FLOW_ROUTE
+        )),
+        None => {
+            html.push_str("<p>Built in. Set SCRAPE_FLOW_FILE to change it without a new build.</p>")
+        }
+    }
+    html.push_str(&format!(
+        "<p>Starts at <a href='{}'>{}</a></p>",
+        escape_html(&flow.url),
+        escape_html(&flow.url)
+    ));
+
+    html.push_str("<ol>");
+    for action in &flow.actions {
+        html.push_str(&format!(
+            "<li>{} - {:?} the {} ({}) once it's {:?}</li>",
+            escape_html(&action.step),
+            action.action,
+            escape_html(&action.element),
+            escape_html(&action.selector),
+            action.ready
+        ));
+    }
+    html.push_str(&format!(
+        "<li>{} - read each bin's dates ({})<ul>",
+        escape_html(&flow.dates.step),
+        escape_html(&flow.dates.selector)
+    ));
+    for section in &flow.bins {
+        html.push_str(&format!(
+            "<li>{} - {}</li>",
+            section.bin,
+            escape_html(&section.selector)
+        ));
+    }
+    html.push_str("</ul></li></ol>");
+    match &flow.layout {
+        Some(layout) => html.push_str(&format!(
+            "<p>The dates page is fingerprinted by its sections ({}), named by their {} class</p>",
+            escape_html(&layout.sections),
+            escape_html(&layout.section_class_prefix)
+        )),
+        None => html.push_str("<p>The dates page isn't fingerprinted, as there's no layout.</p>"),
+    }
+    return Html(html);
+}
+
+pub(crate) async fn submit_reload_scrape_flow(State(app_state): State<AppState>) -> Response {
+    return match app_state.scrape_flow.reload(&app_state.step_timeouts) {
+        Ok(()) => Redirect::to(SCRAPE_FLOW_ROUTE).into_response(),
+        Err(e) => Html(format!(
+            "<h1>Could not reload the scrape flow</h1><p>{}</p><p>The flow already loaded is still being used.</p><a href='{}'>Back</a>",
+            escape_html(&e.to_string()),
+            SCRAPE_FLOW_ROUTE
+        ))
+        .into_response(),
+    };
+}
@@ -0,0 +1,195 @@
+use anyhow::{anyhow, Error};
+use chrono::{Datelike, TimeZone};
+use serde::Deserialize;
+use sqlx::{Row, SqlitePool};
+
+use bin_stuff::locale::long_date;
+use bin_stuff::{NextBinCollection, User};
+use log::{info, warn};
+
+use crate::email_sender::ReminderText;
+
+/// Settings for a Twilio-compatible messages API.
+/// `api_url` can point at a local mock instead of https://api.twilio.com
+#[derive(Clone, Debug)]
+pub struct SmsConfig {
+    pub api_url: String,
+    pub account_sid: String,
+    pub auth_token: String,
+    pub from_number: String,
+    pub monthly_cap: u32,
+    /// Used when the API doesn't tell us the price of a message
+    pub default_cost_per_message: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct SendMessageResponse {
+    sid: String,
+    price: Option<String>,
+}
+
+/// Checks the number is in E.164 format, i.e +447700900123
+pub fn validate_phone_number(phone_number: &str) -> Result<(), Error> {
+    let digits = match phone_number.strip_prefix('+') {
+        Some(digits) => digits,
+        None => return Err(anyhow!("Phone number {} must start with +", phone_number)),
+    };
+    if digits.is_empty() || digits.len() > 15 {
+        return Err(anyhow!(
+            "Phone number {} must have between 1 and 15 digits",
+            phone_number
+        ));
+    }
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(anyhow!("Phone number {} must only be digits", phone_number));
+    }
+    if digits.starts_with('0') {
+        return Err(anyhow!(
+            "Phone number {} must start with a country code",
+            phone_number
+        ));
+    }
+    return Ok(());
+}
+
+pub async fn text_user(
+    user: &User,
+    next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
+    sms_config: &SmsConfig,
+    http_client: &reqwest::Client,
+    pool: &SqlitePool,
+) -> Result<(), Error> {
+    let phone_number = match &user.phone_number {
+        Some(phone_number) => phone_number,
+        None => return Ok(()),
+    };
+
+    let (sent_this_month, cost_this_month) = sms_usage_this_month(pool).await?;
+    if sent_this_month >= sms_config.monthly_cap {
+        warn!(
+            "Monthly SMS cap of {} reached (cost so far {:.2}), not texting {}",
+            sms_config.monthly_cap, cost_this_month, user.email
+        );
+        return Ok(());
+    }
+
+    let url = format!(
+        "{}/2010-04-01/Accounts/{}/Messages.json",
+        sms_config.api_url, sms_config.account_sid
+    );
+    let body = build_sms_body(next_bin_collection, text);
+    let response: SendMessageResponse = http_client
+        .post(url)
+        .basic_auth(&sms_config.account_sid, Some(&sms_config.auth_token))
+        .form(&[
+            ("To", phone_number.as_str()),
+            ("From", sms_config.from_number.as_str()),
+            ("Body", body.as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    // Twilio reports prices as negative numbers, and often hasn't priced the message yet
+    let cost = response
+        .price
+        .and_then(|price| price.parse::<f64>().ok())
+        .map(f64::abs)
+        .unwrap_or(sms_config.default_cost_per_message);
+
+    sqlx::query(
+        "INSERT INTO sms_messages (user_id, phone_number, provider_id, cost, sent_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )
+    .bind(user._id)
+    .bind(phone_number)
+    .bind(&response.sid)
+    .bind(cost)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    info!("SMS sent to {}", user.email);
+    return Ok(());
+}
+
+/// Number of messages sent and their total cost since the start of the current month (UTC)
+pub async fn sms_usage_this_month(pool: &SqlitePool) -> Result<(u32, f64), Error> {
+    let now = chrono::Utc::now();
+    let start_of_month = chrono::Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .unwrap();
+    let row = sqlx::query(
+        "SELECT COUNT(*) AS sent, COALESCE(SUM(cost), 0.0) AS cost FROM sms_messages WHERE sent_at >= ?1",
+    )
+    .bind(start_of_month.to_rfc3339())
+    .fetch_one(pool)
+    .await?;
+
+    let sent: i64 = row.get("sent");
+    return Ok((sent as u32, row.get("cost")));
+}
+
+/// Short version of the email, to keep to a single SMS segment
+fn build_sms_body(next_bin_collection: &NextBinCollection, text: &ReminderText) -> String {
+    let mut body = text.subject.clone();
+    if let Some(bin_day) = next_bin_collection.bins.first() {
+        body.push_str(&format!(" - {}", long_date(bin_day.date, text.locale)));
+    }
+    if let Some(done_link) = &text.done_link {
+        body.push_str(&format!("\n{}", done_link));
+    }
+    return body;
+}
+
+#[cfg(test)]
+mod tests {
+    use bin_stuff::locale::Locale;
+    use bin_stuff::{Bin, NextBinCollectionDay};
+
+    use crate::email_sender::bins_subject;
+
+    use super::*;
+
+    #[test]
+    fn phone_numbers_must_be_e164() {
+        assert!(validate_phone_number("+447700900123").is_ok());
+        assert!(validate_phone_number("+1").is_ok());
+
+        assert!(validate_phone_number("07700900123").is_err());
+        assert!(validate_phone_number("+").is_err());
+        assert!(validate_phone_number("+0447700900123").is_err());
+        assert!(validate_phone_number("+44 7700 900123").is_err());
+        assert!(validate_phone_number("+1234567890123456").is_err());
+    }
+
+    #[test]
+    fn sms_body_is_short() {
+        let date = chrono::NaiveDate::parse_from_str("2023-07-31", "%Y-%m-%d").unwrap();
+        let next_bin_collection = NextBinCollection {
+            bins: vec![
+                NextBinCollectionDay {
+                    bin: Bin::Blue,
+                    date,
+                    projected: None,
+                },
+                NextBinCollectionDay {
+                    bin: Bin::Brown,
+                    date,
+                    projected: None,
+                },
+            ],
+        };
+
+        let text = ReminderText {
+            subject: bins_subject(&next_bin_collection, true, Locale::En),
+            done_link: None,
+            locale: Locale::En,
+        };
+        let body = build_sms_body(&next_bin_collection, &text);
+        assert_eq!(body, "Blue, Brown bins out tonight - Monday 31 July");
+        assert!(body.len() <= 160);
+    }
+}
@@ -0,0 +1,274 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use serde_json::json;
+
+use bin_stuff::locale::{self, long_date, Locale};
+use bin_stuff::schedule_changes::ScheduleChange;
+use bin_stuff::{Bin, NextBinCollection, NextBinCollectionDay};
+use log::info;
+
+use crate::email_sender::{schedule_changes_subject, ReminderText};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebhookKind {
+    Discord,
+    Slack,
+}
+
+impl WebhookKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookKind::Discord => "discord",
+            WebhookKind::Slack => "slack",
+        }
+    }
+}
+
+impl FromStr for WebhookKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "discord" => Ok(WebhookKind::Discord),
+            "slack" => Ok(WebhookKind::Slack),
+            _ => Err(anyhow!("Unknown webhook kind {}", s)),
+        }
+    }
+}
+
+/// A channel (Discord or Slack) that gets one post per collection for a whole household,
+/// rather than one email per person
+#[derive(Debug)]
+pub struct Webhook {
+    pub _id: i64,
+    pub kind: WebhookKind,
+    pub url: String,
+    pub household_id: i64,
+    pub last_reminded_for: Option<chrono::NaiveDate>,
+}
+
+pub async fn post_to_webhook(
+    webhook: &Webhook,
+    next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
+    http_client: &reqwest::Client,
+) -> Result<(), Error> {
+    let payload = match webhook.kind {
+        WebhookKind::Discord => discord_payload(next_bin_collection, text),
+        WebhookKind::Slack => slack_payload(next_bin_collection, text),
+    };
+    return post_payload(webhook, &payload, http_client).await;
+}
+
+pub async fn post_schedule_changes(
+    webhook: &Webhook,
+    changes: &[ScheduleChange],
+    http_client: &reqwest::Client,
+) -> Result<(), Error> {
+    // Shared channels get English, like reminders
+    let locale = Locale::default();
+    let subject = schedule_changes_subject(changes, locale);
+    let lines: Vec<String> = changes
+        .iter()
+        .map(|change| locale::schedule_change(change, locale))
+        .collect();
+    let payload = match webhook.kind {
+        WebhookKind::Discord => json!({
+            "content": subject,
+            "embeds": [{ "description": lines.join("\n") }],
+        }),
+        WebhookKind::Slack => json!({
+            "text": format!("*{}*\n{}", subject, lines.join("\n")),
+        }),
+    };
+    return post_payload(webhook, &payload, http_client).await;
+}
+
+/// Webhook URLs are typed in by an admin and posted to from the server, so only HTTPS ones are
+/// accepted
+pub fn check_webhook_url(url: &str) -> Result<(), Error> {
+    let parsed = reqwest::Url::parse(url)?;
+    if parsed.scheme() != "https" || parsed.host_str().is_none() {
+        return Err(anyhow!("{} is not an https:// URL", url));
+    }
+    return Ok(());
+}
+
+async fn post_payload(
+    webhook: &Webhook,
+    payload: &serde_json::Value,
+    http_client: &reqwest::Client,
+) -> Result<(), Error> {
+    // For any added before URLs were checked
+    check_webhook_url(&webhook.url)?;
+    http_client
+        .post(&webhook.url)
+        .json(payload)
+        .send()
+        .await?
+        .error_for_status()?;
+    info!("Posted to {} webhook", webhook.kind.as_str());
+    return Ok(());
+}
+
+/// Colour used for the Discord embed of each bin
+fn bin_colour(bin: Bin) -> u32 {
+    match bin {
+        Bin::Black => 0x23272a,
+        Bin::Blue => 0x1f6fd1,
+        Bin::Brown => 0x8b5a2b,
+        Bin::Green => 0x2e8b57,
+    }
+}
+
+fn bin_emoji(bin: Bin) -> &'static str {
+    match bin {
+        Bin::Black => ":black_circle:",
+        Bin::Blue => ":large_blue_circle:",
+        Bin::Brown => ":large_brown_circle:",
+        Bin::Green => ":large_green_circle:",
+    }
+}
+
+/// The long date, marked if it's only projected from the bin's usual cadence
+fn collection_date(bin_day: &NextBinCollectionDay, locale: Locale) -> String {
+    let date = long_date(bin_day.date, locale);
+    return match bin_day.projected {
+        Some(confidence) => format!("{} {}", date, locale::projected(confidence, locale)),
+        None => date,
+    };
+}
+
+/// One embed per bin so each one gets its own colour stripe
+fn discord_payload(
+    next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
+) -> serde_json::Value {
+    let mut embeds: Vec<serde_json::Value> = next_bin_collection
+        .bins
+        .iter()
+        .map(|bin_day| {
+            json!({
+                "title": format!("{} bin", bin_day.bin),
+                "description": format!(
+                    "Being collected on {}",
+                    collection_date(bin_day, text.locale)
+                ),
+                "color": bin_colour(bin_day.bin),
+            })
+        })
+        .collect();
+    if let Some(done_link) = &text.done_link {
+        embeds.push(json!({
+            "title": "Put them out? Let everyone know",
+            "url": done_link,
+        }));
+    }
+
+    return json!({
+        "content": text.subject,
+        "embeds": embeds,
+    });
+}
+
+fn slack_payload(
+    next_bin_collection: &NextBinCollection,
+    text: &ReminderText,
+) -> serde_json::Value {
+    let mut blocks = vec![json!({
+        "type": "header",
+        "text": { "type": "plain_text", "text": text.subject },
+    })];
+    for bin_day in &next_bin_collection.bins {
+        blocks.push(json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!(
+                    "{} *{} bin* is being collected on {}",
+                    bin_emoji(bin_day.bin),
+                    bin_day.bin,
+                    collection_date(bin_day, text.locale)
+                ),
+            },
+        }));
+    }
+
+    if let Some(done_link) = &text.done_link {
+        blocks.push(json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!("<{}|Put them out? Let everyone know>", done_link),
+            },
+        }));
+    }
+
+    return json!({
+        // Fallback for notifications that can't show blocks
+        "text": text.subject,
+        "blocks": blocks,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bin_stuff::locale::Locale;
+    use bin_stuff::NextBinCollectionDay;
+
+    use crate::email_sender::{bins_subject, reminder_subject};
+
+    use super::*;
+
+    #[test]
+    fn payloads_include_every_bin() {
+        let date = chrono::NaiveDate::parse_from_str("2023-07-31", "%Y-%m-%d").unwrap();
+        let next_bin_collection = NextBinCollection {
+            bins: vec![
+                NextBinCollectionDay {
+                    bin: Bin::Blue,
+                    date,
+                    projected: None,
+                },
+                NextBinCollectionDay {
+                    bin: Bin::Brown,
+                    date,
+                    projected: None,
+                },
+            ],
+        };
+
+        let text = ReminderText {
+            subject: bins_subject(&next_bin_collection, true, Locale::En),
+            done_link: None,
+            locale: Locale::En,
+        };
+        let discord = discord_payload(&next_bin_collection, &text);
+        assert_eq!(discord["content"], "Blue, Brown bins out tonight");
+        assert_eq!(discord["embeds"].as_array().unwrap().len(), 2);
+        assert_eq!(discord["embeds"][0]["color"], 0x1f6fd1);
+        assert_eq!(
+            discord["embeds"][1]["description"],
+            "Being collected on Monday 31 July"
+        );
+
+        let text = ReminderText {
+            subject: reminder_subject(&next_bin_collection, Some("Alex"), true, Locale::En),
+            done_link: Some("https://bins.example.com/done".to_string()),
+            locale: Locale::En,
+        };
+        let slack = slack_payload(&next_bin_collection, &text);
+        assert_eq!(slack["text"], "Alex's turn: Blue, Brown bins out tonight");
+        // Header, one section per bin, then the done link
+        assert_eq!(slack["blocks"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn only_https_webhook_urls_are_accepted() {
+        assert!(check_webhook_url("https://discord.com/api/webhooks/1/abc").is_ok());
+        assert!(check_webhook_url("http://discord.com/api/webhooks/1/abc").is_err());
+        assert!(check_webhook_url("file:///etc/passwd").is_err());
+        assert!(check_webhook_url("discord.com/api/webhooks").is_err());
+    }
+}